@@ -24,6 +24,10 @@ pub(crate) struct AgentLaunchConfig {
     pub(crate) bin_path: PathBuf,
     pub(crate) keep_output: bool,
     pub(crate) keep_rootfs: bool,
+    /// `--redact-env` patterns the agent applies to the env it reports.
+    pub(crate) redact_env: Vec<String>,
+    /// Make the agent fail instead of dropping secret env vars.
+    pub(crate) strict_secrets: bool,
     cli_override: Option<Vec<OsString>>,
 }
 
@@ -33,6 +37,8 @@ impl AgentLaunchConfig {
         cli_override: Option<String>,
         keep_output: bool,
         keep_rootfs: bool,
+        redact_env: Vec<String>,
+        strict_secrets: bool,
     ) -> Result<Self> {
         let mut raw_path = if let Some(path) = bin_override {
             path
@@ -58,6 +64,8 @@ impl AgentLaunchConfig {
             bin_path,
             keep_output,
             keep_rootfs,
+            redact_env,
+            strict_secrets,
             cli_override,
         })
     }
@@ -84,23 +92,22 @@ pub(crate) struct AgentTraceRunner {
     agent_bin: PathBuf,
     keep_output: bool,
     keep_rootfs: bool,
+    redact_env: Vec<String>,
+    strict_secrets: bool,
 }
 
 impl AgentTraceRunner {
-    pub(crate) fn new(
-        cmd: Vec<OsString>,
-        agent_bin: PathBuf,
-        keep_output: bool,
-        keep_rootfs: bool,
-    ) -> Result<Self> {
+    pub(crate) fn new(cmd: Vec<OsString>, launch: &AgentLaunchConfig) -> Result<Self> {
         if cmd.is_empty() {
             bail!("agent runtime command cannot be empty");
         }
         Ok(Self {
             runtime_cmd: cmd,
-            agent_bin,
-            keep_output,
-            keep_rootfs,
+            agent_bin: launch.bin_path.clone(),
+            keep_output: launch.keep_output,
+            keep_rootfs: launch.keep_rootfs,
+            redact_env: launch.redact_env.clone(),
+            strict_secrets: launch.strict_secrets,
         })
     }
 
@@ -160,6 +167,12 @@ impl AgentTraceRunner {
                 }
                 TraceBackendArg::Off => "off",
             });
+        for pattern in &self.redact_env {
+            create_cmd.arg(format!("--redact-env={pattern}"));
+        }
+        if self.strict_secrets {
+            create_cmd.arg("--strict-secrets");
+        }
         let create_out = create_cmd
             .output()
             .context("failed to create agent container")?;
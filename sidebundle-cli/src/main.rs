@@ -1,4 +1,4 @@
-use std::collections::{BTreeMap, HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::env;
 use std::ffi::{CStr, OsString};
 use std::fmt::Write as _;
@@ -15,6 +15,7 @@ use log::{debug, info, warn, LevelFilter};
 use pathdiff::diff_paths;
 use sha2::{Digest, Sha256};
 use sidebundle_closure::{
+    guard::{SecretFileAction, SecretFileGuard, SecretFileReport},
     image::{DockerProvider, ImageRoot, ImageRootProvider, PodmanProvider},
     trace::{
         TraceBackendKind, TraceCollector, TraceCommand as RuntimeTraceCommand, TraceSpec,
@@ -24,8 +25,12 @@ use sidebundle_closure::{
     ChrootPathResolver, ClosureBuilder, PathResolver, ResolverSet,
 };
 use sidebundle_core::{
-    AuxvEntry, BundleEntry, BundleSpec, DependencyClosure, LogicalPath, MergeReport, Origin,
-    ResolvedFile, ResolvedSymlink, RunMode, RuntimeMetadata, SystemInfo, TargetTriple,
+    parse_umask, AuxvEntry, AuxvProfile, BindSpec, BundleEntry, BundleSpec, BwrapPolicy,
+    BwrapPreset, ConflictPolicy, ConflictResolution, DependencyClosure, DevicePolicy,
+    EntryBundlePlan, EnvMerge, EnvMode, EnvPolicy, HostExec, IoNice, LandlockPolicy, LogicalPath,
+    MergeConflict, MergePolicy, MergeReport, Origin, ProcessPolicy, ResolvedFile, ResolvedSymlink,
+    Rlimit, RunMode, RuntimeMetadata, SecretRedactor, StateLayer, SystemInfo, TargetTriple,
+    DEFAULT_PASSTHROUGH, SECCOMP_SYSCALLS,
 };
 use sidebundle_packager::Packager;
 
@@ -47,7 +52,7 @@ fn main() {
 
 fn run(cli: Cli) -> Result<()> {
     match cli.command {
-        Commands::Create(args) => execute_create(args),
+        Commands::Create(args) => execute_create(*args),
         Commands::Agent(agent) => execute_agent(agent),
     }
 }
@@ -77,7 +82,31 @@ fn execute_create(args: CreateArgs) -> Result<()> {
         allow_gpu_libs,
         strict_validate,
         set_env,
+        env_policy,
+        env_allow,
+        env_deny,
+        env_rule,
+        redact_env,
+        strict_secrets,
+        secret_files,
+        allow_secret_file,
+        copy_host_accounts,
+        origin_namespaces,
+        merge_conflict,
+        merge_conflict_rule,
         run_mode,
+        run_mode_fallback,
+        portable,
+        state,
+        device,
+        passthrough,
+        no_default_passthrough,
+        bwrap,
+        landlock,
+        process,
+        host_exec,
+        replay_auxv,
+        auxv_profile,
         emit_shim,
     } = args;
 
@@ -97,6 +126,38 @@ fn execute_create(args: CreateArgs) -> Result<()> {
     );
 
     let run_mode_resolved = resolve_run_mode(run_mode);
+    let run_mode_fallback: Vec<RunMode> = run_mode_fallback
+        .into_iter()
+        .map(resolve_run_mode)
+        .collect();
+    // Mode-specific flags are accepted when the launcher may end up in that mode.
+    let may_run = |mode: RunMode| run_mode_resolved == mode || run_mode_fallback.contains(&mode);
+    if (replay_auxv || auxv_profile.is_some()) && !may_run(RunMode::Host) {
+        bail!("auxv replay is only supported with --run-mode host (or a host fallback)");
+    }
+    if (!passthrough.is_empty() || no_default_passthrough) && !may_run(RunMode::Overlay) {
+        bail!(
+            "--passthrough and --no-default-passthrough require --run-mode overlay (or an overlay fallback)"
+        );
+    }
+    if bwrap.is_set() && !may_run(RunMode::Bwrap) {
+        bail!("--bwrap-* flags require --run-mode bwrap (or a bwrap fallback)");
+    }
+    if landlock.is_set() && !may_run(RunMode::Host) {
+        bail!("--landlock flags require --run-mode host (or a host fallback)");
+    }
+    let isolated = [
+        RunMode::Bwrap,
+        RunMode::Chroot,
+        RunMode::Sandbox,
+        RunMode::Overlay,
+    ];
+    if state.is_some() && !isolated.into_iter().any(may_run) {
+        bail!("--state requires an isolated --run-mode (bwrap, chroot, sandbox or overlay)");
+    }
+    if !device.is_empty() && !isolated.into_iter().any(may_run) {
+        bail!("--device requires an isolated --run-mode (bwrap, chroot, sandbox or overlay)");
+    }
     let mut spec = BundleSpec::new(name, target);
     spec.set_run_mode(run_mode_resolved);
     for (idx, entry) in from_host.iter().enumerate() {
@@ -128,6 +189,8 @@ fn execute_create(args: CreateArgs) -> Result<()> {
                 image_agent_cli,
                 image_agent_keep_output,
                 image_agent_keep_rootfs,
+                redact_env.clone(),
+                strict_secrets,
             )
             .context("failed to configure image agent settings")?,
         )
@@ -162,11 +225,19 @@ fn execute_create(args: CreateArgs) -> Result<()> {
         .build(&spec)
         .context("failed to build dependency closure")?;
     log_closure_stats("host inputs", &closure);
+    if origin_namespaces {
+        closure.rebase_payload(&Origin::Host.payload_root());
+    }
     let mut resolver_entries: Vec<(Origin, Arc<dyn PathResolver>)> = Vec::new();
     if let Some(resolver) = host_resolvers.get(&Origin::Host) {
         resolver_entries.push((Origin::Host, resolver));
     }
 
+    let merge_policy = merge_conflict_rule.into_iter().fold(
+        MergePolicy::new(merge_conflict),
+        |policy, (pattern, rule)| policy.with_rule(pattern, rule),
+    );
+    let mut merger = ClosureMerger::new(merge_policy);
     let default_backend = BackendPreference::from(image_backend);
     let grouped = group_image_entries(&from_image, default_backend)?;
     for ((preference, reference), entries) in grouped {
@@ -191,29 +262,96 @@ fn execute_create(args: CreateArgs) -> Result<()> {
             format!("failed to build closure for image `{reference}` using backend {preference:?}")
         })?;
         log_closure_stats(&format!("image `{reference}`"), &image_result.closure);
-        let report = closure.merge(image_result.closure);
-        log_merge_report(&reference, &report);
+        let mut image_closure = image_result.closure;
+        if origin_namespaces {
+            image_closure.rebase_payload(&Origin::Image(reference.clone()).payload_root());
+        }
+        let origin = Origin::Image(reference.clone());
+        merger.merge(
+            &mut closure,
+            image_closure,
+            Some(&origin),
+            &format!("image `{reference}`"),
+        );
         resolver_entries.extend(image_result.resolver_entries);
     }
 
-    ensure_system_assets(&mut closure, &resolver_entries)
-        .context("failed to backfill system assets")?;
-    add_copy_dirs(&mut closure, &copy_dir, &resolver_entries)
+    ensure_system_assets(
+        &mut closure,
+        &resolver_entries,
+        origin_namespaces,
+        &mut merger,
+    )
+    .context("failed to backfill system assets")?;
+    let mut copied = DependencyClosure::default();
+    add_copy_dirs(&mut copied, &copy_dir, &resolver_entries)
         .context("failed to apply --copy-dir entries")?;
-    apply_env_overrides(&mut closure, &set_env);
+    if origin_namespaces {
+        // Every payload root gets the copied directories; equal digests share one data file.
+        let roots: BTreeSet<PathBuf> = closure
+            .entry_plans
+            .iter()
+            .map(EntryBundlePlan::payload_root)
+            .collect();
+        for root in roots {
+            let mut rooted = copied.clone();
+            rooted.rebase_payload(&root);
+            merger.merge(&mut closure, rooted, None, "--copy-dir");
+        }
+    } else {
+        merger.merge(&mut closure, copied, None, "--copy-dir");
+    }
+    let merge_conflicts = merger.finish()?;
+    guard_secret_files(&mut closure, secret_files, allow_secret_file)?;
     sanitize_symlinks(&mut closure);
 
     if closure.entry_plans.is_empty() {
         bail!("no executable entries were collected from host or image inputs");
     }
 
-    let packager = if let Some(dir) = out_dir {
-        Packager::new()
-            .with_output_root(dir)
-            .with_shim_output(emit_shim)
-    } else {
-        Packager::new().with_shim_output(emit_shim)
+    let mut packager = Packager::new()
+        .with_shim_output(emit_shim)
+        .with_host_exec(resolve_host_exec(host_exec))
+        .with_secret_patterns(redact_env)
+        .with_strict_secrets(strict_secrets)
+        .with_host_accounts(copy_host_accounts)
+        .with_merge_conflicts(merge_conflicts);
+    if let Some(dir) = out_dir {
+        packager = packager.with_output_root(dir);
+    }
+    for (origin, profile) in resolve_auxv_profiles(&closure, replay_auxv, auxv_profile.as_deref())?
+    {
+        packager = packager.with_auxv_profile(origin, profile);
+    }
+    let env_args = EnvPolicyArgs {
+        mode: env_policy,
+        allow: env_allow,
+        deny: env_deny,
+        rules: env_rule,
+        set: set_env,
     };
+    for origin in entry_origins(&closure) {
+        let policy = resolve_env_policy(&origin, &env_args);
+        packager = packager.with_env_policy(origin, policy);
+    }
+    packager = configure_passthrough(packager, &closure, passthrough, no_default_passthrough)?;
+    packager = packager.with_bwrap_policy(bwrap.into_policy());
+    if let Some(policy) = landlock.into_policy() {
+        packager = packager.with_landlock_policy(policy);
+    }
+    packager = packager.with_process_policy(process.into_policy());
+    packager = packager.with_run_mode_fallback(run_mode_fallback);
+    if let Some(dir) = portable {
+        packager = packager.with_portable_home(dir);
+    }
+    if let Some(dir) = state {
+        packager = packager.with_state_layer(StateLayer { dir });
+    }
+    let mut devices = DevicePolicy::default();
+    for item in &device {
+        devices.push(item).map_err(anyhow::Error::msg)?;
+    }
+    packager = packager.with_devices(devices);
     let output = packager
         .emit(&spec, &closure)
         .context("packaging stage failed")?;
@@ -261,8 +399,9 @@ fn execute_agent_trace(args: AgentTraceArgs) -> Result<()> {
     if trace_spec.commands.is_empty() {
         bail!("agent: trace spec did not contain any commands");
     }
-    let runtime_metadata =
-        capture_runtime_metadata().context("agent: failed to capture runtime metadata")?;
+    let secrets = SecretRedactor::default().with_patterns(args.redact_env.iter().cloned());
+    let runtime_metadata = capture_runtime_metadata(&secrets, args.strict_secrets)
+        .context("agent: failed to capture runtime metadata")?;
 
     let backend =
         resolve_trace_backend(args.trace_backend).context("failed to configure trace backend")?;
@@ -352,7 +491,7 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     /// Create bundle artifacts
-    Create(CreateArgs),
+    Create(Box<CreateArgs>),
     /// Internal helper to run trace/build stages inside a container
     #[command(subcommand, hide = true)]
     Agent(AgentCommands),
@@ -402,7 +541,8 @@ struct CreateArgs {
     #[arg(long = "copy-dir", value_name = "SRC[:DEST]", value_parser = parse_copy_dir, num_args = 0..)]
     copy_dir: Vec<CopyDirArg>,
 
-    /// Allow GPU/DRM libraries (e.g., libdrm, libnvidia) to be included in the bundle
+    /// Allow GPU/DRM libraries (e.g., libdrm, libnvidia) to be included in the bundle instead of
+    /// taking the host's drivers at launch
     #[arg(long = "allow-gpu-libs")]
     allow_gpu_libs: bool,
 
@@ -419,10 +559,152 @@ struct CreateArgs {
     )]
     set_env: Vec<(String, String)>,
 
+    /// Base environment for launchers (default: overlay the build-time env onto the host env)
+    #[arg(long = "env-policy", value_enum)]
+    env_policy: Option<EnvModeArg>,
+
+    /// Only inherit host env vars matching PATTERN (trailing `*` matches a prefix, repeatable)
+    #[arg(long = "env-allow", value_name = "PATTERN", num_args = 0..)]
+    env_allow: Vec<String>,
+
+    /// Drop host env vars matching PATTERN (trailing `*` matches a prefix, repeatable)
+    #[arg(long = "env-deny", value_name = "PATTERN", num_args = 0..)]
+    env_deny: Vec<String>,
+
+    /// How a build-time var combines with the host value (VAR=replace|prepend|append|prefer-host)
+    #[arg(
+        long = "env-rule",
+        value_name = "VAR=MERGE",
+        value_parser = parse_env_rule,
+        num_args = 0..
+    )]
+    env_rule: Vec<(String, EnvMerge)>,
+
+    /// Drop build-time env vars matching PATTERN from launcher configs, on top of the built-in
+    /// secret patterns (`*` wildcard, case-insensitive, repeatable)
+    #[arg(long = "redact-env", value_name = "PATTERN", num_args = 0..)]
+    redact_env: Vec<String>,
+
+    /// Fail the build when a build-time env snapshot contains secrets instead of dropping them
+    #[arg(long = "strict-secrets")]
+    strict_secrets: bool,
+
+    /// What to do with credential files (ssh keys, kubeconfigs, tokens) found among traced or
+    /// copied files
+    #[arg(long = "secret-files", value_enum, default_value_t = SecretFilesArg::Drop)]
+    secret_files: SecretFilesArg,
+
+    /// Bundle path exempt from the secret file guard (`*` wildcard, repeatable)
+    #[arg(long = "allow-secret-file", value_name = "PATTERN", num_args = 0..)]
+    allow_secret_file: Vec<String>,
+
+    /// Bundle this host's /etc/passwd, group, nsswitch.conf and hosts as traced instead of
+    /// writing minimal synthetic ones (exposes the build machine's user list)
+    #[arg(long = "copy-host-accounts")]
+    copy_host_accounts: bool,
+
+    /// Keep each origin's files under its own payload root (`payload/.origins/<origin>`) so
+    /// images whose files conflict can share a bundle; identical files are still stored once
+    #[arg(long = "origin-namespaces")]
+    origin_namespaces: bool,
+
+    /// What to do when images put different files at the same bundle path: error, prefer-first,
+    /// prefer-last, prefer-origin=<host|IMAGE> or prefer-newer-soname-version
+    #[arg(
+        long = "merge-conflict",
+        value_name = "POLICY",
+        default_value = "prefer-first"
+    )]
+    merge_conflict: ConflictPolicy,
+
+    /// Conflict policy for runtime paths matching GLOB (`*` wildcard, e.g.
+    /// `/usr/lib/*=prefer-newer-soname-version`); the first matching rule wins (repeatable)
+    #[arg(
+        long = "merge-conflict-rule",
+        value_name = "GLOB=POLICY",
+        value_parser = parse_conflict_rule,
+        num_args = 0..
+    )]
+    merge_conflict_rule: Vec<(String, ConflictPolicy)>,
+
     /// Runtime execution mode for launchers
     #[arg(long = "run-mode", value_enum, default_value_t = RunModeArg::Host)]
     run_mode: RunModeArg,
 
+    /// Run modes launchers try, in order, when --run-mode is unavailable on the target host
+    /// (e.g. `sandbox,host`); `SIDEBUNDLE_RUN_MODE` overrides the whole list at launch
+    #[arg(
+        long = "run-mode-fallback",
+        value_name = "MODE",
+        value_enum,
+        value_delimiter = ',',
+        num_args = 0..
+    )]
+    run_mode_fallback: Vec<RunModeArg>,
+
+    /// Keep the entries' HOME and XDG directories in DIR, relative to the bundle root (default
+    /// `home`; e.g. `../NAME.home` keeps them beside the bundle so rebuilds do not wipe them)
+    #[arg(
+        long = "portable",
+        value_name = "DIR",
+        num_args = 0..=1,
+        default_missing_value = "home"
+    )]
+    portable: Option<PathBuf>,
+
+    /// Keep writes isolated-mode entries make to the payload in a persistent overlay layer under
+    /// DIR (relative to the bundle root, `~/` for HOME; default
+    /// `$XDG_STATE_HOME/sidebundle/<bundle-name>-<hash>`), leaving the bundle itself untouched
+    #[arg(long = "state", value_name = "DIR", num_args = 0..=1)]
+    state: Option<Option<PathBuf>>,
+
+    /// Host devices isolated-mode launchers bind when present: a profile (dri, nvidia, kvm, fuse,
+    /// input) or an absolute path whose last component may contain `*` (repeatable; `--bwrap-dev`
+    /// is an alias)
+    #[arg(
+        long = "device",
+        alias = "bwrap-dev",
+        value_name = "PROFILE|PATH",
+        value_parser = parse_device,
+        num_args = 0..
+    )]
+    device: Vec<String>,
+
+    /// Host path visible to overlay-mode launchers besides /home, /srv, /mnt and /media
+    /// (`ENTRY=` limits it to one entry, repeatable)
+    #[arg(
+        long = "passthrough",
+        value_name = "[ENTRY=]PATH",
+        value_parser = parse_passthrough,
+        num_args = 0..
+    )]
+    passthrough: Vec<(Option<String>, PathBuf)>,
+
+    /// Do not pass the default host trees through in overlay mode
+    #[arg(long = "no-default-passthrough")]
+    no_default_passthrough: bool,
+
+    #[command(flatten)]
+    bwrap: BwrapArgs,
+
+    #[command(flatten)]
+    landlock: LandlockArgs,
+
+    #[command(flatten)]
+    process: ProcessArgs,
+
+    /// How host-mode launchers start dynamic entries (userland keeps argv0 for multi-call binaries)
+    #[arg(long = "host-exec", value_enum, default_value_t = HostExecArg::Loader)]
+    host_exec: HostExecArg,
+
+    /// Replay the build-time auxv (platform, HWCAP/HWCAP2) when host-mode launchers start entries
+    #[arg(long = "replay-auxv", conflicts_with = "auxv_profile")]
+    replay_auxv: bool,
+
+    /// Replay an explicit auxv profile (JSON with platform/hwcap/hwcap2) in host-mode launchers
+    #[arg(long = "auxv-profile", value_name = "FILE")]
+    auxv_profile: Option<PathBuf>,
+
     /// Runtime trace backend for host inputs
     #[arg(long = "trace-backend", value_enum, default_value_t = TraceBackendArg::Auto)]
     trace_backend: TraceBackendArg,
@@ -452,6 +734,173 @@ struct CreateArgs {
     strict_validate: bool,
 }
 
+/// Sandbox policy for bwrap-mode launchers; launchers also honour `SIDEBUNDLE_BWRAP_*` at runtime.
+#[derive(Args)]
+struct BwrapArgs {
+    /// Starting point for the bwrap policy (strict: no network, only payload/data/devices)
+    #[arg(long = "bwrap-policy", value_enum)]
+    preset: Option<BwrapPresetArg>,
+
+    /// Share the host network with the bwrap sandbox
+    #[arg(long = "bwrap-share-net")]
+    share_net: bool,
+
+    /// Do not bind the caller's working directory (the entry then starts in /)
+    #[arg(long = "bwrap-no-bind-cwd")]
+    no_bind_cwd: bool,
+
+    /// Bind the caller's $HOME read-write
+    #[arg(long = "bwrap-bind-home")]
+    bind_home: bool,
+
+    /// Extra read-only bind into the bwrap sandbox (repeatable)
+    #[arg(long = "bwrap-ro-bind", value_name = "SRC[:DEST]", num_args = 0..)]
+    ro_bind: Vec<BindSpec>,
+
+    /// Extra read-write bind into the bwrap sandbox (repeatable)
+    #[arg(long = "bwrap-bind", value_name = "SRC[:DEST]", num_args = 0..)]
+    bind: Vec<BindSpec>,
+
+    /// Host env var to pass into the sandbox regardless of the env policy (`*` wildcard, repeatable)
+    #[arg(long = "bwrap-env", value_name = "PATTERN", num_args = 0..)]
+    env: Vec<String>,
+}
+
+impl BwrapArgs {
+    fn is_set(&self) -> bool {
+        self.preset.is_some()
+            || self.share_net
+            || self.no_bind_cwd
+            || self.bind_home
+            || !self.ro_bind.is_empty()
+            || !self.bind.is_empty()
+            || !self.env.is_empty()
+    }
+
+    fn into_policy(self) -> BwrapPolicy {
+        let preset = match self.preset.unwrap_or(BwrapPresetArg::Strict) {
+            BwrapPresetArg::Strict => BwrapPreset::Strict,
+            BwrapPresetArg::User => BwrapPreset::User,
+        };
+        let mut policy = BwrapPolicy::preset(preset);
+        policy.share_net |= self.share_net;
+        policy.bind_cwd &= !self.no_bind_cwd;
+        policy.bind_home |= self.bind_home;
+        policy.ro_binds.extend(self.ro_bind);
+        policy.rw_binds.extend(self.bind);
+        policy.env_passthrough.extend(self.env);
+        policy
+    }
+}
+
+/// Landlock ruleset host-mode launchers apply before exec; `SIDEBUNDLE_LANDLOCK=0` skips it.
+#[derive(Args)]
+struct LandlockArgs {
+    /// Confine host-mode entries with Landlock (bundle read/exec, writes to data/, cwd and $TMPDIR)
+    #[arg(long = "landlock")]
+    enabled: bool,
+
+    /// Extra host path the entry may read and execute from (implies --landlock, repeatable)
+    #[arg(long = "landlock-read", value_name = "PATH", num_args = 0..)]
+    read: Vec<PathBuf>,
+
+    /// Extra directory the entry may write to, `~/` is relative to $HOME (implies --landlock, repeatable)
+    #[arg(long = "landlock-write", value_name = "PATH", num_args = 0..)]
+    write: Vec<PathBuf>,
+
+    /// Do not let the entry write to the caller's working directory (implies --landlock)
+    #[arg(long = "landlock-no-write-cwd")]
+    no_write_cwd: bool,
+
+    /// Do not let the entry write to $TMPDIR (implies --landlock)
+    #[arg(long = "landlock-no-write-tmp")]
+    no_write_tmp: bool,
+}
+
+impl LandlockArgs {
+    fn is_set(&self) -> bool {
+        self.enabled
+            || !self.read.is_empty()
+            || !self.write.is_empty()
+            || self.no_write_cwd
+            || self.no_write_tmp
+    }
+
+    fn into_policy(self) -> Option<LandlockPolicy> {
+        if !self.is_set() {
+            return None;
+        }
+        Some(LandlockPolicy {
+            read: self.read,
+            write: self.write,
+            write_cwd: !self.no_write_cwd,
+            write_tmp: !self.no_write_tmp,
+        })
+    }
+}
+
+/// Process constraints every launcher applies right before it execs the entry.
+#[derive(Args)]
+struct ProcessArgs {
+    /// Resource limit for the entry: nofile|as|core=SOFT[:HARD], K/M/G/T suffixes or `unlimited` (repeatable)
+    #[arg(long = "rlimit", value_name = "NAME=SOFT[:HARD]", num_args = 0..)]
+    rlimit: Vec<Rlimit>,
+
+    /// Set no_new_privs so setuid/file capabilities cannot raise the entry's privileges
+    #[arg(long = "no-new-privs")]
+    no_new_privs: bool,
+
+    /// File creation mask for the entry (octal, e.g. 027)
+    #[arg(long = "umask", value_name = "MODE", value_parser = parse_umask)]
+    umask: Option<u32>,
+
+    /// Scheduling niceness for the entry (-20..19)
+    #[arg(
+        long = "nice",
+        value_name = "N",
+        allow_negative_numbers = true,
+        value_parser = clap::value_parser!(i32).range(-20..=19)
+    )]
+    nice: Option<i32>,
+
+    /// I/O priority for the entry: realtime|best-effort|idle[:0-7]
+    #[arg(long = "ionice", value_name = "CLASS[:LEVEL]")]
+    ionice: Option<IoNice>,
+
+    /// Syscall that fails with EPERM in the entry (seccomp, implies --no-new-privs, repeatable)
+    #[arg(
+        long = "deny-syscall",
+        value_name = "NAME",
+        value_parser = parse_deny_syscall,
+        num_args = 0..
+    )]
+    deny_syscall: Vec<String>,
+}
+
+impl ProcessArgs {
+    fn into_policy(self) -> ProcessPolicy {
+        ProcessPolicy {
+            rlimits: self.rlimit,
+            no_new_privs: self.no_new_privs,
+            umask: self.umask,
+            nice: self.nice,
+            ionice: self.ionice,
+            deny_syscalls: self.deny_syscall,
+        }
+    }
+}
+
+fn parse_deny_syscall(value: &str) -> Result<String, String> {
+    if SECCOMP_SYSCALLS.contains(&value) {
+        Ok(value.to_string())
+    } else {
+        Err(format!(
+            "unsupported syscall `{value}` (expected one of: {})",
+            SECCOMP_SYSCALLS.join(", ")
+        ))
+    }
+}
+
 #[derive(Args)]
 struct AgentTraceArgs {
     /// Absolute bundle root inside the container
@@ -469,6 +918,15 @@ struct AgentTraceArgs {
     /// Trace backend to use inside the agent
     #[arg(long = "trace-backend", value_enum, default_value_t = TraceBackendArg::Auto)]
     trace_backend: TraceBackendArg,
+
+    /// Drop env vars matching PATTERN from the reported runtime metadata, on top of the built-in
+    /// secret patterns (repeatable)
+    #[arg(long = "redact-env", value_name = "PATTERN")]
+    redact_env: Vec<String>,
+
+    /// Fail instead of dropping secret env vars from the reported runtime metadata
+    #[arg(long = "strict-secrets")]
+    strict_secrets: bool,
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum)]
@@ -494,6 +952,34 @@ enum RunModeArg {
     Host,
     Bwrap,
     Chroot,
+    Sandbox,
+    Overlay,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum)]
+enum BwrapPresetArg {
+    Strict,
+    User,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum)]
+enum HostExecArg {
+    Loader,
+    Userland,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum)]
+enum EnvModeArg {
+    Inherit,
+    Snapshot,
+    Overlay,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum)]
+enum SecretFilesArg {
+    Drop,
+    Fail,
+    Off,
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum)]
@@ -703,32 +1189,124 @@ fn parse_kv(value: &str) -> Result<(String, String), String> {
     Ok((k.to_string(), v.to_string()))
 }
 
+fn parse_env_rule(value: &str) -> Result<(String, EnvMerge), String> {
+    let (key, merge) = parse_kv(value)?;
+    let merge = match merge.as_str() {
+        "replace" => EnvMerge::Replace,
+        "prepend" => EnvMerge::Prepend,
+        "append" => EnvMerge::Append,
+        "prefer-host" => EnvMerge::PreferHost,
+        other => {
+            return Err(format!(
+                "unknown merge `{other}` (expected replace|prepend|append|prefer-host)"
+            ))
+        }
+    };
+    Ok((key, merge))
+}
+
+fn parse_conflict_rule(value: &str) -> Result<(String, ConflictPolicy), String> {
+    let (pattern, policy) = value
+        .split_once('=')
+        .filter(|(pattern, _)| !pattern.is_empty())
+        .ok_or_else(|| format!("expected GLOB=POLICY, got `{value}`"))?;
+    Ok((pattern.to_string(), policy.parse()?))
+}
+
+/// Split `--passthrough` values into the bundle-wide list and per-entry additions.
+fn configure_passthrough(
+    mut packager: Packager,
+    closure: &DependencyClosure,
+    values: Vec<(Option<String>, PathBuf)>,
+    no_defaults: bool,
+) -> Result<Packager> {
+    let mut global: Vec<PathBuf> = if no_defaults {
+        Vec::new()
+    } else {
+        DEFAULT_PASSTHROUGH.iter().map(PathBuf::from).collect()
+    };
+    let mut customized = no_defaults;
+    for (entry, path) in values {
+        match entry {
+            None => {
+                global.push(path);
+                customized = true;
+            }
+            Some(entry) => {
+                if !closure
+                    .entry_plans
+                    .iter()
+                    .any(|plan| plan.display_name() == entry)
+                {
+                    bail!("--passthrough names unknown entry `{entry}`");
+                }
+                packager = packager.with_entry_passthrough(entry, [path]);
+            }
+        }
+    }
+    if customized {
+        packager = packager.with_passthrough(global);
+    }
+    Ok(packager)
+}
+
+fn parse_device(value: &str) -> Result<String, String> {
+    DevicePolicy::default().push(value)?;
+    Ok(value.to_string())
+}
+
+fn parse_passthrough(value: &str) -> Result<(Option<String>, PathBuf), String> {
+    let (entry, path) = match value.split_once('=') {
+        Some((entry, path)) if !entry.is_empty() && !entry.contains('/') => {
+            (Some(entry.to_string()), path)
+        }
+        _ => (None, value),
+    };
+    let path = PathBuf::from(path);
+    if !path.is_absolute() {
+        return Err(format!(
+            "passthrough path must be absolute: {}",
+            path.display()
+        ));
+    }
+    Ok((entry, path))
+}
+
+/// Image entries sharing a backend and reference.
+type ImageGroup = ((BackendPreference, String), Vec<ImageEntryArg>);
+
+/// Group image entries by backend and reference, in the order each group first appears on the
+/// command line; images are merged in this order, which `prefer-first`/`prefer-last` follow.
 fn group_image_entries(
     inputs: &[ImageEntryArg],
     default_backend: BackendPreference,
-) -> Result<BTreeMap<(BackendPreference, String), Vec<ImageEntryArg>>> {
-    let mut map: BTreeMap<(BackendPreference, String), Vec<ImageEntryArg>> = BTreeMap::new();
+) -> Result<Vec<ImageGroup>> {
+    let mut groups: Vec<ImageGroup> = Vec::new();
     for input in inputs {
-        let preference = input.backend.unwrap_or(default_backend);
-        map.entry((preference, input.reference.clone()))
-            .or_default()
-            .push(input.clone());
+        let key = (
+            input.backend.unwrap_or(default_backend),
+            input.reference.clone(),
+        );
+        match groups.iter_mut().find(|(existing, _)| *existing == key) {
+            Some((_, entries)) => entries.push(input.clone()),
+            None => groups.push((key, vec![input.clone()])),
+        }
     }
-    Ok(map)
+    Ok(groups)
 }
 
+/// Backfill the system config files of each image, merged like any other input.
 fn ensure_system_assets(
     closure: &mut DependencyClosure,
     resolvers: &[(Origin, Arc<dyn PathResolver>)],
+    origin_namespaces: bool,
+    merger: &mut ClosureMerger,
 ) -> Result<()> {
-    let mut existing: HashSet<PathBuf> = HashSet::new();
-    for file in &closure.files {
-        mark_existing(&mut existing, &file.destination);
-    }
     for (origin, resolver) in resolvers {
         if !matches!(origin, Origin::Image(_)) {
             continue;
         }
+        let mut assets = DependencyClosure::default();
         for asset in SYSTEM_ASSET_PATHS {
             let logical = LogicalPath::new(origin.clone(), PathBuf::from(asset));
             let candidate = resolver.to_host(&logical);
@@ -736,10 +1314,11 @@ fn ensure_system_assets(
                 Ok(meta) if meta.is_file() => {}
                 _ => continue,
             }
-            let destination = payload_path_for(logical.path());
-            if existing.contains(&destination) {
-                continue;
-            }
+            let destination = if origin_namespaces {
+                origin.payload_root().join(asset.trim_start_matches('/'))
+            } else {
+                payload_path_for(logical.path())
+            };
             let digest = compute_digest(&candidate).with_context(|| {
                 format!(
                     "failed to hash system asset {} (origin {:?})",
@@ -753,13 +1332,18 @@ fn ensure_system_assets(
                 candidate.display(),
                 origin
             );
-            closure.files.push(ResolvedFile {
+            assets.files.push(ResolvedFile {
                 source: candidate.clone(),
-                destination: destination.clone(),
+                destination,
                 digest,
             });
-            mark_existing(&mut existing, &destination);
         }
+        merger.merge(
+            closure,
+            assets,
+            Some(origin),
+            &format!("system assets of {origin:?}"),
+        );
     }
     Ok(())
 }
@@ -996,20 +1580,6 @@ fn compute_digest(path: &Path) -> Result<String> {
     Ok(format!("{:x}", hasher.finalize()))
 }
 
-fn mark_existing(existing: &mut HashSet<PathBuf>, path: &Path) {
-    let mut current = path.to_path_buf();
-    loop {
-        if existing.contains(&current) {
-            // still walk up to avoid missing parents
-        } else {
-            existing.insert(current.clone());
-        }
-        if !current.pop() {
-            break;
-        }
-    }
-}
-
 fn sanitize_symlinks(closure: &mut DependencyClosure) {
     // Prefer files over symlinks: drop any symlink whose destination already has a file.
     if !closure.symlinks.is_empty() {
@@ -1267,12 +1837,7 @@ fn build_agent_image_closure(args: BuildAgentImageArgs) -> Result<ImageClosureRe
         BackendPreference::Podman => "podman",
         BackendPreference::Auto => unreachable!("auto preference should be resolved earlier"),
     };
-    let runner = AgentTraceRunner::new(
-        launch.command_for_backend(backend),
-        launch.bin_path.clone(),
-        launch.keep_output,
-        launch.keep_rootfs,
-    )?;
+    let runner = AgentTraceRunner::new(launch.command_for_backend(backend), launch)?;
     debug!("agent: launching container backed by {backend:?} for `{reference}`");
     let run_result = runner.run(reference, entries, trace_backend)?;
     let AgentRunResult {
@@ -1618,6 +2183,15 @@ fn resolve_run_mode(arg: RunModeArg) -> RunMode {
         RunModeArg::Host => RunMode::Host,
         RunModeArg::Bwrap => RunMode::Bwrap,
         RunModeArg::Chroot => RunMode::Chroot,
+        RunModeArg::Sandbox => RunMode::Sandbox,
+        RunModeArg::Overlay => RunMode::Overlay,
+    }
+}
+
+fn resolve_host_exec(arg: HostExecArg) -> HostExec {
+    match arg {
+        HostExecArg::Loader => HostExec::Loader,
+        HostExecArg::Userland => HostExec::Userland,
     }
 }
 
@@ -1639,21 +2213,70 @@ fn log_closure_stats(label: &str, closure: &DependencyClosure) {
     }
 }
 
-fn log_merge_report(reference: &str, report: &MergeReport) {
-    info!(
-        "image `{}` merge summary: {} file(s) added, {} reused, {} entry(es) added",
-        reference, report.added_files, report.reused_files, report.added_entries
-    );
+/// Merges inputs into the bundle closure under the merge policy and keeps every decision.
+struct ClosureMerger {
+    policy: MergePolicy,
+    conflicts: Vec<MergeConflict>,
+}
+
+impl ClosureMerger {
+    fn new(policy: MergePolicy) -> Self {
+        Self {
+            policy,
+            conflicts: Vec::new(),
+        }
+    }
+
+    fn merge(
+        &mut self,
+        closure: &mut DependencyClosure,
+        other: DependencyClosure,
+        origin: Option<&Origin>,
+        label: &str,
+    ) {
+        let report = closure.merge_with(other, &self.policy, origin);
+        log_merge_report(label, &report);
+        self.conflicts.extend(report.conflicts);
+    }
+
+    /// The recorded conflicts, or an error listing those rejected under the `error` policy.
+    fn finish(self) -> Result<Vec<MergeConflict>> {
+        let rejected: Vec<String> = self
+            .conflicts
+            .iter()
+            .filter(|conflict| conflict.resolution == ConflictResolution::Rejected)
+            .map(|conflict| conflict.destination.display().to_string())
+            .collect();
+        if !rejected.is_empty() {
+            bail!(
+                "{} merge conflict(s) under policy `error`: {}",
+                rejected.len(),
+                rejected.join(", ")
+            );
+        }
+        Ok(self.conflicts)
+    }
+}
+
+fn log_merge_report(label: &str, report: &MergeReport) {
+    if report.added_files + report.added_entries > 0 || !report.conflicts.is_empty() {
+        info!(
+            "{} merge summary: {} file(s) added, {} reused, {} entry(es) added",
+            label, report.added_files, report.reused_files, report.added_entries
+        );
+    }
     if !report.conflicts.is_empty() {
         warn!(
-            "{} conflict(s) while merging image `{}` into closure",
+            "{} conflict(s) while merging {} into closure",
             report.conflicts.len(),
-            reference
+            label
         );
         for conflict in report.conflicts.iter().take(3) {
             warn!(
-                " - destination {} already provided (existing digest {} vs new {})",
+                " - destination {}: {} under {} (existing digest {} vs new {})",
                 conflict.destination.display(),
+                conflict.resolution,
+                conflict.policy,
                 conflict.existing_digest,
                 conflict.incoming_digest,
             );
@@ -1700,22 +2323,133 @@ fn log_validation_report(report: &ValidationReport) {
     }
 }
 
-fn apply_env_overrides(closure: &mut DependencyClosure, overrides: &[(String, String)]) {
-    if overrides.is_empty() {
-        return;
+fn guard_secret_files(
+    closure: &mut DependencyClosure,
+    mode: SecretFilesArg,
+    allowed: Vec<String>,
+) -> Result<()> {
+    let action = match mode {
+        SecretFilesArg::Off => return Ok(()),
+        SecretFilesArg::Drop => SecretFileAction::Drop,
+        SecretFilesArg::Fail => SecretFileAction::Fail,
+    };
+    let report = SecretFileGuard::new()
+        .with_action(action)
+        .with_allowed(allowed)
+        .apply(closure);
+    if report.is_clean() {
+        return Ok(());
+    }
+    if action == SecretFileAction::Fail {
+        bail!(
+            "secret file guard found {} file(s); use --allow-secret-file for false positives:\n{}",
+            report.findings.len(),
+            describe_secret_findings(&report).trim_end()
+        );
+    }
+    info!(
+        "secret file guard dropped {} file(s); use --allow-secret-file to keep any of them",
+        report.findings.len()
+    );
+    Ok(())
+}
+
+fn describe_secret_findings(report: &SecretFileReport) -> String {
+    let mut details = String::new();
+    for finding in &report.findings {
+        let _ = writeln!(
+            &mut details,
+            " - {} ({}, from {})",
+            finding.path.display(),
+            finding.reason,
+            finding.source.display()
+        );
     }
-    let overrides: BTreeMap<String, String> = overrides.iter().cloned().collect();
-    for snapshot in closure.metadata.values_mut() {
-        snapshot.env.extend(overrides.clone());
+    details
+}
+
+/// `--env-*` / `--set-env` flags, applied on top of each origin's default env policy.
+struct EnvPolicyArgs {
+    mode: Option<EnvModeArg>,
+    allow: Vec<String>,
+    deny: Vec<String>,
+    rules: Vec<(String, EnvMerge)>,
+    set: Vec<(String, String)>,
+}
+
+fn resolve_env_policy(origin: &Origin, args: &EnvPolicyArgs) -> EnvPolicy {
+    let mut policy = EnvPolicy::for_origin(origin);
+    if let Some(mode) = args.mode {
+        policy.mode = match mode {
+            EnvModeArg::Inherit => EnvMode::Inherit,
+            EnvModeArg::Snapshot => EnvMode::Snapshot,
+            EnvModeArg::Overlay => EnvMode::Overlay,
+        };
     }
-    for origin in closure.entry_plans.iter().map(|p| p.origin().clone()) {
-        closure
-            .metadata
-            .entry(origin.clone())
-            .or_default()
-            .env
-            .extend(overrides.clone());
+    policy.allow.extend(args.allow.iter().cloned());
+    policy.deny.extend(args.deny.iter().cloned());
+    policy.rules.extend(args.rules.iter().cloned());
+    policy.set.extend(args.set.iter().cloned());
+    policy
+}
+
+/// Origins that contributed entries, in first-seen order.
+fn entry_origins(closure: &DependencyClosure) -> Vec<Origin> {
+    let mut origins: Vec<Origin> = Vec::new();
+    for plan in &closure.entry_plans {
+        if !origins.contains(plan.origin()) {
+            origins.push(plan.origin().clone());
+        }
+    }
+    origins
+}
+
+/// Collect the auxv profile each origin's launchers should replay, if any was requested.
+fn resolve_auxv_profiles(
+    closure: &DependencyClosure,
+    replay: bool,
+    profile_path: Option<&Path>,
+) -> Result<Vec<(Origin, AuxvProfile)>> {
+    let origins = entry_origins(closure);
+    if let Some(path) = profile_path {
+        let data = fs::read(path)
+            .with_context(|| format!("failed to read auxv profile {}", path.display()))?;
+        let profile: AuxvProfile = serde_json::from_slice(&data)
+            .with_context(|| format!("invalid auxv profile {}", path.display()))?;
+        if profile.is_empty() {
+            bail!("auxv profile {} sets no values", path.display());
+        }
+        return Ok(origins
+            .into_iter()
+            .map(|origin| (origin, profile.clone()))
+            .collect());
+    }
+    if !replay {
+        return Ok(Vec::new());
+    }
+    let mut profiles = Vec::new();
+    for origin in origins {
+        let profile = match &origin {
+            // Host entries are snapshotted from the build process itself.
+            Origin::Host => AuxvProfile::from_metadata(&RuntimeMetadata {
+                auxv: read_auxv_entries()?,
+                platform: capture_platform_string(),
+                ..RuntimeMetadata::default()
+            }),
+            Origin::Image(_) => closure
+                .metadata
+                .get(&origin)
+                .map(AuxvProfile::from_metadata)
+                .unwrap_or_default(),
+        };
+        if profile.is_empty() {
+            warn!("no auxv snapshot captured for {origin:?}; its entries will use the host auxv");
+            continue;
+        }
+        debug!("replaying auxv for {origin:?}: {profile:?}");
+        profiles.push((origin, profile));
     }
+    Ok(profiles)
 }
 
 /// Heuristic env construction for trace runs (host inputs).
@@ -1833,12 +2567,28 @@ fn include_java_runtime(
     }
 }
 
-fn capture_runtime_metadata() -> Result<RuntimeMetadata> {
+/// Snapshot the agent's auxv, env, uname and platform. Env vars `secrets` matches are dropped,
+/// or fail the capture when `strict`, so they never reach report.json.
+fn capture_runtime_metadata(secrets: &SecretRedactor, strict: bool) -> Result<RuntimeMetadata> {
     let auxv = read_auxv_entries().context("failed to read /proc/self/auxv")?;
     let mut env_map = BTreeMap::new();
     for (key, value) in env::vars() {
         env_map.insert(key, value);
     }
+    let dropped = secrets.redact(&mut env_map);
+    if !dropped.is_empty() {
+        if strict {
+            bail!(
+                "build-time env snapshot contains secrets: {}",
+                dropped.join(", ")
+            );
+        }
+        warn!(
+            "agent: dropped {} secret env var(s) from runtime metadata: {}",
+            dropped.len(),
+            dropped.join(", ")
+        );
+    }
     let uname = match capture_uname() {
         Ok(info) => Some(info),
         Err(err) => {
@@ -1976,6 +2726,33 @@ mod tests {
     use super::*;
     use sidebundle_core::{DependencyClosure, ResolvedFile, ResolvedSymlink};
 
+    #[test]
+    fn agent_metadata_drops_secret_env_vars() {
+        unsafe {
+            env::set_var("AWS_SIDEBUNDLE_AGENT_TEST", "key");
+            env::set_var("SIDEBUNDLE_AGENT_TEST_PRIVATE", "value");
+            env::set_var("SIDEBUNDLE_AGENT_TEST_PLAIN", "value");
+        }
+        let secrets = SecretRedactor::default().with_patterns(["*_test_private".to_string()]);
+        let metadata = capture_runtime_metadata(&secrets, false).unwrap();
+        assert!(!metadata.env.contains_key("AWS_SIDEBUNDLE_AGENT_TEST"));
+        assert!(!metadata.env.contains_key("SIDEBUNDLE_AGENT_TEST_PRIVATE"));
+        assert_eq!(
+            metadata
+                .env
+                .get("SIDEBUNDLE_AGENT_TEST_PLAIN")
+                .map(String::as_str),
+            Some("value")
+        );
+        let err = capture_runtime_metadata(&secrets, true).unwrap_err();
+        assert!(err.to_string().contains("AWS_SIDEBUNDLE_AGENT_TEST"));
+        unsafe {
+            env::remove_var("AWS_SIDEBUNDLE_AGENT_TEST");
+            env::remove_var("SIDEBUNDLE_AGENT_TEST_PRIVATE");
+            env::remove_var("SIDEBUNDLE_AGENT_TEST_PLAIN");
+        }
+    }
+
     #[test]
     fn parse_create_cmd_with_host_entries() {
         let cli = Cli::parse_from([
@@ -1996,6 +2773,36 @@ mod tests {
         }
     }
 
+    #[test]
+    fn images_merge_in_command_line_order() {
+        let inputs = [
+            parse_image_entry("zeta:1::/usr/bin/tool").unwrap(),
+            parse_image_entry("alpha:1::/usr/bin/tool").unwrap(),
+            parse_image_entry("zeta:1::/usr/bin/other").unwrap(),
+        ];
+        let groups = group_image_entries(&inputs, BackendPreference::Auto).unwrap();
+        let references: Vec<&str> = groups
+            .iter()
+            .map(|((_, reference), _)| reference.as_str())
+            .collect();
+        assert_eq!(references, ["zeta:1", "alpha:1"]);
+        assert_eq!(groups[0].1.len(), 2);
+
+        let policy = MergePolicy::new(ConflictPolicy::PreferLast);
+        let mut closure = DependencyClosure::default();
+        for ((_, reference), _) in &groups {
+            let mut image = DependencyClosure::default();
+            image.files.push(ResolvedFile::new(
+                format!("/images/{reference}/tool"),
+                "payload/usr/bin/tool",
+                format!("digest-{reference}"),
+            ));
+            closure.merge_with(image, &policy, Some(&Origin::Image(reference.clone())));
+        }
+        assert_eq!(closure.files.len(), 1);
+        assert_eq!(closure.files[0].digest, "digest-alpha:1");
+    }
+
     #[test]
     fn parse_create_cmd_with_image_entries() {
         let cli = Cli::parse_from([
@@ -2013,6 +2820,226 @@ mod tests {
         }
     }
 
+    #[test]
+    fn replay_auxv_conflicts_with_explicit_profile() {
+        let result = Cli::try_parse_from([
+            "sidebundle",
+            "create",
+            "--from-host",
+            "/bin/echo",
+            "--replay-auxv",
+            "--auxv-profile",
+            "profile.json",
+        ]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn bwrap_flags_extend_preset() {
+        let cli = Cli::try_parse_from([
+            "sidebundle",
+            "create",
+            "--from-host",
+            "/bin/echo",
+            "--run-mode",
+            "bwrap",
+            "--bwrap-policy",
+            "user",
+            "--bwrap-ro-bind",
+            "/opt/models:/models",
+            "--bwrap-env",
+            "DISPLAY",
+        ])
+        .unwrap();
+        let Commands::Create(args) = cli.command else {
+            panic!("expected create");
+        };
+        assert!(args.bwrap.is_set());
+        let policy = args.bwrap.into_policy();
+        assert!(policy.share_net && policy.bind_cwd && policy.bind_home);
+        assert_eq!(policy.ro_binds[0].dest, PathBuf::from("/models"));
+        assert_eq!(policy.env_passthrough, ["DISPLAY"]);
+    }
+
+    #[test]
+    fn landlock_flags_imply_the_ruleset() {
+        let parse = |extra: &[&str]| {
+            let mut argv = vec!["sidebundle", "create", "--from-host", "/bin/echo"];
+            argv.extend_from_slice(extra);
+            let Commands::Create(args) = Cli::try_parse_from(argv).unwrap().command else {
+                panic!("expected create");
+            };
+            args.landlock.into_policy()
+        };
+        assert_eq!(parse(&[]), None);
+        let policy = parse(&[
+            "--landlock-write",
+            "~/.cache/tool",
+            "--landlock-no-write-cwd",
+        ])
+        .expect("flags enable landlock");
+        assert_eq!(policy.write, [PathBuf::from("~/.cache/tool")]);
+        assert!(!policy.write_cwd && policy.write_tmp);
+        assert_eq!(parse(&["--landlock"]), Some(LandlockPolicy::default()));
+    }
+
+    #[test]
+    fn process_flags_build_policy() {
+        let cli = Cli::try_parse_from([
+            "sidebundle",
+            "create",
+            "--from-host",
+            "/bin/echo",
+            "--rlimit",
+            "nofile=1024:4096",
+            "--umask",
+            "027",
+            "--nice",
+            "-5",
+            "--ionice",
+            "idle",
+            "--deny-syscall",
+            "ptrace",
+            "--deny-syscall",
+            "mount",
+        ])
+        .unwrap();
+        let Commands::Create(args) = cli.command else {
+            panic!("expected create");
+        };
+        let policy = args.process.into_policy();
+        assert_eq!(policy.rlimits[0].hard, Some(4096));
+        assert_eq!(policy.umask, Some(0o027));
+        assert_eq!(policy.nice, Some(-5));
+        assert_eq!(policy.deny_syscalls, ["ptrace", "mount"]);
+        assert!(Cli::try_parse_from([
+            "sidebundle",
+            "create",
+            "--from-host",
+            "/bin/echo",
+            "--deny-syscall",
+            "read",
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn run_mode_fallback_accepts_a_list() {
+        let cli = Cli::try_parse_from([
+            "sidebundle",
+            "create",
+            "--from-host",
+            "/bin/echo",
+            "--run-mode",
+            "bwrap",
+            "--run-mode-fallback",
+            "sandbox,host",
+        ])
+        .unwrap();
+        let Commands::Create(args) = cli.command else {
+            panic!("expected create");
+        };
+        assert_eq!(
+            args.run_mode_fallback,
+            [RunModeArg::Sandbox, RunModeArg::Host]
+        );
+    }
+
+    #[test]
+    fn portable_dir_defaults_inside_the_bundle() {
+        let parse = |extra: &[&str]| {
+            let cli = Cli::try_parse_from(
+                ["sidebundle", "create", "--from-host", "/bin/echo"]
+                    .into_iter()
+                    .chain(extra.iter().copied()),
+            )
+            .unwrap();
+            let Commands::Create(args) = cli.command else {
+                panic!("expected create");
+            };
+            args.portable
+        };
+        assert_eq!(parse(&[]), None);
+        assert_eq!(parse(&["--portable"]), Some(PathBuf::from("home")));
+        assert_eq!(
+            parse(&["--portable", "../tool.home"]),
+            Some(PathBuf::from("../tool.home"))
+        );
+    }
+
+    #[test]
+    fn state_dir_is_optional() {
+        let parse = |extra: &[&str]| {
+            let cli = Cli::try_parse_from(
+                ["sidebundle", "create", "--from-host", "/bin/echo"]
+                    .into_iter()
+                    .chain(extra.iter().copied()),
+            )
+            .unwrap();
+            let Commands::Create(args) = cli.command else {
+                panic!("expected create");
+            };
+            args.state
+        };
+        assert_eq!(parse(&[]), None);
+        assert_eq!(parse(&["--state"]), Some(None));
+        assert_eq!(
+            parse(&["--state", "~/tool.state"]),
+            Some(Some(PathBuf::from("~/tool.state")))
+        );
+    }
+
+    #[test]
+    fn device_accepts_profiles_and_paths() {
+        assert_eq!(parse_device("nvidia").unwrap(), "nvidia");
+        assert_eq!(parse_device("/dev/video*").unwrap(), "/dev/video*");
+        assert!(parse_device("gpu").is_err());
+    }
+
+    #[test]
+    fn passthrough_accepts_optional_entry_scope() {
+        assert_eq!(
+            parse_passthrough("/srv/data").unwrap(),
+            (None, PathBuf::from("/srv/data"))
+        );
+        assert_eq!(
+            parse_passthrough("tool=/opt/models").unwrap(),
+            (Some("tool".into()), PathBuf::from("/opt/models"))
+        );
+        assert_eq!(
+            parse_passthrough("/srv/a=b").unwrap(),
+            (None, PathBuf::from("/srv/a=b"))
+        );
+        assert!(parse_passthrough("tool=relative").is_err());
+    }
+
+    #[test]
+    fn env_flags_extend_origin_defaults() {
+        let args = EnvPolicyArgs {
+            mode: None,
+            allow: Vec::new(),
+            deny: vec!["AWS_*".into()],
+            rules: vec![parse_env_rule("HOME=replace").unwrap()],
+            set: vec![("APP_MODE".into(), "dev".into())],
+        };
+        let policy = resolve_env_policy(&Origin::Image("app".into()), &args);
+        assert_eq!(policy.mode, EnvMode::Overlay);
+        assert_eq!(policy.rules.get("HOME"), Some(&EnvMerge::Replace));
+        assert_eq!(policy.rules.get("PATH"), Some(&EnvMerge::Prepend));
+        assert_eq!(policy.deny, vec!["AWS_*".to_string()]);
+        assert_eq!(policy.set.get("APP_MODE").map(String::as_str), Some("dev"));
+        assert!(parse_env_rule("PATH=merge").is_err());
+        assert_eq!(
+            parse_conflict_rule("/usr/lib/*=prefer-origin=alpine:3.20").unwrap(),
+            (
+                "/usr/lib/*".to_string(),
+                ConflictPolicy::PreferOrigin("alpine:3.20".into())
+            )
+        );
+        assert!(parse_conflict_rule("=prefer-last").is_err());
+        assert!(parse_conflict_rule("/etc/*=newest").is_err());
+    }
+
     fn file(src: &str, dst: &str) -> ResolvedFile {
         ResolvedFile {
             source: PathBuf::from(src),
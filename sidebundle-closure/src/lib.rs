@@ -1,3 +1,4 @@
+pub mod guard;
 pub mod image;
 mod linker;
 pub mod trace;
@@ -284,6 +285,8 @@ pub struct ClosureBuilder {
     origin_paths: HashMap<Origin, Vec<PathBuf>>,
     scanned_scripts: RefCell<HashSet<PathBuf>>,
     allow_gpu_libs: bool,
+    /// Sonames of driver libraries left out by the gpu-driver filter.
+    host_drivers: RefCell<BTreeSet<String>>,
     runner: LinkerRunner,
     tracer: Option<trace::TraceCollector>,
     resolvers: ResolverSet,
@@ -301,6 +304,7 @@ impl ClosureBuilder {
             origin_paths: HashMap::new(),
             scanned_scripts: RefCell::new(HashSet::new()),
             allow_gpu_libs: false,
+            host_drivers: RefCell::new(BTreeSet::new()),
             runner: LinkerRunner::new(),
             tracer: None,
             resolvers: ResolverSet::new(),
@@ -504,6 +508,7 @@ impl ClosureBuilder {
             runtime_aliases,
             symlinks,
             metadata: HashMap::new(),
+            host_drivers: self.host_drivers.take(),
         })
     }
 
@@ -537,6 +542,9 @@ impl ClosureBuilder {
                 "skipping traced artifact {} (filtered: {reason})",
                 resolved.display()
             );
+            if let Some(name) = original.runtime_path.file_name().and_then(|n| n.to_str()) {
+                self.record_host_driver(reason, name);
+            }
             return None;
         }
         let host_path = match canonicalize(resolved, resolver.trace_root()) {
@@ -730,6 +738,7 @@ impl ClosureBuilder {
                     linker_destination: interpreter_plan.linker_destination.clone(),
                     interpreter_args: shebang.args,
                     library_dirs: interpreter_plan.library_dirs.clone(),
+                    library_search_path: interpreter_plan.library_search_path.clone(),
                     requires_linker: interpreter_plan.requires_linker,
                     origin: origin.clone(),
                     run_mode: None,
@@ -776,6 +785,7 @@ impl ClosureBuilder {
         if let Some(dir) = entry_dest.parent() {
             lib_dirs.insert(dir.to_path_buf());
         }
+        let mut search_path: Vec<PathBuf> = Vec::new();
 
         let mut visited: HashSet<PathBuf> = HashSet::new();
         let mut queue: VecDeque<PathBuf> = VecDeque::new();
@@ -806,6 +816,7 @@ impl ClosureBuilder {
                         canonical.display(),
                         current.display()
                     );
+                    self.record_host_driver(reason, &resolution.name);
                     continue;
                 }
                 let alias_runtime = resolver
@@ -820,6 +831,9 @@ impl ClosureBuilder {
                 );
                 if let Some(dir) = dest.parent() {
                     lib_dirs.insert(dir.to_path_buf());
+                    if !search_path.iter().any(|known| known == dir) {
+                        search_path.push(dir.to_path_buf());
+                    }
                     let mut alias_path = dir.to_path_buf();
                     alias_path.push(&resolution.name);
                     if let Some(logical) = resolver.host_to_logical(&canonical) {
@@ -895,6 +909,7 @@ impl ClosureBuilder {
             linker_source: interpreter_source.unwrap_or_else(|| entry_source.to_path_buf()),
             linker_destination: interpreter_dest.unwrap_or_else(|| binary_destination.clone()),
             library_dirs: libraries,
+            library_search_path: search_path,
             requires_linker,
             origin: origin.clone(),
             run_mode: None,
@@ -1159,6 +1174,13 @@ impl ClosureBuilder {
         }
     }
 
+    /// Remember a filtered driver library so launchers can take it from the host instead.
+    fn record_host_driver(&self, reason: &str, name: &str) {
+        if reason == "gpu-driver" && name.contains(".so") {
+            self.host_drivers.borrow_mut().insert(name.to_string());
+        }
+    }
+
     fn is_bash_interpreter(interpreter: &Path) -> bool {
         interpreter
             .file_name()
@@ -1582,6 +1604,14 @@ mod tests {
                     .any(|plan| plan.display_name() == "ls"),
                 "entry plan should include launcher info"
             );
+            let Some(EntryBundlePlan::Binary(plan)) = closure.entry_plans.first() else {
+                panic!("expected a binary plan for /bin/ls");
+            };
+            assert!(!plan.library_search_path.is_empty());
+            assert!(plan
+                .library_search_path
+                .iter()
+                .all(|dir| plan.library_dirs.contains(dir)));
         }
     }
 
@@ -1624,6 +1654,19 @@ mod tests {
         );
     }
 
+    #[test]
+    fn filtered_gpu_drivers_are_recorded_for_the_host() {
+        let builder = ClosureBuilder::new();
+        builder.record_host_driver("gpu-driver", "libEGL.so.1");
+        builder.record_host_driver("gpu-driver", "libcuda.so.1");
+        builder.record_host_driver("gpu-driver", "libdrm_amdgpu.ids");
+        builder.record_host_driver("glibc-hwcaps", "libc.so.6");
+        assert_eq!(
+            builder.host_drivers.take().into_iter().collect::<Vec<_>>(),
+            ["libEGL.so.1", "libcuda.so.1"]
+        );
+    }
+
     #[test]
     fn scan_bash_commands_finds_literal_invocations() {
         let script = Path::new("tests/fixtures/scip-java");
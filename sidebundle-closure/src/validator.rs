@@ -323,6 +323,7 @@ mod tests {
             linker_source: PathBuf::from("/lib64/ld-linux-x86-64.so.2"),
             linker_destination: PathBuf::from("payload/lib64/ld-linux-x86-64.so.2"),
             library_dirs: vec![PathBuf::from("payload/lib64")],
+            library_search_path: vec![PathBuf::from("payload/lib64")],
             requires_linker: require_linker,
             origin: Origin::Host,
             run_mode: Some(RunMode::Host),
@@ -0,0 +1,206 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+/// 运行时覆盖策略的环境变量前缀，例如 `SIDEBUNDLE_BWRAP_SHARE_NET=1`。
+pub const BWRAP_ENV_PREFIX: &str = "SIDEBUNDLE_BWRAP_";
+
+/// bwrap 沙箱策略的预设。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BwrapPreset {
+    /// 隔离全部 namespace（含网络），只绑定 payload、data、少量设备、DNS 文件与当前目录。
+    #[default]
+    Strict,
+    /// 面向操作用户文件的工具：在 Strict 基础上共享网络并读写绑定 `$HOME`。
+    User,
+}
+
+impl FromStr for BwrapPreset {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "strict" => Ok(Self::Strict),
+            "user" => Ok(Self::User),
+            other => Err(format!(
+                "unknown bwrap preset `{other}` (expected strict|user)"
+            )),
+        }
+    }
+}
+
+/// 一条绑定挂载，`SRC[:DEST]`，省略 DEST 时与 SRC 相同。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BindSpec {
+    pub source: PathBuf,
+    pub dest: PathBuf,
+}
+
+impl FromStr for BindSpec {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (source, dest) = match value.split_once(':') {
+            Some((source, dest)) if !dest.is_empty() => (source, dest),
+            _ => (value.trim_end_matches(':'), value.trim_end_matches(':')),
+        };
+        let (source, dest) = (PathBuf::from(source), PathBuf::from(dest));
+        if !source.is_absolute() || !dest.is_absolute() {
+            return Err(format!("bind paths must be absolute: {value}"));
+        }
+        Ok(Self { source, dest })
+    }
+}
+
+impl fmt::Display for BindSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.source == self.dest {
+            write!(f, "{}", self.source.display())
+        } else {
+            write!(f, "{}:{}", self.source.display(), self.dest.display())
+        }
+    }
+}
+
+/// bwrap 模式的沙箱策略，写入 launcher 配置；默认即 [`BwrapPreset::Strict`]。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BwrapPolicy {
+    /// 共享宿主网络 namespace。
+    pub share_net: bool,
+    /// 读写绑定调用时的工作目录（payload 未提供该路径时），并在其中启动入口。
+    pub bind_cwd: bool,
+    /// 读写绑定宿主 `$HOME`。
+    pub bind_home: bool,
+    /// 额外的只读绑定。
+    pub ro_binds: Vec<BindSpec>,
+    /// 额外的读写绑定。
+    pub rw_binds: Vec<BindSpec>,
+    /// 无论 env policy 如何都从宿主带入沙箱的变量（`*` 通配）。
+    pub env_passthrough: Vec<String>,
+}
+
+impl Default for BwrapPolicy {
+    fn default() -> Self {
+        Self {
+            share_net: false,
+            bind_cwd: true,
+            bind_home: false,
+            ro_binds: Vec::new(),
+            rw_binds: Vec::new(),
+            env_passthrough: Vec::new(),
+        }
+    }
+}
+
+impl BwrapPolicy {
+    pub fn preset(preset: BwrapPreset) -> Self {
+        match preset {
+            BwrapPreset::Strict => Self::default(),
+            BwrapPreset::User => Self {
+                share_net: true,
+                bind_home: true,
+                ..Self::default()
+            },
+        }
+    }
+
+    /// 按 `SIDEBUNDLE_BWRAP_*` 变量覆盖策略：`POLICY` 先重置为预设，`SHARE_NET`/`BIND_CWD`/
+    /// `BIND_HOME` 取 `0`/`1`，`RO_BIND`/`BIND`/`ENV` 为逗号分隔的追加项。设备见 [`crate::DevicePolicy`]。
+    pub fn apply_overrides(
+        &mut self,
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> Result<(), String> {
+        let var = |name: &str| lookup(&format!("{BWRAP_ENV_PREFIX}{name}"));
+        if let Some(preset) = var("POLICY") {
+            *self = Self::preset(preset.parse()?);
+        }
+        for (name, flag) in [
+            ("SHARE_NET", &mut self.share_net),
+            ("BIND_CWD", &mut self.bind_cwd),
+            ("BIND_HOME", &mut self.bind_home),
+        ] {
+            if let Some(value) = var(name) {
+                *flag = parse_switch(&value)
+                    .ok_or_else(|| format!("{BWRAP_ENV_PREFIX}{name} must be 0 or 1"))?;
+            }
+        }
+        for (name, binds) in [
+            ("RO_BIND", &mut self.ro_binds),
+            ("BIND", &mut self.rw_binds),
+        ] {
+            for item in var(name).iter().flat_map(|value| list_items(value)) {
+                binds.push(item.parse()?);
+            }
+        }
+        if let Some(value) = var("ENV") {
+            self.env_passthrough
+                .extend(list_items(&value).map(str::to_string));
+        }
+        Ok(())
+    }
+}
+
+fn parse_switch(value: &str) -> Option<bool> {
+    match value.trim() {
+        "1" | "true" | "yes" | "on" => Some(true),
+        "0" | "false" | "no" | "off" | "" => Some(false),
+        _ => None,
+    }
+}
+
+fn list_items(value: &str) -> impl Iterator<Item = &str> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn bind_spec_defaults_destination_to_source() {
+        let bind: BindSpec = "/srv/data".parse().unwrap();
+        assert_eq!(bind.dest, PathBuf::from("/srv/data"));
+        let bind: BindSpec = "/srv/data:/data".parse().unwrap();
+        assert_eq!(bind.source, PathBuf::from("/srv/data"));
+        assert_eq!(bind.dest, PathBuf::from("/data"));
+        assert_eq!(bind.to_string(), "/srv/data:/data");
+        assert!("data".parse::<BindSpec>().is_err());
+    }
+
+    #[test]
+    fn env_overrides_reset_preset_and_extend_lists() {
+        let env: HashMap<&str, &str> = [
+            ("SIDEBUNDLE_BWRAP_POLICY", "user"),
+            ("SIDEBUNDLE_BWRAP_BIND_HOME", "0"),
+            ("SIDEBUNDLE_BWRAP_RO_BIND", "/etc/ssl, /opt/models:/models"),
+            ("SIDEBUNDLE_BWRAP_ENV", "DISPLAY,XAUTHORITY"),
+        ]
+        .into_iter()
+        .collect();
+        let mut policy = BwrapPolicy {
+            rw_binds: vec!["/srv".parse().unwrap()],
+            ..BwrapPolicy::default()
+        };
+        policy
+            .apply_overrides(|name| env.get(name).map(|value| value.to_string()))
+            .unwrap();
+        assert!(policy.share_net && policy.bind_cwd && !policy.bind_home);
+        assert!(policy.rw_binds.is_empty());
+        assert_eq!(policy.ro_binds.len(), 2);
+        assert_eq!(policy.ro_binds[1].dest, PathBuf::from("/models"));
+        assert_eq!(policy.env_passthrough, ["DISPLAY", "XAUTHORITY"]);
+
+        let mut strict = BwrapPolicy::default();
+        let err = strict
+            .apply_overrides(|name| (name == "SIDEBUNDLE_BWRAP_SHARE_NET").then(|| "maybe".into()))
+            .unwrap_err();
+        assert!(err.contains("SHARE_NET"));
+    }
+}
@@ -0,0 +1,188 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+/// 运行时跳过宿主兼容性预检的环境变量（`SIDEBUNDLE_SKIP_PREFLIGHT=1`）。
+pub const PREFLIGHT_ENV: &str = "SIDEBUNDLE_SKIP_PREFLIGHT";
+
+/// Linux 内核版本，配置中写作 `"MAJOR.MINOR.PATCH"`。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub struct KernelVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl KernelVersion {
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// 解析 `uname -r` 形式的版本，忽略数字之后的发行版后缀（如 `5.15.0-91-generic`）。
+    pub fn from_release(release: &str) -> Option<Self> {
+        let mut parts = release.split('.').map(|part| {
+            let digits = part
+                .find(|c: char| !c.is_ascii_digit())
+                .map_or(part, |end| &part[..end]);
+            digits.parse::<u32>().ok()
+        });
+        let major = parts.next()??;
+        let minor = parts.next().flatten().unwrap_or(0);
+        let patch = parts.next().flatten().unwrap_or(0);
+        Some(Self::new(major, minor, patch))
+    }
+}
+
+impl fmt::Display for KernelVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl FromStr for KernelVersion {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Self::from_release(value).ok_or_else(|| format!("invalid kernel version `{value}`"))
+    }
+}
+
+impl From<KernelVersion> for String {
+    fn from(version: KernelVersion) -> Self {
+        version.to_string()
+    }
+}
+
+impl TryFrom<String> for KernelVersion {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// 某个 bundle 文件提出的最低内核版本。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KernelRequirement {
+    pub version: KernelVersion,
+    /// 提出要求的 bundle 内路径。
+    pub required_by: PathBuf,
+}
+
+/// 某个 bundle 文件需要的 x86-64 微架构级别（1 为基线，2-4 对应 x86-64-v2 到 v4）。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IsaRequirement {
+    pub level: u8,
+    pub required_by: PathBuf,
+}
+
+/// 打包时从 bundle 内 ELF 推导出的宿主要求，launcher 在 exec 前对照 `uname` 与 CPUID 检查。
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HostRequirements {
+    /// 来自 `NT_GNU_ABI_TAG`（包括 glibc 自身的最低内核版本）。
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kernel: Option<KernelRequirement>,
+    /// 来自 GNU property note 的 `GNU_PROPERTY_X86_ISA_1_NEEDED`，或直接使用的
+    /// `glibc-hwcaps/x86-64-vN` 目录。
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub x86_isa: Option<IsaRequirement>,
+}
+
+impl HostRequirements {
+    pub fn is_empty(&self) -> bool {
+        self.kernel.is_none() && self.x86_isa.is_none()
+    }
+
+    /// 记录 `path` 需要的内核版本，保留较高者。
+    pub fn require_kernel(&mut self, version: KernelVersion, path: &Path) {
+        if self.kernel.as_ref().is_none_or(|req| version > req.version) {
+            self.kernel = Some(KernelRequirement {
+                version,
+                required_by: path.to_path_buf(),
+            });
+        }
+    }
+
+    /// 记录 `path` 需要的 x86-64 级别，保留较高者；基线级别不构成要求。
+    pub fn require_x86_isa(&mut self, level: u8, path: &Path) {
+        if level > 1 && self.x86_isa.as_ref().is_none_or(|req| level > req.level) {
+            self.x86_isa = Some(IsaRequirement {
+                level,
+                required_by: path.to_path_buf(),
+            });
+        }
+    }
+}
+
+/// `glibc-hwcaps/x86-64-vN` 目录下的库只能在支持该级别的 CPU 上加载。
+pub fn hwcaps_isa_level(path: &Path) -> Option<u8> {
+    let mut components = path.components().map(|c| c.as_os_str().to_str());
+    while let Some(component) = components.next() {
+        if component == Some("glibc-hwcaps") {
+            return components
+                .next()
+                .flatten()
+                .and_then(|dir| dir.strip_prefix("x86-64-v"))
+                .and_then(|level| level.parse().ok());
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kernel_versions_parse_uname_releases() {
+        assert_eq!(
+            KernelVersion::from_release("5.15.0-91-generic"),
+            Some(KernelVersion::new(5, 15, 0))
+        );
+        assert_eq!(
+            KernelVersion::from_release("6.8"),
+            Some(KernelVersion::new(6, 8, 0))
+        );
+        assert_eq!(KernelVersion::from_release("linux"), None);
+        assert!(KernelVersion::new(3, 10, 0) < KernelVersion::new(4, 4, 0));
+    }
+
+    #[test]
+    fn requirements_keep_the_strictest_source() {
+        let mut req = HostRequirements::default();
+        req.require_kernel(KernelVersion::new(3, 2, 0), Path::new("payload/bin/tool"));
+        req.require_kernel(
+            KernelVersion::new(4, 4, 0),
+            Path::new("payload/lib/libc.so.6"),
+        );
+        req.require_kernel(
+            KernelVersion::new(3, 10, 0),
+            Path::new("payload/lib/libm.so.6"),
+        );
+        req.require_x86_isa(1, Path::new("payload/bin/tool"));
+        assert!(req.x86_isa.is_none());
+        req.require_x86_isa(3, Path::new("payload/lib/libz.so.1"));
+        let kernel = req.kernel.as_ref().unwrap();
+        assert_eq!(kernel.version, KernelVersion::new(4, 4, 0));
+        assert_eq!(kernel.required_by, Path::new("payload/lib/libc.so.6"));
+        assert_eq!(req.x86_isa.as_ref().unwrap().level, 3);
+        assert_eq!(
+            hwcaps_isa_level(Path::new(
+                "payload/usr/lib/x86_64-linux-gnu/glibc-hwcaps/x86-64-v3/libz.so.1"
+            )),
+            Some(3)
+        );
+        assert_eq!(
+            hwcaps_isa_level(Path::new("payload/usr/lib/libz.so.1")),
+            None
+        );
+    }
+}
@@ -0,0 +1,176 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::bwrap::BWRAP_ENV_PREFIX;
+
+/// 运行时追加设备的环境变量，逗号分隔的设备组名或路径；`SIDEBUNDLE_BWRAP_DEV` 为其别名。
+pub const DEVICE_ENV: &str = "SIDEBUNDLE_DEVICE";
+
+/// 常见的宿主设备组。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceProfile {
+    /// DRM/Mesa 渲染节点（`/dev/dri`）。
+    Dri,
+    /// NVIDIA 专有驱动的控制与 UVM 节点（`/dev/nvidia*`）。
+    Nvidia,
+    /// 硬件虚拟化（`/dev/kvm`）。
+    Kvm,
+    /// 用户态文件系统（`/dev/fuse`）。
+    Fuse,
+    /// 输入设备与 uinput（`/dev/input`、`/dev/uinput`）。
+    Input,
+}
+
+impl DeviceProfile {
+    /// 该组包含的宿主路径，可含 `*` 通配（仅限最后一级）。
+    pub fn patterns(self) -> &'static [&'static str] {
+        match self {
+            Self::Dri => &["/dev/dri"],
+            Self::Nvidia => &["/dev/nvidia*"],
+            Self::Kvm => &["/dev/kvm"],
+            Self::Fuse => &["/dev/fuse"],
+            Self::Input => &["/dev/input", "/dev/uinput"],
+        }
+    }
+}
+
+impl FromStr for DeviceProfile {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "dri" => Ok(Self::Dri),
+            "nvidia" => Ok(Self::Nvidia),
+            "kvm" => Ok(Self::Kvm),
+            "fuse" => Ok(Self::Fuse),
+            "input" => Ok(Self::Input),
+            other => Err(format!(
+                "unknown device profile `{other}` (expected dri|nvidia|kvm|fuse|input or an \
+                 absolute path)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for DeviceProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Dri => "dri",
+            Self::Nvidia => "nvidia",
+            Self::Kvm => "kvm",
+            Self::Fuse => "fuse",
+            Self::Input => "input",
+        };
+        f.write_str(name)
+    }
+}
+
+/// 隔离模式（bwrap/chroot/sandbox/overlay）下透传的宿主设备，写入 launcher 配置。
+///
+/// launcher 启动时展开通配，只绑定宿主上实际存在的路径，缺失的静默跳过。
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DevicePolicy {
+    /// 设备组。
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub profiles: Vec<DeviceProfile>,
+    /// 额外的设备路径，可含 `*` 通配（仅限最后一级，如 `/dev/video*`）。
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub paths: Vec<String>,
+}
+
+impl DevicePolicy {
+    /// 追加一项：`/` 开头视为设备路径，否则为设备组名。
+    pub fn push(&mut self, item: &str) -> Result<(), String> {
+        if item.starts_with('/') {
+            self.paths.push(item.to_string());
+        } else {
+            let profile = item.parse()?;
+            if !self.profiles.contains(&profile) {
+                self.profiles.push(profile);
+            }
+        }
+        Ok(())
+    }
+
+    /// 追加 [`DEVICE_ENV`] 与 `SIDEBUNDLE_BWRAP_DEV` 中列出的设备。
+    pub fn apply_overrides(
+        &mut self,
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> Result<(), String> {
+        for name in [DEVICE_ENV.to_string(), format!("{BWRAP_ENV_PREFIX}DEV")] {
+            let Some(value) = lookup(&name) else {
+                continue;
+            };
+            for item in value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+            {
+                self.push(item).map_err(|err| format!("{name}: {err}"))?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.profiles.is_empty() && self.paths.is_empty()
+    }
+
+    /// 展开设备组后的全部路径模式。
+    pub fn patterns(&self) -> impl Iterator<Item = &str> {
+        self.profiles
+            .iter()
+            .flat_map(|profile| profile.patterns().iter().copied())
+            .chain(self.paths.iter().map(String::as_str))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profiles_and_paths_expand_to_patterns() {
+        let mut policy = DevicePolicy::default();
+        for item in ["input", "/dev/video*", "input", "kvm"] {
+            policy.push(item).unwrap();
+        }
+        assert_eq!(policy.profiles, [DeviceProfile::Input, DeviceProfile::Kvm]);
+        assert_eq!(
+            policy.patterns().collect::<Vec<_>>(),
+            ["/dev/input", "/dev/uinput", "/dev/kvm", "/dev/video*"]
+        );
+        assert!(policy.push("gpu").unwrap_err().contains("dri|nvidia"));
+        assert_eq!(DeviceProfile::Nvidia.to_string(), "nvidia");
+
+        let json = serde_json::to_string(&policy).unwrap();
+        assert_eq!(
+            json,
+            r#"{"profiles":["input","kvm"],"paths":["/dev/video*"]}"#
+        );
+    }
+
+    #[test]
+    fn env_overrides_append_devices() {
+        let mut policy = DevicePolicy::default();
+        policy.push("kvm").unwrap();
+        policy
+            .apply_overrides(|name| match name {
+                "SIDEBUNDLE_DEVICE" => Some("dri, /dev/video0".into()),
+                "SIDEBUNDLE_BWRAP_DEV" => Some("/dev/fuse,kvm".into()),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(policy.profiles, [DeviceProfile::Kvm, DeviceProfile::Dri]);
+        assert_eq!(policy.paths, ["/dev/video0", "/dev/fuse"]);
+
+        let err = policy
+            .apply_overrides(|name| (name == DEVICE_ENV).then(|| "gpu".into()))
+            .unwrap_err();
+        assert!(err.starts_with("SIDEBUNDLE_DEVICE:"));
+    }
+}
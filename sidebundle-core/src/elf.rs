@@ -1,8 +1,18 @@
 use std::fs;
 use std::path::{Path, PathBuf};
 
+use goblin::elf::note::{NT_GNU_ABI_TAG, NT_GNU_PROPERTY_TYPE_0};
+use goblin::elf::Elf;
 use goblin::Object;
 
+use crate::ld_cache::cache_flags;
+use crate::KernelVersion;
+
+/// `GNU_PROPERTY_X86_ISA_1_NEEDED`：位 0 为基线，位 1-3 对应 x86-64-v2 到 v4。
+const GNU_PROPERTY_X86_ISA_1_NEEDED: u32 = 0xc000_8002;
+/// `NT_GNU_ABI_TAG` 中表示 Linux 的 OS 字段。
+const ELF_NOTE_OS_LINUX: u32 = 0;
+
 /// ELF 元信息，供闭包构建参考。
 #[derive(Debug, Clone)]
 pub struct ElfMetadata {
@@ -11,6 +21,12 @@ pub struct ElfMetadata {
     pub rpaths: Vec<String>,
     pub runpaths: Vec<String>,
     pub soname: Option<String>,
+    /// `NT_GNU_ABI_TAG` 声明的最低 Linux 内核版本。
+    pub min_kernel: Option<KernelVersion>,
+    /// GNU property note 声明需要的 x86-64 ISA 级别（1 为基线）。
+    pub x86_isa_needed: Option<u8>,
+    /// 作为共享库写入 `ld.so.cache` 时的标志，未知架构为 `None`。
+    pub cache_flags: Option<i32>,
 }
 
 /// 解析失败时的错误类型。
@@ -82,5 +98,88 @@ pub fn parse_elf_metadata(path: &Path) -> Result<ElfMetadata, ElfParseError> {
         rpaths: elf.rpaths.iter().map(|r| r.to_string()).collect(),
         runpaths: elf.runpaths.iter().map(|r| r.to_string()).collect(),
         soname: elf.soname.map(|s| s.to_string()),
+        min_kernel: abi_tag_kernel(&elf, &data),
+        x86_isa_needed: x86_isa_needed(&elf, &data),
+        cache_flags: cache_flags(elf.header.e_machine, elf.is_64),
     })
 }
+
+fn gnu_notes<'a>(elf: &'a Elf, data: &'a [u8], n_type: u32) -> impl Iterator<Item = &'a [u8]> {
+    elf.iter_note_headers(data)
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .filter(move |note| note.n_type == n_type && note.name.trim_end_matches('\0') == "GNU")
+        .map(|note| note.desc)
+}
+
+fn read_u32(bytes: &[u8], little_endian: bool) -> Option<u32> {
+    let raw: [u8; 4] = bytes.get(..4)?.try_into().ok()?;
+    Some(if little_endian {
+        u32::from_le_bytes(raw)
+    } else {
+        u32::from_be_bytes(raw)
+    })
+}
+
+fn abi_tag_kernel(elf: &Elf, data: &[u8]) -> Option<KernelVersion> {
+    gnu_notes(elf, data, NT_GNU_ABI_TAG)
+        .filter_map(|desc| {
+            let word = |index: usize| read_u32(desc.get(index * 4..)?, elf.little_endian);
+            (word(0)? == ELF_NOTE_OS_LINUX)
+                .then(|| Some(KernelVersion::new(word(1)?, word(2)?, word(3)?)))
+                .flatten()
+        })
+        .max()
+}
+
+fn x86_isa_needed(elf: &Elf, data: &[u8]) -> Option<u8> {
+    let align = if elf.is_64 { 8 } else { 4 };
+    gnu_notes(elf, data, NT_GNU_PROPERTY_TYPE_0)
+        .filter_map(|desc| isa_level_from_properties(desc, elf.little_endian, align))
+        .max()
+}
+
+/// 遍历 `NT_GNU_PROPERTY_TYPE_0` 的属性数组（`pr_type`、`pr_datasz`、按 `align` 填充的数据）。
+fn isa_level_from_properties(desc: &[u8], little_endian: bool, align: usize) -> Option<u8> {
+    let mut level = None;
+    let mut offset = 0;
+    while let (Some(pr_type), Some(size)) = (
+        desc.get(offset..).and_then(|d| read_u32(d, little_endian)),
+        desc.get(offset + 4..)
+            .and_then(|d| read_u32(d, little_endian)),
+    ) {
+        let start = offset + 8;
+        if pr_type == GNU_PROPERTY_X86_ISA_1_NEEDED {
+            let bits = desc
+                .get(start..)
+                .and_then(|d| read_u32(d, little_endian))
+                .unwrap_or(0);
+            if bits != 0 {
+                level = level.max(Some((32 - bits.leading_zeros()) as u8));
+            }
+        }
+        offset = start + (size as usize).next_multiple_of(align);
+    }
+    level
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn isa_level_comes_from_the_highest_needed_bit() {
+        let mut desc = Vec::new();
+        // GNU_PROPERTY_X86_FEATURE_1_AND（IBT/SHSTK），不影响 ISA 级别。
+        for word in [0xc000_0002u32, 4, 3, 0] {
+            desc.extend(word.to_le_bytes());
+        }
+        // 需要 baseline 与 x86-64-v3。
+        for word in [GNU_PROPERTY_X86_ISA_1_NEEDED, 4, 0b101, 0] {
+            desc.extend(word.to_le_bytes());
+        }
+        assert_eq!(isa_level_from_properties(&desc, true, 8), Some(3));
+        assert_eq!(isa_level_from_properties(&desc[..16], true, 8), None);
+    }
+}
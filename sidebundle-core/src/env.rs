@@ -0,0 +1,367 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Origin;
+
+/// 镜像入口默认保留宿主值的会话类变量（用户身份、终端、图形会话、代理、locale）。
+const HOST_SESSION_VARS: &[&str] = &[
+    "HOME",
+    "USER",
+    "LOGNAME",
+    "SHELL",
+    "PWD",
+    "OLDPWD",
+    "HOSTNAME",
+    "TERM",
+    "COLORTERM",
+    "DISPLAY",
+    "WAYLAND_DISPLAY",
+    "XAUTHORITY",
+    "XDG_*",
+    "DBUS_SESSION_BUS_ADDRESS",
+    "SSH_AUTH_SOCK",
+    "LANG",
+    "LANGUAGE",
+    "LC_*",
+    "TZ",
+    "http_proxy",
+    "https_proxy",
+    "no_proxy",
+    "all_proxy",
+    "HTTP_PROXY",
+    "HTTPS_PROXY",
+    "NO_PROXY",
+    "ALL_PROXY",
+];
+
+/// 构建期快照中默认视为凭据、不写入 bundle 的变量名（大小写不敏感）。
+pub const DEFAULT_SECRET_PATTERNS: &[&str] = &[
+    "AWS_*",
+    "AZURE_*",
+    "GOOGLE_APPLICATION_CREDENTIALS",
+    "GITHUB_TOKEN",
+    "GH_TOKEN",
+    "GITLAB_TOKEN",
+    "CI_JOB_TOKEN",
+    "NPM_TOKEN",
+    "NODE_AUTH_TOKEN",
+    "VAULT_TOKEN",
+    "DOCKER_AUTH_CONFIG",
+    "SSH_AUTH_SOCK",
+    "SSH_AGENT_PID",
+    "GPG_AGENT_INFO",
+    "*_TOKEN",
+    "*SECRET*",
+    "*PASSWORD*",
+    "*PASSWD*",
+    "*_API_KEY",
+    "*_APIKEY",
+    "*_ACCESS_KEY",
+    "*_PRIVATE_KEY",
+    "*_CREDENTIALS",
+];
+
+/// launcher 组装环境时的基础来源。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum EnvMode {
+    /// 仅使用宿主环境，忽略构建期快照。
+    Inherit,
+    /// 仅使用构建期快照（无快照时退回宿主环境）。
+    Snapshot,
+    /// 宿主环境经 allow/deny 过滤后，按变量规则叠加快照。
+    #[default]
+    Overlay,
+}
+
+/// Overlay 模式下快照变量与宿主同名变量的合并方式。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum EnvMerge {
+    /// 快照值覆盖宿主值。
+    #[default]
+    Replace,
+    /// 快照值以 `:` 拼接在宿主值之前。
+    Prepend,
+    /// 快照值以 `:` 拼接在宿主值之后。
+    Append,
+    /// 宿主已设置时保留宿主值，否则使用快照值。
+    PreferHost,
+}
+
+/// 入口的环境继承策略，写入 launcher 配置。
+///
+/// `allow`/`deny`/`rules` 的键支持 `*` 通配（如 `LC_*`）。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct EnvPolicy {
+    #[serde(default)]
+    pub mode: EnvMode,
+    /// 非空时仅保留匹配的宿主变量。
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow: Vec<String>,
+    /// 丢弃匹配的宿主变量。
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deny: Vec<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub rules: BTreeMap<String, EnvMerge>,
+    /// 显式设置（`--set-env`），在任何模式下最后生效。
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub set: BTreeMap<String, String>,
+}
+
+impl EnvPolicy {
+    /// 按来源选择默认策略：镜像入口把镜像环境叠加到宿主环境上，会话类变量保留宿主值，
+    /// `PATH` 以镜像值在前拼接；宿主入口直接叠加。
+    pub fn for_origin(origin: &Origin) -> Self {
+        match origin {
+            Origin::Host => Self::default(),
+            Origin::Image(_) => {
+                let mut rules: BTreeMap<String, EnvMerge> = HOST_SESSION_VARS
+                    .iter()
+                    .map(|name| (name.to_string(), EnvMerge::PreferHost))
+                    .collect();
+                rules.insert("PATH".into(), EnvMerge::Prepend);
+                Self {
+                    rules,
+                    ..Self::default()
+                }
+            }
+        }
+    }
+
+    /// 由宿主环境与快照组装最终环境。
+    pub fn compose(
+        &self,
+        host: impl IntoIterator<Item = (String, String)>,
+        snapshot: Option<&BTreeMap<String, String>>,
+    ) -> BTreeMap<String, String> {
+        let host: BTreeMap<String, String> = host
+            .into_iter()
+            .filter(|(key, _)| self.keeps_host_var(key))
+            .collect();
+        let mut env = match (self.mode, snapshot) {
+            (EnvMode::Snapshot, Some(snapshot)) => snapshot.clone(),
+            (EnvMode::Overlay, Some(snapshot)) => {
+                let mut env = host;
+                for (key, value) in snapshot {
+                    let merged = match (self.rule_for(key), env.get(key)) {
+                        (EnvMerge::PreferHost, Some(_)) => continue,
+                        (EnvMerge::Prepend, Some(current)) if !current.is_empty() => {
+                            join_list(value, current)
+                        }
+                        (EnvMerge::Append, Some(current)) if !current.is_empty() => {
+                            join_list(current, value)
+                        }
+                        _ => value.clone(),
+                    };
+                    env.insert(key.clone(), merged);
+                }
+                env
+            }
+            _ => host,
+        };
+        env.extend(self.set.clone());
+        env
+    }
+
+    fn keeps_host_var(&self, key: &str) -> bool {
+        let allowed =
+            self.allow.is_empty() || self.allow.iter().any(|pattern| glob_match(pattern, key));
+        allowed && !self.deny.iter().any(|pattern| glob_match(pattern, key))
+    }
+
+    fn rule_for(&self, key: &str) -> EnvMerge {
+        if let Some(rule) = self.rules.get(key) {
+            return *rule;
+        }
+        self.rules
+            .iter()
+            .find(|(pattern, _)| pattern.contains('*') && glob_match(pattern, key))
+            .map(|(_, rule)| *rule)
+            .unwrap_or_default()
+    }
+}
+
+/// 从构建期快照中剔除凭据类变量。
+#[derive(Debug, Clone)]
+pub struct SecretRedactor {
+    patterns: Vec<String>,
+}
+
+impl Default for SecretRedactor {
+    fn default() -> Self {
+        Self {
+            patterns: DEFAULT_SECRET_PATTERNS
+                .iter()
+                .map(|pattern| pattern.to_ascii_uppercase())
+                .collect(),
+        }
+    }
+}
+
+impl SecretRedactor {
+    /// 在内置规则之外追加用户规则。
+    pub fn with_patterns(mut self, patterns: impl IntoIterator<Item = String>) -> Self {
+        self.patterns.extend(
+            patterns
+                .into_iter()
+                .map(|pattern| pattern.to_ascii_uppercase()),
+        );
+        self
+    }
+
+    pub fn is_secret(&self, key: &str) -> bool {
+        let key = key.to_ascii_uppercase();
+        self.patterns
+            .iter()
+            .any(|pattern| glob_match(pattern, &key))
+    }
+
+    /// 删除匹配的变量，返回被删除的变量名。
+    pub fn redact(&self, env: &mut BTreeMap<String, String>) -> Vec<String> {
+        let dropped: Vec<String> = env
+            .keys()
+            .filter(|key| self.is_secret(key))
+            .cloned()
+            .collect();
+        for key in &dropped {
+            env.remove(key);
+        }
+        dropped
+    }
+}
+
+/// `*` 匹配任意长度字符的通配。
+pub fn glob_match(pattern: &str, key: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = key.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(idx) => rest = &rest[idx + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+fn join_list(first: &str, second: &str) -> String {
+    if first.is_empty() {
+        return second.to_string();
+    }
+    format!("{first}:{second}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn image_default_overlays_snapshot_on_host() {
+        let host = vars(&[
+            ("HOME", "/home/alice"),
+            ("LC_ALL", "de_DE.UTF-8"),
+            ("PATH", "/usr/bin"),
+        ]);
+        let snapshot = vars(&[
+            ("HOME", "/root"),
+            ("LC_ALL", "C"),
+            ("PATH", "/opt/app/bin"),
+            ("APP_MODE", "prod"),
+        ]);
+        let env =
+            EnvPolicy::for_origin(&Origin::Image("app".into())).compose(host, Some(&snapshot));
+        assert_eq!(env["HOME"], "/home/alice");
+        assert_eq!(env["LC_ALL"], "de_DE.UTF-8");
+        assert_eq!(env["PATH"], "/opt/app/bin:/usr/bin");
+        assert_eq!(env["APP_MODE"], "prod");
+    }
+
+    #[test]
+    fn snapshot_mode_ignores_host_and_inherit_ignores_snapshot() {
+        let host = vars(&[("TERM", "xterm")]);
+        let snapshot = vars(&[("APP_MODE", "prod")]);
+        let policy = EnvPolicy {
+            mode: EnvMode::Snapshot,
+            set: vars(&[("EXTRA", "1")]),
+            ..EnvPolicy::default()
+        };
+        assert_eq!(
+            policy.compose(host.clone(), Some(&snapshot)),
+            vars(&[("APP_MODE", "prod"), ("EXTRA", "1")])
+        );
+
+        let policy = EnvPolicy {
+            mode: EnvMode::Inherit,
+            ..EnvPolicy::default()
+        };
+        assert_eq!(policy.compose(host.clone(), Some(&snapshot)), host);
+    }
+
+    #[test]
+    fn allow_and_deny_filter_host_vars() {
+        let host = vars(&[
+            ("AWS_SECRET_ACCESS_KEY", "x"),
+            ("LANG", "C.UTF-8"),
+            ("TERM", "xterm"),
+        ]);
+        let policy = EnvPolicy {
+            allow: vec!["LANG".into(), "AWS_*".into()],
+            deny: vec!["AWS_*".into()],
+            ..EnvPolicy::default()
+        };
+        assert_eq!(policy.compose(host, None), vars(&[("LANG", "C.UTF-8")]));
+    }
+
+    #[test]
+    fn redactor_drops_builtin_and_user_patterns() {
+        let mut env = vars(&[
+            ("AWS_SESSION_TOKEN", "x"),
+            ("github_token", "x"),
+            ("DB_PASSWORD_FILE", "/run/secrets/db"),
+            ("INTERNAL_LICENSE", "x"),
+            ("PATH", "/usr/bin"),
+            ("TOKENIZERS_PARALLELISM", "false"),
+        ]);
+        let dropped = SecretRedactor::default()
+            .with_patterns(["internal_*".to_string()])
+            .redact(&mut env);
+        assert_eq!(
+            dropped,
+            vec![
+                "AWS_SESSION_TOKEN",
+                "DB_PASSWORD_FILE",
+                "INTERNAL_LICENSE",
+                "github_token"
+            ]
+        );
+        assert_eq!(
+            env,
+            vars(&[("PATH", "/usr/bin"), ("TOKENIZERS_PARALLELISM", "false")])
+        );
+    }
+
+    #[test]
+    fn wildcard_matches_anywhere() {
+        assert!(glob_match("*_SECRET_*", "APP_SECRET_KEY"));
+        assert!(glob_match("LC_*", "LC_ALL"));
+        assert!(!glob_match("LC_*", "XLC_ALL"));
+        assert!(!glob_match("*_TOKEN", "TOKEN"));
+        assert!(glob_match("A*B*A", "ABA"));
+        assert!(!glob_match("A*A", "A"));
+    }
+}
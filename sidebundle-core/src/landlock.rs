@@ -0,0 +1,68 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// 运行时关闭 Landlock 的环境变量（`SIDEBUNDLE_LANDLOCK=0`）。
+pub const LANDLOCK_ENV: &str = "SIDEBUNDLE_LANDLOCK";
+
+/// Host 模式下 Landlock 始终放行只读访问的宿主路径。
+pub const DEFAULT_LANDLOCK_READ: &[&str] = &["/etc", "/proc", "/sys", "/dev", "/usr/share"];
+
+/// Host 模式的 Landlock 文件系统规则，写入 launcher 配置并在 exec 前由 launcher 应用。
+///
+/// bundle 根目录总是可读、可执行，`data/` 可写；内核不支持 Landlock 时跳过并给出警告。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LandlockPolicy {
+    /// 额外的可读、可执行宿主路径。
+    pub read: Vec<PathBuf>,
+    /// 额外的可写目录（如状态目录）；`~/` 开头的路径在运行时相对 `$HOME` 展开。
+    pub write: Vec<PathBuf>,
+    /// 允许写调用时的工作目录。
+    pub write_cwd: bool,
+    /// 允许写 `$TMPDIR`（未设置时为 `/tmp`）。
+    pub write_tmp: bool,
+}
+
+impl Default for LandlockPolicy {
+    fn default() -> Self {
+        Self {
+            read: Vec::new(),
+            write: Vec::new(),
+            write_cwd: true,
+            write_tmp: true,
+        }
+    }
+}
+
+impl LandlockPolicy {
+    /// 把 `~/` 开头的路径展开到 `home`；没有 `home` 时返回 `None`。
+    pub fn expand_home(path: &Path, home: Option<&Path>) -> Option<PathBuf> {
+        match path.strip_prefix("~") {
+            Ok(rest) => home.map(|home| home.join(rest)),
+            Err(_) => Some(path.to_path_buf()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expand_home_only_rewrites_tilde_paths() {
+        let home = Path::new("/home/dev");
+        assert_eq!(
+            LandlockPolicy::expand_home(Path::new("~/.local/state/tool"), Some(home)),
+            Some(PathBuf::from("/home/dev/.local/state/tool"))
+        );
+        assert_eq!(
+            LandlockPolicy::expand_home(Path::new("/var/cache/tool"), None),
+            Some(PathBuf::from("/var/cache/tool"))
+        );
+        assert_eq!(
+            LandlockPolicy::expand_home(Path::new("~/state"), None),
+            None
+        );
+    }
+}
@@ -0,0 +1,269 @@
+//! glibc `ld.so.cache` 的读写（`glibc-ld.so.cache1.1` 格式）。
+//!
+//! 只处理与本机字节序一致的缓存；旧版 `ld.so-1.7.0` 前缀段会被跳过。
+
+use std::cmp::Ordering;
+use std::path::{Path, PathBuf};
+
+use goblin::elf::header::{EM_386, EM_AARCH64, EM_RISCV, EM_X86_64};
+
+const MAGIC_OLD: &[u8] = b"ld.so-1.7.0";
+const MAGIC_NEW: &[u8] = b"glibc-ld.so.cache1.1";
+const HEADER_OLD: usize = 16;
+const ENTRY_OLD: usize = 12;
+const HEADER_NEW: usize = 48;
+const ENTRY_NEW: usize = 24;
+/// 新格式头部 `flags` 字段中的字节序标记。
+const ENDIAN_LITTLE: u8 = 2;
+const ENDIAN_BIG: u8 = 3;
+
+/// 当前编译目标的库在缓存中的标志（`FLAG_ELF_LIBC6` 加架构位），未知架构为 `None`。
+pub const NATIVE_CACHE_FLAGS: Option<i32> = if cfg!(target_arch = "x86_64") {
+    Some(0x0303)
+} else if cfg!(target_arch = "aarch64") {
+    Some(0x0a03)
+} else if cfg!(target_arch = "riscv64") {
+    Some(0x1003)
+} else if cfg!(target_arch = "x86") {
+    Some(0x0003)
+} else {
+    None
+};
+
+/// ELF 机器类型与位宽对应的缓存标志，与 ldconfig 一致；未知组合为 `None`。
+pub(crate) fn cache_flags(machine: u16, is_64: bool) -> Option<i32> {
+    match (machine, is_64) {
+        (EM_X86_64, true) => Some(0x0303),
+        (EM_X86_64, false) => Some(0x0803),
+        (EM_AARCH64, true) => Some(0x0a03),
+        (EM_RISCV, true) => Some(0x1003),
+        (EM_386, false) => Some(0x0003),
+        _ => None,
+    }
+}
+
+/// 缓存中的一条记录。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LdCacheEntry {
+    /// 库类型与架构标志，见 [`NATIVE_CACHE_FLAGS`]。
+    pub flags: i32,
+    pub soname: String,
+    pub path: PathBuf,
+    /// 非零表示 hwcap 子目录中的变体。
+    pub hwcap: u64,
+}
+
+/// 解析后的 `ld.so.cache`。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LdCache {
+    entries: Vec<LdCacheEntry>,
+}
+
+impl LdCache {
+    pub fn new(entries: Vec<LdCacheEntry>) -> Self {
+        Self { entries }
+    }
+
+    /// 解析缓存文件内容；格式不符时返回 `None`。
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let mut start = 0;
+        if data.starts_with(MAGIC_OLD) {
+            let nlibs = read_u32(data, MAGIC_OLD.len() + 1)? as usize;
+            start = (HEADER_OLD + nlibs.checked_mul(ENTRY_OLD)?).next_multiple_of(8);
+        }
+        let cache = data.get(start..)?;
+        if !cache.starts_with(MAGIC_NEW) || cache.len() < HEADER_NEW {
+            return None;
+        }
+        let nlibs = read_u32(cache, 20)? as usize;
+        match cache[28] {
+            0 => {}
+            ENDIAN_LITTLE if cfg!(target_endian = "little") => {}
+            ENDIAN_BIG if cfg!(target_endian = "big") => {}
+            _ => return None,
+        }
+        let mut entries = Vec::with_capacity(nlibs.min(cache.len() / ENTRY_NEW));
+        for index in 0..nlibs {
+            let at = HEADER_NEW + index.checked_mul(ENTRY_NEW)?;
+            let flags = read_u32(cache, at)? as i32;
+            let soname = read_str(cache, read_u32(cache, at + 4)? as usize)?;
+            let path = read_str(cache, read_u32(cache, at + 8)? as usize)?;
+            let hwcap = u64::from_ne_bytes(cache.get(at + 16..at + 24)?.try_into().ok()?);
+            entries.push(LdCacheEntry {
+                flags,
+                soname,
+                path: PathBuf::from(path),
+                hwcap,
+            });
+        }
+        Some(Self { entries })
+    }
+
+    pub fn entries(&self) -> &[LdCacheEntry] {
+        &self.entries
+    }
+
+    /// `soname` 在本机架构下对应的路径，优先不带 hwcap 的记录。
+    pub fn lookup(&self, soname: &str) -> Option<&Path> {
+        let flags = NATIVE_CACHE_FLAGS?;
+        let mut matches = self
+            .entries
+            .iter()
+            .filter(|entry| entry.flags == flags && entry.soname == soname);
+        let first = matches.next()?;
+        let plain = std::iter::once(first)
+            .chain(matches)
+            .find(|entry| entry.hwcap == 0);
+        Some(plain.unwrap_or(first).path.as_path())
+    }
+
+    /// 按 ld.so 二分查找要求的顺序序列化为新格式缓存。
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut entries: Vec<&LdCacheEntry> = self.entries.iter().collect();
+        entries.sort_by(|a, b| {
+            libcmp(&b.soname, &a.soname)
+                .then(b.flags.cmp(&a.flags))
+                .then(b.hwcap.cmp(&a.hwcap))
+        });
+        let strings_at = HEADER_NEW + entries.len() * ENTRY_NEW;
+        let mut strings: Vec<u8> = Vec::new();
+        let mut table: Vec<u8> = Vec::with_capacity(entries.len() * ENTRY_NEW);
+        for entry in &entries {
+            let mut intern = |value: &[u8]| {
+                let offset = strings_at + strings.len();
+                strings.extend_from_slice(value);
+                strings.push(0);
+                offset as u32
+            };
+            let key = intern(entry.soname.as_bytes());
+            let value = intern(entry.path.as_os_str().as_encoded_bytes());
+            table.extend_from_slice(&entry.flags.to_ne_bytes());
+            table.extend_from_slice(&key.to_ne_bytes());
+            table.extend_from_slice(&value.to_ne_bytes());
+            table.extend_from_slice(&0u32.to_ne_bytes());
+            table.extend_from_slice(&entry.hwcap.to_ne_bytes());
+        }
+
+        let mut out = Vec::with_capacity(strings_at + strings.len());
+        out.extend_from_slice(MAGIC_NEW);
+        out.extend_from_slice(&(entries.len() as u32).to_ne_bytes());
+        out.extend_from_slice(&(strings.len() as u32).to_ne_bytes());
+        out.push(if cfg!(target_endian = "big") {
+            ENDIAN_BIG
+        } else {
+            ENDIAN_LITTLE
+        });
+        out.extend_from_slice(&[0; 3]);
+        // extension_offset 与保留字段。
+        out.extend_from_slice(&[0; 16]);
+        out.extend_from_slice(&table);
+        out.extend_from_slice(&strings);
+        out
+    }
+}
+
+/// glibc `_dl_cache_libcmp`：数字段按数值比较。
+fn libcmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.as_bytes(), b.as_bytes());
+    loop {
+        match (a.first(), b.first()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let (x, rest_a) = split_number(a);
+                let (y, rest_b) = split_number(b);
+                match x.cmp(&y) {
+                    Ordering::Equal => (a, b) = (rest_a, rest_b),
+                    other => return other,
+                }
+            }
+            (Some(x), _) if x.is_ascii_digit() => return Ordering::Greater,
+            (_, Some(y)) if y.is_ascii_digit() => return Ordering::Less,
+            (Some(x), Some(y)) if x != y => return x.cmp(y),
+            _ => (a, b) = (&a[1..], &b[1..]),
+        }
+    }
+}
+
+fn split_number(value: &[u8]) -> (u64, &[u8]) {
+    let digits = value.iter().take_while(|b| b.is_ascii_digit()).count();
+    let number = value[..digits].iter().fold(0u64, |acc, digit| {
+        acc.saturating_mul(10)
+            .saturating_add(u64::from(digit - b'0'))
+    });
+    (number, &value[digits..])
+}
+
+fn read_u32(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_ne_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn read_str(data: &[u8], at: usize) -> Option<String> {
+    let bytes = data.get(at..)?;
+    let end = bytes.iter().position(|b| *b == 0)?;
+    String::from_utf8(bytes[..end].to_vec()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(soname: &str, path: &str, hwcap: u64) -> LdCacheEntry {
+        LdCacheEntry {
+            flags: NATIVE_CACHE_FLAGS.unwrap_or(0x0303),
+            soname: soname.into(),
+            path: path.into(),
+            hwcap,
+        }
+    }
+
+    #[test]
+    fn cache_round_trips_in_lookup_order() {
+        let cache = LdCache::new(vec![
+            entry("libz.so.1", "/usr/lib/libz.so.1", 0),
+            entry("libGL.so.1", "/usr/lib/x86-64-v3/libGL.so.1", 1),
+            entry("libGL.so.1", "/usr/lib/libGL.so.1", 0),
+            entry("libc.so.10", "/usr/lib/libc.so.10", 0),
+            entry("libc.so.9", "/usr/lib/libc.so.9", 0),
+        ]);
+        let parsed = LdCache::parse(&cache.to_bytes()).unwrap();
+        let names: Vec<&str> = parsed
+            .entries()
+            .iter()
+            .map(|entry| entry.soname.as_str())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "libz.so.1",
+                "libc.so.10",
+                "libc.so.9",
+                "libGL.so.1",
+                "libGL.so.1"
+            ]
+        );
+        if NATIVE_CACHE_FLAGS.is_some() {
+            assert_eq!(
+                parsed.lookup("libGL.so.1"),
+                Some(Path::new("/usr/lib/libGL.so.1"))
+            );
+        }
+        assert_eq!(parsed.lookup("libEGL.so.1"), None);
+
+        // A legacy section in front is skipped.
+        let mut legacy = MAGIC_OLD.to_vec();
+        legacy.push(0);
+        legacy.extend_from_slice(&1u32.to_ne_bytes());
+        legacy.extend_from_slice(&[0; ENTRY_OLD + 4]);
+        legacy.extend_from_slice(&cache.to_bytes());
+        assert_eq!(LdCache::parse(&legacy), Some(parsed));
+        assert_eq!(LdCache::parse(b"not a cache"), None);
+
+        assert_eq!(cache_flags(EM_X86_64, true), Some(0x0303));
+        assert_eq!(cache_flags(EM_AARCH64, false), None);
+        let exe = std::env::current_exe().unwrap();
+        let meta = crate::parse_elf_metadata(&exe).unwrap();
+        assert_eq!(meta.cache_flags, NATIVE_CACHE_FLAGS);
+    }
+}
@@ -1,9 +1,33 @@
+mod bwrap;
+mod compat;
+mod device;
 mod elf;
-
+mod env;
+mod landlock;
+mod ld_cache;
+mod merge;
+mod process;
+mod state;
+
+pub use bwrap::{BindSpec, BwrapPolicy, BwrapPreset, BWRAP_ENV_PREFIX};
+pub use compat::{
+    hwcaps_isa_level, HostRequirements, IsaRequirement, KernelRequirement, KernelVersion,
+    PREFLIGHT_ENV,
+};
+pub use device::{DevicePolicy, DeviceProfile, DEVICE_ENV};
 pub use elf::{parse_elf_metadata, ElfMetadata, ElfParseError};
+pub use env::{glob_match, EnvMerge, EnvMode, EnvPolicy, SecretRedactor, DEFAULT_SECRET_PATTERNS};
+pub use landlock::{LandlockPolicy, DEFAULT_LANDLOCK_READ, LANDLOCK_ENV};
+pub use ld_cache::{LdCache, LdCacheEntry, NATIVE_CACHE_FLAGS};
+pub use merge::{ConflictPolicy, ConflictResolution, MergePolicy};
+pub use process::{
+    parse_umask, IoClass, IoNice, ProcessPolicy, Rlimit, RlimitResource, SECCOMP_SYSCALLS,
+};
+pub use state::{StateLayer, STATE_DIR_ENV};
 
 use serde::{Deserialize, Serialize};
-use std::collections::{BTreeMap, HashMap, HashSet};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::error::Error;
 use std::fmt::{self, Display};
 use std::path::{Path, PathBuf};
@@ -29,6 +53,63 @@ pub enum RunMode {
     Host,
     Bwrap,
     Chroot,
+    /// launcher 自行创建 user namespace 并 pivot_root 到 payload，无需 bwrap 或 root。
+    Sandbox,
+    /// 同 Sandbox，但根目录由 payload 顶层目录叠加宿主的 passthrough 路径组成。
+    Overlay,
+}
+
+impl RunMode {
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            RunMode::Host => "host",
+            RunMode::Bwrap => "bwrap",
+            RunMode::Chroot => "chroot",
+            RunMode::Sandbox => "sandbox",
+            RunMode::Overlay => "overlay",
+        }
+    }
+}
+
+impl Display for RunMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// 解析运行模式名称；`userns` 是 `sandbox` 的别名。
+impl FromStr for RunMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim() {
+            "host" => Ok(RunMode::Host),
+            "bwrap" => Ok(RunMode::Bwrap),
+            "chroot" => Ok(RunMode::Chroot),
+            "sandbox" | "userns" => Ok(RunMode::Sandbox),
+            "overlay" => Ok(RunMode::Overlay),
+            other => Err(format!(
+                "unknown run mode `{other}` (expected host|bwrap|chroot|sandbox|overlay)"
+            )),
+        }
+    }
+}
+
+/// 运行时覆盖运行模式偏好列表的环境变量，取值如 `host` 或 `bwrap,sandbox,host`。
+pub const RUN_MODE_ENV: &str = "SIDEBUNDLE_RUN_MODE";
+
+/// Overlay 模式下默认透传的宿主路径（未在 launcher 配置中指定时使用）。
+pub const DEFAULT_PASSTHROUGH: &[&str] = &["/home", "/srv", "/mnt", "/media"];
+
+/// Host 模式下动态入口的启动方式。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum HostExec {
+    /// 显式 exec 打包的 ld-linux，入口作为参数传入。
+    #[default]
+    Loader,
+    /// launcher 在进程内映射入口与打包的 ld-linux（userland execve），保留入口的 argv0。
+    Userland,
 }
 
 /// 目标三元组，用于后续扩展到多平台。
@@ -119,6 +200,47 @@ pub enum Origin {
     Image(String),
 }
 
+/// 按来源隔离 payload 时，各来源根目录的父目录（相对 bundle 根）。
+pub const ORIGIN_ROOTS_DIR: &str = "payload/.origins";
+
+/// bundle 路径 `destination` 在运行期的绝对路径：去掉 `payload` 以及按来源隔离时的
+/// `.origins/<来源>` 根目录。
+pub fn payload_runtime_path(destination: &Path) -> PathBuf {
+    let relative = destination.strip_prefix("/").unwrap_or(destination);
+    let mut inside = relative.strip_prefix("payload").unwrap_or(relative);
+    if let Ok(rooted) = inside.strip_prefix(".origins") {
+        let mut components = rooted.components();
+        components.next();
+        inside = components.as_path();
+    }
+    Path::new("/").join(inside)
+}
+
+impl Origin {
+    /// 按来源隔离时该来源的 payload 根目录：宿主为 `host`，镜像为 `image-<引用>-<摘要>`，
+    /// 引用中的非法字符替换为 `_`，摘要取原始引用 SHA-256 的前 8 位，避免替换后重名。
+    pub fn payload_root(&self) -> PathBuf {
+        let name = match self {
+            Origin::Host => "host".to_string(),
+            Origin::Image(reference) => {
+                let digest = format!("{:x}", Sha256::digest(reference.as_bytes()));
+                let sanitized: String = reference
+                    .chars()
+                    .map(|c| {
+                        if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') {
+                            c
+                        } else {
+                            '_'
+                        }
+                    })
+                    .collect();
+                format!("image-{sanitized}-{}", &digest[..8])
+            }
+        };
+        Path::new(ORIGIN_ROOTS_DIR).join(name)
+    }
+}
+
 /// 单条 AUXV 数据（键值对）。
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct AuxvEntry {
@@ -161,6 +283,44 @@ impl RuntimeMetadata {
     }
 }
 
+/// AUXV 中的 `AT_HWCAP` 标签。
+pub const AT_HWCAP: u64 = 16;
+/// AUXV 中的 `AT_HWCAP2` 标签。
+pub const AT_HWCAP2: u64 = 26;
+
+/// 启动时向入口重放的 AUXV 子集，用于固定 hwcaps 目录选择与 IFUNC 分派。
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub struct AuxvProfile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub platform: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hwcap: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hwcap2: Option<u64>,
+}
+
+impl AuxvProfile {
+    /// 从运行期元数据的 AUXV 快照中提取平台与 hwcap。
+    pub fn from_metadata(metadata: &RuntimeMetadata) -> Self {
+        let lookup = |key: u64| {
+            metadata
+                .auxv
+                .iter()
+                .find(|entry| entry.key == key)
+                .map(|entry| entry.value)
+        };
+        Self {
+            platform: metadata.platform.clone(),
+            hwcap: lookup(AT_HWCAP),
+            hwcap2: lookup(AT_HWCAP2),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.platform.is_none() && self.hwcap.is_none() && self.hwcap2.is_none()
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct BundleEntry {
     pub logical: LogicalPath,
@@ -278,6 +438,8 @@ pub struct BinaryEntryPlan {
     pub linker_source: PathBuf,
     pub linker_destination: PathBuf,
     pub library_dirs: Vec<PathBuf>,
+    /// 入口闭包中各库所在目录，按加载器首次解析的顺序排列，作为该入口独享的库搜索列表。
+    pub library_search_path: Vec<PathBuf>,
     pub requires_linker: bool,
     pub origin: Origin,
     pub run_mode: Option<RunMode>,
@@ -294,6 +456,8 @@ pub struct ScriptEntryPlan {
     pub linker_destination: PathBuf,
     pub interpreter_args: Vec<String>,
     pub library_dirs: Vec<PathBuf>,
+    /// 入口闭包中各库所在目录，按加载器首次解析的顺序排列，作为该入口独享的库搜索列表。
+    pub library_search_path: Vec<PathBuf>,
     pub requires_linker: bool,
     pub origin: Origin,
     pub run_mode: Option<RunMode>,
@@ -313,6 +477,29 @@ impl EntryBundlePlan {
             EntryBundlePlan::Script(plan) => &plan.origin,
         }
     }
+
+    /// 入口所在的 payload 根目录：程序位于其来源的独立根目录下时为该目录，否则为 `payload`。
+    pub fn payload_root(&self) -> PathBuf {
+        let program = match self {
+            EntryBundlePlan::Binary(plan) => &plan.binary_destination,
+            EntryBundlePlan::Script(plan) => &plan.interpreter_destination,
+        };
+        let root = self.origin().payload_root();
+        if program.starts_with(&root) {
+            root
+        } else {
+            PathBuf::from("payload")
+        }
+    }
+
+    /// 入口的运行模式，未指定时为 Host。
+    pub fn run_mode(&self) -> RunMode {
+        match self {
+            EntryBundlePlan::Binary(plan) => plan.run_mode,
+            EntryBundlePlan::Script(plan) => plan.run_mode,
+        }
+        .unwrap_or(RunMode::Host)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -339,6 +526,8 @@ pub struct DependencyClosure {
     pub runtime_aliases: HashMap<PathBuf, Vec<PathBuf>>,
     pub symlinks: Vec<ResolvedSymlink>,
     pub metadata: HashMap<Origin, RuntimeMetadata>,
+    /// 因属于宿主驱动而未打包的库（soname），launcher 启动时从宿主补齐。
+    pub host_drivers: BTreeSet<String>,
 }
 
 impl DependencyClosure {
@@ -352,11 +541,57 @@ impl DependencyClosure {
         self
     }
 
+    /// 把闭包中的 payload 路径整体移到 `root`（相对 bundle 根）之下，用于按来源隔离 payload。
+    /// `payload/` 开头的 bundle 路径与绝对的运行期路径都会改写。
+    pub fn rebase_payload(&mut self, root: &Path) {
+        let rebase = |path: &mut PathBuf| *path = rebase_payload_path(path, root);
+        for file in &mut self.files {
+            rebase(&mut file.destination);
+        }
+        for link in &mut self.symlinks {
+            rebase(&mut link.destination);
+            rebase(&mut link.bundle_target);
+        }
+        for aliases in self.runtime_aliases.values_mut() {
+            aliases.iter_mut().for_each(rebase);
+        }
+        for plan in &mut self.entry_plans {
+            match plan {
+                EntryBundlePlan::Binary(plan) => {
+                    rebase(&mut plan.binary_destination);
+                    rebase(&mut plan.linker_destination);
+                    plan.library_dirs.iter_mut().for_each(rebase);
+                    plan.library_search_path.iter_mut().for_each(rebase);
+                }
+                EntryBundlePlan::Script(plan) => {
+                    rebase(&mut plan.script_destination);
+                    rebase(&mut plan.interpreter_destination);
+                    rebase(&mut plan.linker_destination);
+                    plan.library_dirs.iter_mut().for_each(rebase);
+                    plan.library_search_path.iter_mut().for_each(rebase);
+                }
+            }
+        }
+    }
+
     pub fn merge(&mut self, other: DependencyClosure) -> MergeReport {
-        let mut by_destination: HashMap<PathBuf, String> = self
+        self.merge_with(other, &MergePolicy::default(), None)
+    }
+
+    /// 合并来自 `origin` 的 `other`，同一路径内容不同的文件、指向内容不同文件的别名（如 soname
+    /// 路径）以及目标不同的符号链接按 `policy` 取舍，每个冲突的决定记入报告。`origin` 为 `None`
+    /// （如 `--copy-dir`）时 `prefer-origin` 不会选中新文件。
+    pub fn merge_with(
+        &mut self,
+        other: DependencyClosure,
+        policy: &MergePolicy,
+        origin: Option<&Origin>,
+    ) -> MergeReport {
+        let mut by_destination: HashMap<PathBuf, usize> = self
             .files
             .iter()
-            .map(|file| (file.destination.clone(), file.digest.clone()))
+            .enumerate()
+            .map(|(index, file)| (file.destination.clone(), index))
             .collect();
         let mut seen_entry_names: HashSet<String> = self
             .entry_plans
@@ -374,24 +609,49 @@ impl DependencyClosure {
             .map(|link| (link.destination.clone(), link.bundle_target.clone()))
             .collect();
 
+        let existing_by_source: HashMap<PathBuf, ResolvedFile> = self
+            .files
+            .iter()
+            .map(|file| (file.source.clone(), file.clone()))
+            .collect();
+        let incoming_by_source: HashMap<PathBuf, ResolvedFile> = other
+            .files
+            .iter()
+            .map(|file| (file.source.clone(), file.clone()))
+            .collect();
+        let mut alias_owners: HashMap<PathBuf, PathBuf> = HashMap::new();
+        for (source, aliases) in &self.runtime_aliases {
+            if let Some(file) = existing_by_source.get(source) {
+                for alias in aliases {
+                    alias_owners
+                        .insert(alias_destination(alias, &file.destination), source.clone());
+                }
+            }
+        }
+
         let mut report = MergeReport::default();
 
         for file in other.files {
             match by_destination.get(&file.destination) {
-                Some(existing_digest) if existing_digest == &file.digest => {
+                Some(&index) if self.files[index].digest == file.digest => {
                     report.reused_files += 1;
                 }
-                Some(existing_digest) => {
-                    report.conflicts.push(MergeConflict {
-                        destination: file.destination.clone(),
-                        existing_digest: existing_digest.clone(),
-                        incoming_digest: file.digest.clone(),
-                        incoming_source: file.source.clone(),
-                    });
-                    continue;
+                Some(&index) => {
+                    let conflict = MergeConflict::resolve(
+                        &file.destination,
+                        &self.files[index],
+                        &file,
+                        policy,
+                        origin,
+                    );
+                    let resolution = conflict.resolution;
+                    report.conflicts.push(conflict);
+                    if resolution == ConflictResolution::TookIncoming {
+                        self.files[index] = file;
+                    }
                 }
                 None => {
-                    by_destination.insert(file.destination.clone(), file.digest.clone());
+                    by_destination.insert(file.destination.clone(), self.files.len());
                     report.added_files += 1;
                     self.files.push(file);
                 }
@@ -417,7 +677,30 @@ impl DependencyClosure {
         for link in other.symlinks {
             match symlink_map.get(&link.destination) {
                 Some(existing_target) if existing_target == &link.bundle_target => {}
-                Some(_) => continue,
+                Some(existing_target) => {
+                    // 符号链接没有内容摘要，以链接目标作为双方的来源比较。
+                    let existing = ResolvedFile::new(existing_target, &link.destination, "");
+                    let incoming = ResolvedFile::new(&link.bundle_target, &link.destination, "");
+                    let conflict = MergeConflict::resolve(
+                        &link.destination,
+                        &existing,
+                        &incoming,
+                        policy,
+                        origin,
+                    );
+                    let resolution = conflict.resolution;
+                    report.conflicts.push(conflict);
+                    if resolution == ConflictResolution::TookIncoming {
+                        symlink_map.insert(link.destination.clone(), link.bundle_target.clone());
+                        if let Some(slot) = self
+                            .symlinks
+                            .iter_mut()
+                            .find(|existing| existing.destination == link.destination)
+                        {
+                            *slot = link;
+                        }
+                    }
+                }
                 None => {
                     symlink_map.insert(link.destination.clone(), link.bundle_target.clone());
                     self.symlinks.push(link);
@@ -426,17 +709,77 @@ impl DependencyClosure {
         }
 
         for (source, aliases) in other.runtime_aliases {
-            let entry = self.runtime_aliases.entry(source).or_default();
-            entry.extend(aliases);
+            let mut kept = Vec::new();
+            for alias in aliases {
+                let Some(incoming) = incoming_by_source.get(&source) else {
+                    kept.push(alias);
+                    continue;
+                };
+                let destination = alias_destination(&alias, &incoming.destination);
+                let existing = alias_owners
+                    .get(&destination)
+                    .filter(|owner| **owner != source)
+                    .and_then(|owner| existing_by_source.get(owner));
+                if let Some(existing) =
+                    existing.filter(|existing| existing.digest != incoming.digest)
+                {
+                    let conflict =
+                        MergeConflict::resolve(&destination, existing, incoming, policy, origin);
+                    let resolution = conflict.resolution;
+                    report.conflicts.push(conflict);
+                    if resolution != ConflictResolution::TookIncoming {
+                        continue;
+                    }
+                    if let Some(owned) = self.runtime_aliases.get_mut(&existing.source) {
+                        owned.retain(|alias| {
+                            alias_destination(alias, &existing.destination) != destination
+                        });
+                    }
+                }
+                alias_owners.insert(destination, source.clone());
+                kept.push(alias);
+            }
+            if !kept.is_empty() {
+                self.runtime_aliases.entry(source).or_default().extend(kept);
+            }
         }
         for (origin, snapshot) in other.metadata {
             self.metadata.entry(origin).or_insert(snapshot);
         }
+        self.host_drivers.extend(other.host_drivers);
 
         report
     }
 }
 
+/// 别名在 bundle 中的路径：`payload/` 开头的别名即 bundle 路径，绝对的运行期路径落在文件
+/// `file_destination` 所在的 payload 根（与打包时一致）。
+fn alias_destination(alias: &Path, file_destination: &Path) -> PathBuf {
+    let relative = alias.strip_prefix("/").unwrap_or(alias);
+    let inside = relative.strip_prefix("payload").unwrap_or(relative);
+    if !alias.is_absolute() {
+        return Path::new("payload").join(inside);
+    }
+    let root = match file_destination
+        .strip_prefix(ORIGIN_ROOTS_DIR)
+        .ok()
+        .and_then(|rest| rest.components().next())
+    {
+        Some(name) => Path::new(ORIGIN_ROOTS_DIR).join(name),
+        None => PathBuf::from("payload"),
+    };
+    root.join(inside)
+}
+
+fn rebase_payload_path(path: &Path, root: &Path) -> PathBuf {
+    let relative = path.strip_prefix("/").unwrap_or(path);
+    match relative.strip_prefix("payload") {
+        Ok(rest) => root.join(rest),
+        Err(_) if path.is_absolute() => root.join(relative),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct TraceAccess(u8);
 
@@ -487,12 +830,50 @@ pub struct MergeReport {
     pub conflicts: Vec<MergeConflict>,
 }
 
+impl MergeReport {
+    /// 策略为 `error` 而被拒绝的冲突。
+    pub fn rejected(&self) -> impl Iterator<Item = &MergeConflict> {
+        self.conflicts
+            .iter()
+            .filter(|conflict| conflict.resolution == ConflictResolution::Rejected)
+    }
+}
+
+/// 同一 bundle 路径上的冲突。别名冲突的来源为别名指向的文件；符号链接冲突的来源为链接目标，
+/// digest 为空。
 #[derive(Debug, Clone)]
 pub struct MergeConflict {
     pub destination: PathBuf,
     pub existing_digest: String,
+    pub existing_source: PathBuf,
     pub incoming_digest: String,
     pub incoming_source: PathBuf,
+    /// 该路径适用的策略。
+    pub policy: ConflictPolicy,
+    /// 按策略做出的决定。
+    pub resolution: ConflictResolution,
+}
+
+impl MergeConflict {
+    /// 按 `destination` 适用的策略在 `existing` 与来自 `origin` 的 `incoming` 之间做选择。
+    fn resolve(
+        destination: &Path,
+        existing: &ResolvedFile,
+        incoming: &ResolvedFile,
+        policy: &MergePolicy,
+        origin: Option<&Origin>,
+    ) -> Self {
+        let conflict_policy = policy.policy_for(destination);
+        Self {
+            destination: destination.to_path_buf(),
+            existing_digest: existing.digest.clone(),
+            existing_source: existing.source.clone(),
+            incoming_digest: incoming.digest.clone(),
+            incoming_source: incoming.source.clone(),
+            policy: conflict_policy.clone(),
+            resolution: MergePolicy::resolve(conflict_policy, existing, incoming, origin),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -511,6 +892,29 @@ mod tests {
         assert!(err.to_string().contains("unsupported"));
     }
 
+    #[test]
+    fn auxv_profile_extracts_platform_and_hwcaps() {
+        let metadata = RuntimeMetadata {
+            auxv: vec![
+                AuxvEntry {
+                    key: 6,
+                    value: 4096,
+                },
+                AuxvEntry {
+                    key: AT_HWCAP,
+                    value: 0xbfebfbff,
+                },
+            ],
+            platform: Some("x86_64".into()),
+            ..RuntimeMetadata::default()
+        };
+        let profile = AuxvProfile::from_metadata(&metadata);
+        assert_eq!(profile.platform.as_deref(), Some("x86_64"));
+        assert_eq!(profile.hwcap, Some(0xbfebfbff));
+        assert_eq!(profile.hwcap2, None);
+        assert!(AuxvProfile::from_metadata(&RuntimeMetadata::default()).is_empty());
+    }
+
     #[test]
     fn merge_deduplicates_files_by_hash() {
         let mut base = DependencyClosure {
@@ -525,6 +929,7 @@ mod tests {
             runtime_aliases: HashMap::new(),
             symlinks: Vec::new(),
             metadata: HashMap::new(),
+            host_drivers: BTreeSet::new(),
         };
 
         let other = DependencyClosure {
@@ -550,6 +955,7 @@ mod tests {
             runtime_aliases: HashMap::new(),
             symlinks: Vec::new(),
             metadata: HashMap::new(),
+            host_drivers: BTreeSet::new(),
         };
 
         let report = base.merge(other);
@@ -574,6 +980,7 @@ mod tests {
             runtime_aliases: HashMap::new(),
             symlinks: Vec::new(),
             metadata: HashMap::new(),
+            host_drivers: BTreeSet::new(),
         };
 
         let other = DependencyClosure {
@@ -587,10 +994,258 @@ mod tests {
             runtime_aliases: HashMap::new(),
             symlinks: Vec::new(),
             metadata: HashMap::new(),
+            host_drivers: BTreeSet::new(),
         };
 
         let report = base.merge(other);
         assert_eq!(report.conflicts.len(), 1);
+        assert_eq!(report.conflicts[0].policy, ConflictPolicy::PreferFirst);
+        assert_eq!(
+            report.conflicts[0].resolution,
+            ConflictResolution::KeptExisting
+        );
         assert_eq!(base.files.len(), 1);
+        assert_eq!(base.files[0].digest, "digest-foo");
+    }
+
+    fn conflicting_closures() -> (DependencyClosure, DependencyClosure) {
+        let closure = |source: &str, digest: &str| DependencyClosure {
+            files: vec![
+                ResolvedFile::new(source, "payload/bin/foo", digest),
+                ResolvedFile::new(source, "payload/etc/foo.conf", digest),
+            ],
+            ..DependencyClosure::default()
+        };
+        (
+            closure("/bin/foo", "digest-first"),
+            closure("/img/bin/foo", "digest-last"),
+        )
+    }
+
+    #[test]
+    fn merge_with_takes_incoming_in_place() {
+        let (mut base, other) = conflicting_closures();
+        let report = base.merge_with(
+            other,
+            &MergePolicy::new(ConflictPolicy::PreferLast),
+            Some(&Origin::Image("app".into())),
+        );
+        assert_eq!(report.conflicts.len(), 2);
+        assert!(report.conflicts.iter().all(|conflict| {
+            conflict.resolution == ConflictResolution::TookIncoming
+                && conflict.existing_digest == "digest-first"
+        }));
+        let files: Vec<(&Path, &str)> = base
+            .files
+            .iter()
+            .map(|file| (file.destination.as_path(), file.digest.as_str()))
+            .collect();
+        assert_eq!(
+            files,
+            [
+                (Path::new("payload/bin/foo"), "digest-last"),
+                (Path::new("payload/etc/foo.conf"), "digest-last"),
+            ]
+        );
+    }
+
+    #[test]
+    fn merge_with_rejection_keeps_existing_file() {
+        let (mut base, other) = conflicting_closures();
+        let report = base.merge_with(other, &MergePolicy::new(ConflictPolicy::Error), None);
+        assert_eq!(report.rejected().count(), 2);
+        assert_eq!(base.files.len(), 2);
+        assert!(base.files.iter().all(|file| file.digest == "digest-first"));
+    }
+
+    #[test]
+    fn merge_with_rules_take_precedence_over_default() {
+        let (mut base, other) = conflicting_closures();
+        let policy = MergePolicy::new(ConflictPolicy::Error)
+            .with_rule("/etc/*", ConflictPolicy::PreferOrigin("app".into()));
+        let app = Origin::Image("app".into());
+        let report = base.merge_with(other.clone(), &policy, Some(&app));
+        let rejected: Vec<&Path> = report
+            .rejected()
+            .map(|conflict| conflict.destination.as_path())
+            .collect();
+        assert_eq!(rejected, [Path::new("payload/bin/foo")]);
+        assert_eq!(base.files[0].digest, "digest-first");
+        assert_eq!(base.files[1].digest, "digest-last");
+
+        // Without an origin the preferred image cannot be recognised.
+        let (mut base, _) = conflicting_closures();
+        base.merge_with(other, &policy, None);
+        assert_eq!(base.files[1].digest, "digest-first");
+    }
+
+    #[test]
+    fn merge_with_resolves_soname_alias_collisions() {
+        // Libraries live at their canonical names; the soname path is only an alias.
+        let closure = |source: &str, version: &str| DependencyClosure {
+            files: vec![ResolvedFile::new(
+                source,
+                format!("payload/usr/lib/libz.so.{version}"),
+                format!("digest-{version}"),
+            )],
+            runtime_aliases: HashMap::from([(
+                PathBuf::from(source),
+                vec![PathBuf::from("/usr/lib/libz.so.1")],
+            )]),
+            ..DependencyClosure::default()
+        };
+        let old = "/usr/lib/libz.so.1.2.11";
+        let new = "/img/usr/lib/libz.so.1.2.13";
+        let image = Origin::Image("app".into());
+
+        let mut base = closure(old, "1.2.11");
+        let report = base.merge_with(
+            closure(new, "1.2.13"),
+            &MergePolicy::new(ConflictPolicy::PreferNewerSonameVersion),
+            Some(&image),
+        );
+        assert_eq!(report.conflicts.len(), 1);
+        let conflict = &report.conflicts[0];
+        assert_eq!(conflict.destination, Path::new("payload/usr/lib/libz.so.1"));
+        assert_eq!(conflict.resolution, ConflictResolution::TookIncoming);
+        assert_eq!(conflict.existing_source, Path::new(old));
+        assert!(base.runtime_aliases[Path::new(old)].is_empty());
+        assert_eq!(
+            base.runtime_aliases[Path::new(new)],
+            [PathBuf::from("/usr/lib/libz.so.1")]
+        );
+
+        let mut base = closure(new, "1.2.13");
+        let report = base.merge_with(
+            closure(old, "1.2.11"),
+            &MergePolicy::new(ConflictPolicy::PreferNewerSonameVersion),
+            Some(&image),
+        );
+        assert_eq!(
+            report.conflicts[0].resolution,
+            ConflictResolution::KeptExisting
+        );
+        assert!(!base.runtime_aliases.contains_key(Path::new(old)));
+
+        let mut base = closure(old, "1.2.11");
+        let report = base.merge_with(
+            closure(new, "1.2.13"),
+            &MergePolicy::new(ConflictPolicy::Error),
+            Some(&image),
+        );
+        assert_eq!(report.rejected().count(), 1);
+
+        // Each origin root keeps its own soname path.
+        let mut base = closure(old, "1.2.11");
+        base.rebase_payload(&Origin::Host.payload_root());
+        let mut other = closure(new, "1.2.13");
+        other.rebase_payload(&image.payload_root());
+        let report = base.merge_with(other, &MergePolicy::default(), Some(&image));
+        assert!(report.conflicts.is_empty());
+    }
+
+    #[test]
+    fn merge_with_resolves_symlink_collisions() {
+        let closure = |target: &str| DependencyClosure {
+            symlinks: vec![ResolvedSymlink::new("payload/usr/lib/libssl.so", target)],
+            ..DependencyClosure::default()
+        };
+        let mut base = closure("payload/usr/lib/libssl.so.1.1");
+        let report = base.merge_with(
+            closure("payload/usr/lib/libssl.so.3"),
+            &MergePolicy::new(ConflictPolicy::PreferNewerSonameVersion),
+            None,
+        );
+        assert_eq!(report.conflicts.len(), 1);
+        assert_eq!(
+            report.conflicts[0].resolution,
+            ConflictResolution::TookIncoming
+        );
+        assert_eq!(base.symlinks.len(), 1);
+        assert_eq!(
+            base.symlinks[0].bundle_target,
+            Path::new("payload/usr/lib/libssl.so.3")
+        );
+
+        let mut base = closure("payload/usr/lib/libssl.so.1.1");
+        let report = base.merge(closure("payload/usr/lib/libssl.so.3"));
+        assert_eq!(
+            report.conflicts[0].resolution,
+            ConflictResolution::KeptExisting
+        );
+        assert_eq!(
+            base.symlinks[0].bundle_target,
+            Path::new("payload/usr/lib/libssl.so.1.1")
+        );
+    }
+
+    #[test]
+    fn origin_roots_keep_conflicting_files_apart() {
+        let image = Origin::Image("docker.io/library/python:3.12".into());
+        let image_root = image.payload_root();
+        assert_eq!(
+            image_root,
+            Path::new("payload/.origins/image-docker.io_library_python_3.12-9f39de2c")
+        );
+        assert_ne!(
+            Origin::Image("reg/app:1".into()).payload_root(),
+            Origin::Image("reg/app_1".into()).payload_root()
+        );
+        let plan = |origin: &Origin| {
+            EntryBundlePlan::Binary(BinaryEntryPlan {
+                display_name: "python3".into(),
+                binary_source: PathBuf::from("/usr/bin/python3"),
+                binary_destination: PathBuf::from("payload/usr/bin/python3"),
+                linker_source: PathBuf::from("/lib64/ld-linux-x86-64.so.2"),
+                linker_destination: PathBuf::from("payload/lib64/ld-linux-x86-64.so.2"),
+                library_dirs: vec![PathBuf::from("payload/usr/lib")],
+                library_search_path: vec![PathBuf::from("payload/usr/lib")],
+                requires_linker: true,
+                origin: origin.clone(),
+                run_mode: None,
+            })
+        };
+        let closure = |origin: &Origin, digest: &str| DependencyClosure {
+            files: vec![ResolvedFile::new(
+                "/usr/bin/python3",
+                "payload/usr/bin/python3",
+                digest,
+            )],
+            entry_plans: vec![plan(origin)],
+            runtime_aliases: HashMap::from([(
+                PathBuf::from("/usr/lib/libc.so.6"),
+                vec![PathBuf::from("/lib/libc.so.6")],
+            )]),
+            symlinks: vec![ResolvedSymlink::new("payload/lib64", "payload/usr/lib64")],
+            ..DependencyClosure::default()
+        };
+
+        let mut base = closure(&Origin::Host, "digest-host");
+        base.rebase_payload(&Origin::Host.payload_root());
+        let mut other = closure(&image, "digest-image");
+        other.rebase_payload(&image_root);
+        let EntryBundlePlan::Binary(rebased) = &other.entry_plans[0] else {
+            unreachable!();
+        };
+        assert_eq!(
+            rebased.linker_destination,
+            image_root.join("lib64/ld-linux-x86-64.so.2")
+        );
+        assert_eq!(rebased.library_search_path, [image_root.join("usr/lib")]);
+        assert_eq!(other.entry_plans[0].payload_root(), image_root);
+        assert_eq!(
+            other.runtime_aliases[Path::new("/usr/lib/libc.so.6")],
+            [image_root.join("lib/libc.so.6")]
+        );
+        assert_eq!(
+            other.symlinks[0].bundle_target,
+            image_root.join("usr/lib64")
+        );
+
+        let report = base.merge(other);
+        assert!(report.conflicts.is_empty());
+        assert_eq!(report.added_files, 1);
+        assert_eq!(base.files.len(), 2);
+        assert_eq!(plan(&image).payload_root(), Path::new("payload"));
     }
 }
@@ -0,0 +1,237 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::{glob_match, payload_runtime_path, Origin, ResolvedFile};
+
+/// 合并闭包时，同一 bundle 路径上内容不同的两个文件（冲突）的处理方式。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// 记为拒绝，由调用方让构建失败。
+    Error,
+    /// 保留先合并的文件。
+    #[default]
+    PreferFirst,
+    /// 改用后合并的文件。
+    PreferLast,
+    /// 保留来自该来源（`host` 或镜像引用）的文件；双方都不是时保留先合并的。
+    PreferOrigin(String),
+    /// 共享库取文件名中版本号较新的一方；非共享库或版本无法比较时保留先合并的。
+    PreferNewerSonameVersion,
+}
+
+impl FromStr for ConflictPolicy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if let Some(origin) = value.strip_prefix("prefer-origin=") {
+            if origin.is_empty() {
+                return Err("prefer-origin needs an origin (`host` or an image reference)".into());
+            }
+            return Ok(Self::PreferOrigin(origin.to_string()));
+        }
+        match value {
+            "error" => Ok(Self::Error),
+            "prefer-first" => Ok(Self::PreferFirst),
+            "prefer-last" => Ok(Self::PreferLast),
+            "prefer-newer-soname-version" => Ok(Self::PreferNewerSonameVersion),
+            other => Err(format!(
+                "unknown conflict policy `{other}` (expected error|prefer-first|prefer-last|\
+                 prefer-origin=<ref>|prefer-newer-soname-version)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ConflictPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Error => f.write_str("error"),
+            Self::PreferFirst => f.write_str("prefer-first"),
+            Self::PreferLast => f.write_str("prefer-last"),
+            Self::PreferOrigin(origin) => write!(f, "prefer-origin={origin}"),
+            Self::PreferNewerSonameVersion => f.write_str("prefer-newer-soname-version"),
+        }
+    }
+}
+
+/// 冲突的处理结果。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictResolution {
+    /// 保留已有文件，丢弃新合并的。
+    KeptExisting,
+    /// 新合并的文件替换了已有文件。
+    TookIncoming,
+    /// 策略为 `error`：保留已有文件，调用方应让构建失败。
+    Rejected,
+}
+
+impl fmt::Display for ConflictResolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::KeptExisting => "kept-existing",
+            Self::TookIncoming => "took-incoming",
+            Self::Rejected => "rejected",
+        };
+        f.write_str(name)
+    }
+}
+
+/// 合并冲突策略：全局默认值加按路径通配匹配的规则，首个匹配的规则优先。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MergePolicy {
+    default: ConflictPolicy,
+    rules: Vec<(String, ConflictPolicy)>,
+}
+
+impl MergePolicy {
+    pub fn new(default: ConflictPolicy) -> Self {
+        Self {
+            default,
+            rules: Vec::new(),
+        }
+    }
+
+    /// 对运行期路径匹配 `pattern`（如 `/usr/lib/*`，`*` 可跨目录）的冲突使用 `policy`。
+    pub fn with_rule(mut self, pattern: impl Into<String>, policy: ConflictPolicy) -> Self {
+        self.rules.push((pattern.into(), policy));
+        self
+    }
+
+    /// bundle 路径 `destination` 适用的策略。
+    pub fn policy_for(&self, destination: &Path) -> &ConflictPolicy {
+        let runtime = payload_runtime_path(destination);
+        let runtime = runtime.to_string_lossy();
+        let runtime = runtime.trim_start_matches('/');
+        self.rules
+            .iter()
+            .find(|(pattern, _)| glob_match(pattern.trim_start_matches('/'), runtime))
+            .map_or(&self.default, |(_, policy)| policy)
+    }
+
+    /// 按 `policy` 在已有文件与来自 `origin` 的新文件之间做选择。
+    pub(crate) fn resolve(
+        policy: &ConflictPolicy,
+        existing: &ResolvedFile,
+        incoming: &ResolvedFile,
+        origin: Option<&Origin>,
+    ) -> ConflictResolution {
+        let take_incoming = match policy {
+            ConflictPolicy::Error => return ConflictResolution::Rejected,
+            ConflictPolicy::PreferFirst => false,
+            ConflictPolicy::PreferLast => true,
+            ConflictPolicy::PreferOrigin(reference) => {
+                origin.is_some_and(|origin| origin_matches(origin, reference))
+            }
+            ConflictPolicy::PreferNewerSonameVersion => {
+                match (
+                    soname_version(&existing.source),
+                    soname_version(&incoming.source),
+                ) {
+                    (Some(old), Some(new)) => new.cmp(&old) == Ordering::Greater,
+                    _ => false,
+                }
+            }
+        };
+        if take_incoming {
+            ConflictResolution::TookIncoming
+        } else {
+            ConflictResolution::KeptExisting
+        }
+    }
+}
+
+fn origin_matches(origin: &Origin, reference: &str) -> bool {
+    match origin {
+        Origin::Host => reference == "host",
+        Origin::Image(image) => image == reference,
+    }
+}
+
+/// 共享库文件名中的版本号：`libfoo.so.1.2.3` 为 `[1, 2, 3]`，`libc-2.31.so` 为 `[2, 31]`。
+fn soname_version(path: &Path) -> Option<Vec<u64>> {
+    let name = path.file_name()?.to_str()?;
+    let version = match name.split_once(".so.") {
+        Some((_, version)) => version,
+        None => name.strip_suffix(".so")?.rsplit_once('-')?.1,
+    };
+    version.split('.').map(|part| part.parse().ok()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policies_round_trip() {
+        for value in [
+            "error",
+            "prefer-first",
+            "prefer-last",
+            "prefer-origin=alpine:3.20",
+            "prefer-newer-soname-version",
+        ] {
+            assert_eq!(value.parse::<ConflictPolicy>().unwrap().to_string(), value);
+        }
+        assert!("prefer-origin=".parse::<ConflictPolicy>().is_err());
+        assert!("newest".parse::<ConflictPolicy>().is_err());
+    }
+
+    #[test]
+    fn first_matching_rule_applies() {
+        let policy = MergePolicy::new(ConflictPolicy::Error)
+            .with_rule("/usr/lib/*", ConflictPolicy::PreferNewerSonameVersion)
+            .with_rule("*", ConflictPolicy::PreferLast);
+        assert_eq!(
+            policy.policy_for(Path::new("payload/usr/lib/x86_64/libz.so.1")),
+            &ConflictPolicy::PreferNewerSonameVersion
+        );
+        assert_eq!(
+            policy.policy_for(Path::new("payload/.origins/host/usr/lib/libz.so.1")),
+            &ConflictPolicy::PreferNewerSonameVersion
+        );
+        assert_eq!(
+            policy.policy_for(Path::new("payload/etc/hosts")),
+            &ConflictPolicy::PreferLast
+        );
+        assert_eq!(
+            MergePolicy::default().policy_for(Path::new("payload/etc/hosts")),
+            &ConflictPolicy::PreferFirst
+        );
+    }
+
+    #[test]
+    fn policies_resolve_conflicts() {
+        let old = ResolvedFile::new("/usr/lib/libstdc++.so.6.0.28", "payload/usr/lib/x", "a");
+        let new = ResolvedFile::new("/img/usr/lib/libstdc++.so.6.0.30", "payload/usr/lib/x", "b");
+        let glibc = ResolvedFile::new("/img/lib/libc-2.31.so", "payload/lib/libc.so.6", "c");
+        let resolve = MergePolicy::resolve;
+        let by_version = &ConflictPolicy::PreferNewerSonameVersion;
+        assert_eq!(
+            resolve(by_version, &old, &new, None),
+            ConflictResolution::TookIncoming
+        );
+        assert_eq!(
+            resolve(by_version, &new, &old, None),
+            ConflictResolution::KeptExisting
+        );
+        assert_eq!(soname_version(&glibc.source), Some(vec![2, 31]));
+        assert_eq!(soname_version(Path::new("/usr/bin/python3")), None);
+
+        let image = Origin::Image("alpine:3.20".into());
+        let prefer = ConflictPolicy::PreferOrigin("alpine:3.20".into());
+        assert_eq!(
+            resolve(&prefer, &old, &new, Some(&image)),
+            ConflictResolution::TookIncoming
+        );
+        assert_eq!(
+            resolve(&prefer, &old, &new, Some(&Origin::Host)),
+            ConflictResolution::KeptExisting
+        );
+        assert_eq!(
+            resolve(&ConflictPolicy::Error, &old, &new, None),
+            ConflictResolution::Rejected
+        );
+    }
+}
@@ -0,0 +1,280 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// 可在 seccomp 拒绝列表中使用的系统调用；launcher 在目标架构上查不到的名字会被跳过。
+pub const SECCOMP_SYSCALLS: &[&str] = &[
+    "acct",
+    "add_key",
+    "adjtimex",
+    "bpf",
+    "chroot",
+    "clock_adjtime",
+    "clock_settime",
+    "delete_module",
+    "fanotify_init",
+    "finit_module",
+    "init_module",
+    "io_uring_enter",
+    "io_uring_register",
+    "io_uring_setup",
+    "ioperm",
+    "iopl",
+    "kexec_file_load",
+    "kexec_load",
+    "keyctl",
+    "mount",
+    "move_mount",
+    "name_to_handle_at",
+    "open_by_handle_at",
+    "open_tree",
+    "perf_event_open",
+    "personality",
+    "pivot_root",
+    "process_vm_readv",
+    "process_vm_writev",
+    "ptrace",
+    "quotactl",
+    "reboot",
+    "request_key",
+    "setdomainname",
+    "sethostname",
+    "setns",
+    "settimeofday",
+    "swapoff",
+    "swapon",
+    "syslog",
+    "umount2",
+    "unshare",
+    "userfaultfd",
+];
+
+/// launcher 可设置的资源限制。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RlimitResource {
+    /// `RLIMIT_NOFILE`
+    Nofile,
+    /// `RLIMIT_AS`
+    As,
+    /// `RLIMIT_CORE`
+    Core,
+}
+
+impl fmt::Display for RlimitResource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            RlimitResource::Nofile => "nofile",
+            RlimitResource::As => "as",
+            RlimitResource::Core => "core",
+        })
+    }
+}
+
+/// 一条资源限制，`NAME=SOFT[:HARD]`；值可带 `K`/`M`/`G`/`T` 后缀或为 `unlimited`（`None`），
+/// 省略 HARD 时与 SOFT 相同。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rlimit {
+    pub resource: RlimitResource,
+    pub soft: Option<u64>,
+    pub hard: Option<u64>,
+}
+
+impl FromStr for Rlimit {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (name, limits) = value
+            .split_once('=')
+            .ok_or_else(|| format!("expected NAME=SOFT[:HARD], got `{value}`"))?;
+        let resource = match name.trim().to_ascii_lowercase().as_str() {
+            "nofile" => RlimitResource::Nofile,
+            "as" => RlimitResource::As,
+            "core" => RlimitResource::Core,
+            other => {
+                return Err(format!(
+                    "unknown rlimit `{other}` (expected nofile|as|core)"
+                ))
+            }
+        };
+        let (soft, hard) = match limits.split_once(':') {
+            Some((soft, hard)) => (parse_limit(soft)?, parse_limit(hard)?),
+            None => {
+                let limit = parse_limit(limits)?;
+                (limit, limit)
+            }
+        };
+        let exceeds = match (soft, hard) {
+            (Some(soft), Some(hard)) => soft > hard,
+            (None, Some(_)) => true,
+            _ => false,
+        };
+        if exceeds {
+            return Err(format!("soft limit exceeds hard limit in `{value}`"));
+        }
+        Ok(Self {
+            resource,
+            soft,
+            hard,
+        })
+    }
+}
+
+fn parse_limit(value: &str) -> Result<Option<u64>, String> {
+    let value = value.trim();
+    if matches!(value, "unlimited" | "infinity") {
+        return Ok(None);
+    }
+    let (digits, shift) = match value.as_bytes().last() {
+        Some(b'K' | b'k') => (&value[..value.len() - 1], 10),
+        Some(b'M' | b'm') => (&value[..value.len() - 1], 20),
+        Some(b'G' | b'g') => (&value[..value.len() - 1], 30),
+        Some(b'T' | b't') => (&value[..value.len() - 1], 40),
+        _ => (value, 0),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|base| base.checked_mul(1 << shift))
+        .map(Some)
+        .ok_or_else(|| format!("invalid limit `{value}`"))
+}
+
+/// I/O 调度类别。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IoClass {
+    Realtime,
+    BestEffort,
+    Idle,
+}
+
+/// ionice 设置，`CLASS[:LEVEL]`，LEVEL 为 0（最高）到 7，默认 4；`idle` 不使用 LEVEL。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IoNice {
+    pub class: IoClass,
+    pub level: u8,
+}
+
+impl FromStr for IoNice {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (class, level) = match value.split_once(':') {
+            Some((class, level)) => (class, Some(level)),
+            None => (value, None),
+        };
+        let class = match class.trim() {
+            "realtime" | "rt" => IoClass::Realtime,
+            "best-effort" | "best_effort" | "be" => IoClass::BestEffort,
+            "idle" => IoClass::Idle,
+            other => {
+                return Err(format!(
+                    "unknown ionice class `{other}` (expected realtime|best-effort|idle)"
+                ))
+            }
+        };
+        let level = match level {
+            Some(level) => level
+                .trim()
+                .parse::<u8>()
+                .ok()
+                .filter(|level| *level <= 7)
+                .ok_or_else(|| format!("ionice level must be 0-7, got `{level}`"))?,
+            None => 4,
+        };
+        Ok(Self { class, level })
+    }
+}
+
+/// launcher 在 exec 入口前对自身进程应用的约束，所有运行模式通用。
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProcessPolicy {
+    /// 资源限制，按顺序设置。
+    pub rlimits: Vec<Rlimit>,
+    /// 设置 `PR_SET_NO_NEW_PRIVS`；配置 seccomp 拒绝列表时总是设置。
+    pub no_new_privs: bool,
+    /// 文件创建掩码，配置中写作八进制字符串。
+    #[serde(with = "octal", skip_serializing_if = "Option::is_none")]
+    pub umask: Option<u32>,
+    /// nice 值（-20 到 19）；提高优先级失败时只给出警告。
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nice: Option<i32>,
+    /// I/O 优先级；设置失败时只给出警告。
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ionice: Option<IoNice>,
+    /// 调用时返回 `EPERM` 的系统调用，取值见 [`SECCOMP_SYSCALLS`]。
+    pub deny_syscalls: Vec<String>,
+}
+
+impl ProcessPolicy {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// 解析八进制 umask，如 `022` 或 `0o077`。
+pub fn parse_umask(value: &str) -> Result<u32, String> {
+    let digits = value.trim().trim_start_matches("0o");
+    u32::from_str_radix(digits, 8)
+        .ok()
+        .filter(|mask| *mask <= 0o777)
+        .ok_or_else(|| format!("umask must be an octal mode up to 0777, got `{value}`"))
+}
+
+mod octal {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(value: &Option<u32>, serializer: S) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(mask) => serializer.serialize_str(&format!("{mask:04o}")),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<u32>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|value| parse_umask(&value).map_err(serde::de::Error::custom))
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rlimit_and_ionice_specs_parse() {
+        let limit: Rlimit = "nofile=1024:4096".parse().unwrap();
+        assert_eq!(limit.resource, RlimitResource::Nofile);
+        assert_eq!((limit.soft, limit.hard), (Some(1024), Some(4096)));
+        let limit: Rlimit = "as=4G".parse().unwrap();
+        assert_eq!((limit.soft, limit.hard), (Some(4 << 30), Some(4 << 30)));
+        let limit: Rlimit = "core=0:unlimited".parse().unwrap();
+        assert_eq!((limit.soft, limit.hard), (Some(0), None));
+        assert!("nofile=8:4".parse::<Rlimit>().is_err());
+        assert!("stack=8M".parse::<Rlimit>().is_err());
+
+        let ionice: IoNice = "best-effort:7".parse().unwrap();
+        assert_eq!((ionice.class, ionice.level), (IoClass::BestEffort, 7));
+        assert_eq!("idle".parse::<IoNice>().unwrap().class, IoClass::Idle);
+        assert!("realtime:9".parse::<IoNice>().is_err());
+    }
+
+    #[test]
+    fn umask_round_trips_as_octal_string() {
+        let policy = ProcessPolicy {
+            umask: Some(parse_umask("027").unwrap()),
+            ..ProcessPolicy::default()
+        };
+        let json = serde_json::to_value(&policy).unwrap();
+        assert_eq!(json["umask"], "0027");
+        let back: ProcessPolicy = serde_json::from_value(json).unwrap();
+        assert_eq!(back.umask, Some(0o027));
+        assert!(parse_umask("0999").is_err());
+    }
+}
@@ -0,0 +1,97 @@
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// 运行时改写状态目录的环境变量（`SIDEBUNDLE_STATE_DIR=/abs/path`）。
+pub const STATE_DIR_ENV: &str = "SIDEBUNDLE_STATE_DIR";
+
+/// 隔离模式（bwrap/chroot/sandbox/overlay）下叠加在 payload 之上的持久可写层。
+///
+/// launcher 以 payload 为下层、状态目录的 `upper/` 为上层挂载 overlayfs，写入跨运行保留，
+/// bundle 本身保持不变；Host 模式直接使用宿主文件系统，忽略该层。
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StateLayer {
+    /// 状态目录：相对路径相对 bundle 根目录，`~/` 开头相对入口的 `$HOME`；
+    /// 未设置时为 `$XDG_STATE_HOME/sidebundle/<bundle-name>-<摘要>`。
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dir: Option<PathBuf>,
+}
+
+impl StateLayer {
+    /// 计算 `bundle_root`（应为规范化的绝对路径）的状态目录；`home`、`state_home` 为入口看到的
+    /// `$HOME` 与 `$XDG_STATE_HOME`。默认目录名在 bundle 目录名后附上其路径 SHA-256 的前 8 位，
+    /// 同名的不同 bundle 不会共用状态。无法确定时返回 `None`。
+    pub fn locate(
+        &self,
+        bundle_root: &Path,
+        home: Option<&Path>,
+        state_home: Option<&Path>,
+    ) -> Option<PathBuf> {
+        if let Some(dir) = &self.dir {
+            return crate::LandlockPolicy::expand_home(dir, home).map(|dir| bundle_root.join(dir));
+        }
+        let state_home = state_home
+            .filter(|dir| dir.is_absolute())
+            .map(Path::to_path_buf)
+            .or_else(|| home.map(|home| home.join(".local/state")))?;
+        let digest = format!("{:x}", Sha256::digest(bundle_root.as_os_str().as_bytes()));
+        let mut name = bundle_root.file_name()?.to_os_string();
+        name.push(format!("-{}", &digest[..8]));
+        Some(state_home.join("sidebundle").join(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn state_dir_defaults_to_xdg_state_home() {
+        let bundle = Path::new("/opt/tools/jq");
+        let home = Some(Path::new("/home/dev"));
+        let default = StateLayer::default();
+        let dir = default.locate(bundle, home, None).unwrap();
+        assert_eq!(
+            dir.parent(),
+            Some(Path::new("/home/dev/.local/state/sidebundle"))
+        );
+        let name = dir.file_name().unwrap().to_str().unwrap();
+        let digest = name.strip_prefix("jq-").unwrap();
+        assert!(digest.len() == 8 && digest.bytes().all(|b| b.is_ascii_hexdigit()));
+        assert_eq!(
+            default.locate(bundle, home, Some(Path::new("/var/state"))),
+            Some(Path::new("/var/state/sidebundle").join(name))
+        );
+        assert_eq!(default.locate(bundle, home, None), Some(dir.clone()));
+        // Bundles sharing a directory name keep separate state.
+        let other = default
+            .locate(Path::new("/home/dev/jq"), home, None)
+            .unwrap();
+        assert_ne!(other, dir);
+        assert!(other
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .starts_with("jq-"));
+        assert_eq!(default.locate(bundle, None, None), None);
+
+        let beside = StateLayer {
+            dir: Some(PathBuf::from("../jq.state")),
+        };
+        assert_eq!(
+            beside.locate(bundle, home, None),
+            Some(PathBuf::from("/opt/tools/jq/../jq.state"))
+        );
+        let in_home = StateLayer {
+            dir: Some(PathBuf::from("~/jq.state")),
+        };
+        assert_eq!(
+            in_home.locate(bundle, home, None),
+            Some(PathBuf::from("/home/dev/jq.state"))
+        );
+    }
+}
@@ -0,0 +1,113 @@
+//! The running user's passwd and group entries in the isolated run modes.
+//!
+//! Bundles carry minimal synthetic account files, so inside bwrap, chroot, sandbox and overlay
+//! mode `getpwuid()` would not know the caller. Before the root is built the launcher adds an
+//! entry for the current uid/gid, named as on the host, and mounts the result read-only over the
+//! payload's copy; neither the bundle nor the state layer is modified.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use super::sandbox::RootFile;
+use super::{state, StateDirs};
+
+/// The payload's passwd and group, extended with the caller where they lack the current uid or
+/// gid. `home` is the entry's HOME.
+pub(crate) fn runtime_files(
+    payload_root: &Path,
+    state: Option<&StateDirs>,
+    home: Option<&Path>,
+) -> Vec<RootFile> {
+    let uid = unsafe { libc::getuid() };
+    let gid = unsafe { libc::getgid() };
+    let host_passwd = fs::read_to_string("/etc/passwd").unwrap_or_default();
+    let host_group = fs::read_to_string("/etc/group").unwrap_or_default();
+    let user = lookup_name(&host_passwd, uid)
+        .map(str::to_string)
+        .or_else(|| env::var("USER").ok().filter(|user| !user.is_empty()))
+        .unwrap_or_else(|| "user".to_string());
+    let group = lookup_name(&host_group, gid).unwrap_or(&user);
+    let home = home.map_or("/".into(), Path::to_string_lossy);
+
+    let mut files = Vec::new();
+    let passwd_entry = format!("{user}:x:{uid}:{gid}:{user}:{home}:/bin/sh");
+    let group_entry = format!("{group}:x:{gid}:");
+    for (path, id, entry) in [
+        ("etc/passwd", uid, passwd_entry),
+        ("etc/group", gid, group_entry),
+    ] {
+        let current = current_contents(payload_root, state, path);
+        if let Some(contents) = with_entry(&current, id, &entry) {
+            files.push(RootFile {
+                path,
+                contents: contents.into_bytes(),
+            });
+        }
+    }
+    files
+}
+
+/// `path` as the entry would see it: the state layer's copy if it has one, else the payload's.
+fn current_contents(payload_root: &Path, state: Option<&StateDirs>, path: &str) -> String {
+    fs::read_to_string(state::current_path(payload_root, state, path)).unwrap_or_default()
+}
+
+/// The name of the passwd or group line whose id (third field) is `id`.
+fn lookup_name(db: &str, id: u32) -> Option<&str> {
+    db.lines().find_map(|line| {
+        let mut fields = line.split(':');
+        let name = fields.next()?;
+        let found = fields.nth(1)?.parse::<u32>().ok()? == id;
+        found.then_some(name)
+    })
+}
+
+/// `db` with `entry` appended, or `None` when it already has a line for `id`.
+fn with_entry(db: &str, id: u32, entry: &str) -> Option<String> {
+    if lookup_name(db, id).is_some() {
+        return None;
+    }
+    let mut contents = db.to_string();
+    if !contents.is_empty() && !contents.ends_with('\n') {
+        contents.push('\n');
+    }
+    contents.push_str(entry);
+    contents.push('\n');
+    Some(contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn caller_is_appended_only_when_missing() {
+        let passwd = "root:x:0:0:root:/root:/bin/sh\nnobody:x:65534:65534::/nonexistent:/bin/false";
+        assert_eq!(lookup_name(passwd, 65534), Some("nobody"));
+        assert_eq!(lookup_name(passwd, 1000), None);
+        assert_eq!(with_entry(passwd, 0, "admin:x:0:0::/:/bin/sh"), None);
+        assert_eq!(
+            with_entry(passwd, 1000, "dev:x:1000:1000:dev:/home/dev:/bin/sh").unwrap(),
+            format!("{passwd}\ndev:x:1000:1000:dev:/home/dev:/bin/sh\n")
+        );
+        assert_eq!(with_entry("", 7, "g:x:7:").unwrap(), "g:x:7:\n");
+
+        let dir = tempfile::tempdir().unwrap();
+        let payload = dir.path().join("payload");
+        fs::create_dir_all(payload.join("etc")).unwrap();
+        fs::write(payload.join("etc/group"), "root:x:0:\n").unwrap();
+        let state = StateDirs {
+            upper: dir.path().join("upper"),
+            work: dir.path().join("work"),
+        };
+        fs::create_dir_all(state.upper.join("etc")).unwrap();
+        fs::write(state.upper.join("etc/group"), "staff:x:50:\n").unwrap();
+        assert_eq!(current_contents(&payload, None, "etc/group"), "root:x:0:\n");
+        assert_eq!(
+            current_contents(&payload, Some(&state), "etc/group"),
+            "staff:x:50:\n"
+        );
+        assert_eq!(current_contents(&payload, Some(&state), "etc/passwd"), "");
+    }
+}
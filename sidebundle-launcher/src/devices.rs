@@ -0,0 +1,129 @@
+//! Host devices in the isolated run modes.
+//!
+//! Every isolated root gets the host's basic device nodes on a fresh `/dev`. Device profiles and paths from the launcher config are expanded against the
+//! host at launch and only the paths that exist are bound, so one bundle runs on machines with
+//! and without the hardware.
+
+use anyhow::Result;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use sidebundle_core::{glob_match, DevicePolicy};
+
+use super::sandbox;
+
+/// Device nodes bound into every isolated root.
+pub(crate) const BASE_DEVICES: &[&str] = &[
+    "/dev/null",
+    "/dev/zero",
+    "/dev/full",
+    "/dev/random",
+    "/dev/urandom",
+    "/dev/tty",
+];
+
+/// Host paths matching `policy`, without duplicates. A `*` may only appear in the last component.
+pub(crate) fn resolve(policy: &DevicePolicy) -> Vec<PathBuf> {
+    resolve_in(Path::new("/"), policy)
+}
+
+fn resolve_in(host: &Path, policy: &DevicePolicy) -> Vec<PathBuf> {
+    let mut devices = Vec::new();
+    for pattern in policy.patterns() {
+        let path = Path::new(pattern);
+        let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+            continue;
+        };
+        let name = name.to_string_lossy();
+        if !path.is_absolute() || parent.to_string_lossy().contains('*') {
+            eprintln!("sidebundle launcher: warning: ignoring device pattern {pattern}");
+            continue;
+        }
+        if !name.contains('*') {
+            if fs::symlink_metadata(sandbox::host_path(host, path)).is_ok() {
+                devices.push(path.to_path_buf());
+            }
+            continue;
+        }
+        let Ok(entries) = fs::read_dir(sandbox::host_path(host, parent)) else {
+            continue;
+        };
+        let mut matched: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| glob_match(&name, &entry.file_name().to_string_lossy()))
+            .map(|entry| parent.join(entry.file_name()))
+            .collect();
+        matched.sort();
+        devices.extend(matched);
+    }
+    let mut seen = HashSet::new();
+    devices.retain(|device| seen.insert(device.clone()));
+    devices
+}
+
+/// Bind `devices` (host paths) to the same paths in `root`. Devices the host lacks are skipped, as
+/// are those whose mount point cannot be created.
+pub(crate) fn bind<'a>(
+    root: &sandbox::StagedRoot,
+    devices: impl IntoIterator<Item = &'a Path>,
+) -> Result<()> {
+    for device in devices {
+        let source = sandbox::host_path(&root.host, device);
+        let Ok(meta) = fs::metadata(&source) else {
+            continue;
+        };
+        if device.is_symlink() {
+            // It would be resolved against the host root; leave it alone.
+            continue;
+        }
+        let target = match root.mount_point(device, meta.is_dir()) {
+            Ok(target) => target,
+            Err(err) => {
+                eprintln!(
+                    "sidebundle launcher: warning: {err:#}; skipping {}",
+                    device.display()
+                );
+                continue;
+            }
+        };
+        sandbox::bind(&source, &target)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns_expand_against_the_host() {
+        let host = tempfile::tempdir().unwrap();
+        fs::create_dir_all(host.path().join("dev/dri")).unwrap();
+        for node in ["nvidia0", "nvidia1", "nvidiactl", "kvm"] {
+            fs::write(host.path().join("dev").join(node), b"").unwrap();
+        }
+        let mut policy = DevicePolicy::default();
+        for item in [
+            "dri",
+            "nvidia",
+            "fuse",
+            "/dev/kvm",
+            "/dev/nvidia0",
+            "/dev/*/card0",
+        ] {
+            policy.push(item).unwrap();
+        }
+        assert_eq!(
+            resolve_in(host.path(), &policy),
+            [
+                "/dev/dri",
+                "/dev/nvidia0",
+                "/dev/nvidia1",
+                "/dev/nvidiactl",
+                "/dev/kvm"
+            ]
+            .map(PathBuf::from)
+        );
+    }
+}
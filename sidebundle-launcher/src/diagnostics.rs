@@ -0,0 +1,300 @@
+//! Launcher diagnostics for bundles that misbehave on someone else's host.
+//!
+//! A leading `--sidebundle-dry-run` argument makes the launcher print the resolved config, the
+//! chosen run mode, the exact command it would exec and how the entry's environment differs from
+//! the inherited one, then exit without running anything. `--sidebundle-report` prints the same
+//! after a summary of the host (kernel, privileges, which run modes are usable), meant to be
+//! attached to bug reports. With `SIDEBUNDLE_DEBUG` set the same output goes to stderr and the
+//! entry runs as usual.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::ffi::{CString, OsStr, OsString};
+use std::fmt::Write as _;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use sidebundle_core::SecretRedactor;
+
+use super::{fallback, landlock, preflight, StateDirs};
+
+const DRY_RUN_ARG: &str = "--sidebundle-dry-run";
+const REPORT_ARG: &str = "--sidebundle-report";
+const DEBUG_ENV: &str = "SIDEBUNDLE_DEBUG";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Exec,
+    DryRun,
+    Report,
+}
+
+/// The command the launcher is about to exec.
+pub(crate) struct ExecPlan {
+    /// How the command is started, e.g. through the bundled loader or inside a new root.
+    pub(crate) method: String,
+    pub(crate) program: PathBuf,
+    pub(crate) argv: Vec<CString>,
+    pub(crate) envp: Vec<CString>,
+}
+
+pub(crate) struct Diagnostics {
+    action: Action,
+    debug: bool,
+}
+
+impl Diagnostics {
+    /// Consume a leading diagnostics flag from the entry's arguments (argv without argv0).
+    pub(crate) fn from_args(args: &mut Vec<OsString>) -> Self {
+        let action = match args.first().and_then(|arg| arg.to_str()) {
+            Some(DRY_RUN_ARG) => Action::DryRun,
+            Some(REPORT_ARG) => Action::Report,
+            _ => Action::Exec,
+        };
+        if action != Action::Exec {
+            args.remove(0);
+        }
+        Self {
+            action,
+            debug: debug_enabled(),
+        }
+    }
+
+    fn enabled(&self) -> bool {
+        self.action != Action::Exec || self.debug
+    }
+
+    /// Host facts, printed for reports only.
+    pub(crate) fn host(&self, bundle_root: &Path, entry_name: &str) {
+        if self.action == Action::Report {
+            self.emit("host", &host_section(bundle_root, entry_name));
+        }
+    }
+
+    pub(crate) fn config(&self, path: &Path, config: &impl Serialize) {
+        if !self.enabled() {
+            return;
+        }
+        let json = serde_json::to_string_pretty(config)
+            .unwrap_or_else(|err| format!("<cannot serialize config: {err}>"));
+        self.emit("config", &format!("path: {}\n{json}\n", path.display()));
+    }
+
+    /// The host compatibility check; a failure only stops real launches.
+    pub(crate) fn preflight(&self, result: anyhow::Result<()>) -> anyhow::Result<()> {
+        if self.enabled() {
+            let text = match &result {
+                Ok(()) => "ok\n".to_string(),
+                Err(err) => format!("{err:#}\n"),
+            };
+            self.emit("preflight", &text);
+        }
+        match self.action {
+            Action::Exec => result,
+            _ => Ok(()),
+        }
+    }
+
+    /// How the run mode was chosen, as noted by the fallback chain.
+    pub(crate) fn run_mode(&self, notes: &[String]) {
+        if self.enabled() {
+            self.emit("run mode", &(notes.join("\n") + "\n"));
+        }
+    }
+
+    /// The state layer mounted over the payload, if any.
+    pub(crate) fn state(&self, state: Option<&StateDirs>) {
+        if let (true, Some(state)) = (self.enabled(), state) {
+            let text = format!(
+                "upper: {}\nwork: {}\n",
+                state.upper.display(),
+                state.work.display()
+            );
+            self.emit("state", &text);
+        }
+    }
+
+    /// Show `plan`; `false` when the launcher should stop here instead of exec'ing it.
+    pub(crate) fn should_exec(&self, plan: &ExecPlan) -> bool {
+        if self.enabled() {
+            self.emit("exec", &exec_section(plan));
+            let inherited = std::env::vars_os()
+                .map(|(key, value)| (key.as_bytes().to_vec(), value.as_bytes().to_vec()));
+            self.emit("env", &env_diff(inherited, &plan.envp));
+        }
+        self.action == Action::Exec
+    }
+
+    fn emit(&self, title: &str, body: &str) {
+        if self.action == Action::Exec {
+            for line in body.lines() {
+                eprintln!("sidebundle launcher: debug: [{title}] {line}");
+            }
+        } else {
+            println!("== {title} ==\n{body}");
+        }
+    }
+}
+
+fn debug_enabled() -> bool {
+    std::env::var_os(DEBUG_ENV).is_some_and(|value| !value.is_empty() && value != "0")
+}
+
+fn host_section(bundle_root: &Path, entry_name: &str) -> String {
+    let mut text = String::new();
+    let _ = writeln!(
+        text,
+        "launcher: sidebundle-launcher {}",
+        env!("CARGO_PKG_VERSION")
+    );
+    let _ = writeln!(text, "bundle: {}", bundle_root.display());
+    let _ = writeln!(text, "entry: {entry_name}");
+    let _ = writeln!(text, "kernel: {}", preflight::kernel_release());
+    if let Some(level) = preflight::host_isa_level() {
+        let _ = writeln!(text, "cpu: {}", preflight::isa_name(level));
+    }
+    let (uid, euid, gid, egid) = unsafe {
+        (
+            libc::getuid(),
+            libc::geteuid(),
+            libc::getgid(),
+            libc::getegid(),
+        )
+    };
+    let _ = writeln!(text, "ids: uid={uid} euid={euid} gid={gid} egid={egid}");
+    match std::env::current_dir() {
+        Ok(cwd) => {
+            let _ = writeln!(text, "cwd: {}", cwd.display());
+        }
+        Err(err) => {
+            let _ = writeln!(text, "cwd: unavailable ({err})");
+        }
+    }
+    match landlock::abi_version() {
+        Ok(abi) => {
+            let _ = writeln!(text, "landlock: abi {abi}");
+        }
+        Err(err) => {
+            let _ = writeln!(text, "landlock: unavailable ({err})");
+        }
+    }
+    for (mode, status) in fallback::probe_all() {
+        match status {
+            Ok(()) => {
+                let _ = writeln!(text, "run_mode={mode}: available");
+            }
+            Err(reason) => {
+                let _ = writeln!(text, "run_mode={mode}: unavailable ({reason})");
+            }
+        }
+    }
+    text
+}
+
+fn exec_section(plan: &ExecPlan) -> String {
+    let mut text = String::new();
+    let _ = writeln!(text, "method: {}", plan.method);
+    let _ = writeln!(text, "program: {}", plan.program.display());
+    for (index, arg) in plan.argv.iter().enumerate() {
+        let _ = writeln!(text, "argv[{index}]: {}", quote(arg.as_bytes()));
+    }
+    text
+}
+
+/// `+` lines for variables the entry gets that differ from the inherited environment, `-` lines
+/// for inherited values it does not see. Values of credential-like variables are not shown.
+fn env_diff(inherited: impl IntoIterator<Item = (Vec<u8>, Vec<u8>)>, envp: &[CString]) -> String {
+    let inherited: BTreeMap<Vec<u8>, Vec<u8>> = inherited.into_iter().collect();
+    let target: BTreeMap<&[u8], &[u8]> = envp
+        .iter()
+        .map(|entry| {
+            let bytes = entry.as_bytes();
+            match bytes.iter().position(|b| *b == b'=') {
+                Some(split) => (&bytes[..split], &bytes[split + 1..]),
+                None => (bytes, &[][..]),
+            }
+        })
+        .collect();
+    let secrets = SecretRedactor::default();
+    let value_of = |key: &[u8], value: &[u8]| {
+        if secrets.is_secret(&show(key)) {
+            "<redacted>".to_string()
+        } else {
+            quote(value)
+        }
+    };
+    let mut text = String::new();
+    let mut unchanged = 0;
+    for (key, value) in &inherited {
+        match target.get(key.as_slice()) {
+            Some(new) if *new == value.as_slice() => unchanged += 1,
+            _ => {
+                let _ = writeln!(text, "- {}={}", show(key), value_of(key, value));
+            }
+        }
+    }
+    for (key, value) in &target {
+        if inherited.get(*key).map(Vec::as_slice) != Some(*value) {
+            let _ = writeln!(text, "+ {}={}", show(key), value_of(key, value));
+        }
+    }
+    let _ = writeln!(text, "({unchanged} inherited variables unchanged)");
+    text
+}
+
+fn show(bytes: &[u8]) -> String {
+    OsStr::from_bytes(bytes).to_string_lossy().into_owned()
+}
+
+/// Arguments and values as they would be typed into a shell.
+fn quote(bytes: &[u8]) -> String {
+    let plain = !bytes.is_empty()
+        && bytes
+            .iter()
+            .all(|b| b.is_ascii_alphanumeric() || b"/._-+=:,@%".contains(b));
+    if plain {
+        show(bytes)
+    } else {
+        format!("'{}'", show(bytes).replace('\'', r"'\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leading_flag_is_consumed() {
+        let mut args = vec![OsString::from(DRY_RUN_ARG), OsString::from("--version")];
+        let diag = Diagnostics::from_args(&mut args);
+        assert_eq!(diag.action, Action::DryRun);
+        assert_eq!(args, [OsString::from("--version")]);
+
+        let mut args = vec![OsString::from("-v"), OsString::from(REPORT_ARG)];
+        let diag = Diagnostics::from_args(&mut args);
+        assert_eq!(diag.action, Action::Exec);
+        assert_eq!(args.len(), 2);
+    }
+
+    #[test]
+    fn env_diff_lists_changed_added_and_dropped_vars() {
+        let inherited = [
+            ("HOME", "/home/dev"),
+            ("PATH", "/usr/bin"),
+            ("OPTS", "x y"),
+            ("API_TOKEN", "hunter2"),
+        ]
+        .map(|(key, value)| (key.as_bytes().to_vec(), value.as_bytes().to_vec()));
+        let envp = [
+            "HOME=/home/dev",
+            "PATH=/b/bin:/usr/bin",
+            "SIDEBUNDLE_ROOT=/b",
+        ]
+        .map(|entry| CString::new(entry).unwrap());
+        assert_eq!(
+            env_diff(inherited, &envp),
+            "- API_TOKEN=<redacted>\n- OPTS='x y'\n- PATH=/usr/bin\n+ PATH=/b/bin:/usr/bin\n\
+             + SIDEBUNDLE_ROOT=/b\n(1 inherited variables unchanged)\n"
+        );
+    }
+}
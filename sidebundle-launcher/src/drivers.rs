@@ -0,0 +1,504 @@
+//! Host GPU driver libraries for bundles that were built without them.
+//!
+//! libGL, libEGL, libvulkan, libcuda, libnvidia-* and friends belong to the host's kernel module
+//! and hardware, so the closure leaves them out and the launcher config lists their sonames. At
+//! launch they are looked up in the host's `ld.so.cache`, together with the vendor libraries the
+//! host's EGL, Vulkan and OpenCL ICD files name and whatever those need, and exposed to the entry
+//! in one directory searched after the bundled libraries. glibc itself is never taken from the
+//! host. Host mode gets a cached directory of symlinks; the isolated modes bind the files into
+//! [`DRIVER_DIR`] and, when the payload has its own `ld.so.cache`, list them in it.
+
+use anyhow::{bail, Context, Result};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::env;
+use std::fs;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::os::unix::fs::{symlink, DirBuilderExt, MetadataExt};
+use std::path::{Path, PathBuf};
+
+use sidebundle_core::{parse_elf_metadata, LdCache, LdCacheEntry, RunMode, NATIVE_CACHE_FLAGS};
+
+use super::sandbox;
+
+/// Where the isolated modes expose the drivers.
+pub(crate) const DRIVER_DIR: &str = "/run/sidebundle/drivers";
+
+const HOST_CACHE: &str = "/etc/ld.so.cache";
+
+/// glibc's own libraries; the entry keeps using the bundled ones.
+const GLIBC_SONAMES: &[&str] = &[
+    "libc.so.6",
+    "libm.so.6",
+    "libmvec.so.1",
+    "libpthread.so.0",
+    "libdl.so.2",
+    "librt.so.1",
+    "libresolv.so.2",
+    "libutil.so.1",
+    "libanl.so.1",
+    "libBrokenLocale.so.1",
+    "libnsl.so.1",
+    "libnss_files.so.2",
+    "libnss_dns.so.2",
+];
+
+/// Vendor libraries a loader picks by name at run time, by the prefix of the loader's soname.
+const VENDOR_PREFIXES: &[(&str, &str)] = &[
+    ("libGL.so", "libGLX_"),
+    ("libGLX.so", "libGLX_"),
+    ("libcuda.so", "libnvidia-"),
+    ("libnvidia-", "libnvidia-"),
+];
+
+/// Vendor files of an ICD loader.
+struct IcdKind {
+    /// Soname prefix of the loader library.
+    loader: &'static str,
+    /// Host directories holding the vendor files, in override order.
+    dirs: &'static [&'static str],
+    extension: &'static str,
+    /// Variables listing the vendor files.
+    vars: &'static [&'static str],
+    /// Variable naming the directory instead, for loaders that scan one.
+    dir_var: Option<&'static str>,
+}
+
+const ICD_KINDS: &[IcdKind] = &[
+    IcdKind {
+        loader: "libEGL.so",
+        dirs: &["/etc/glvnd/egl_vendor.d", "/usr/share/glvnd/egl_vendor.d"],
+        extension: "json",
+        vars: &["__EGL_VENDOR_LIBRARY_FILENAMES"],
+        dir_var: None,
+    },
+    IcdKind {
+        loader: "libvulkan.so",
+        dirs: &["/etc/vulkan/icd.d", "/usr/share/vulkan/icd.d"],
+        extension: "json",
+        vars: &["VK_DRIVER_FILES", "VK_ICD_FILENAMES"],
+        dir_var: None,
+    },
+    IcdKind {
+        loader: "libOpenCL.so",
+        dirs: &["/etc/OpenCL/vendors"],
+        extension: "icd",
+        vars: &[],
+        dir_var: Some("OCL_ICD_VENDORS"),
+    },
+];
+
+/// Host driver files found for the recorded sonames.
+#[derive(Debug, Default, PartialEq, Eq, Hash)]
+pub(crate) struct HostDrivers {
+    /// Host libraries, by the file name they are exposed under.
+    pub(crate) libraries: BTreeMap<String, PathBuf>,
+    /// Vendor files rewritten to load their library by file name, by file name.
+    pub(crate) icds: BTreeMap<String, String>,
+    /// Loader variables with the vendor files they list.
+    vars: BTreeMap<&'static str, Vec<String>>,
+    /// Loader variables naming the directory itself.
+    dir_vars: BTreeSet<&'static str>,
+}
+
+/// The drivers an entry gets and the directory it sees them in.
+pub(crate) struct DriverDir {
+    pub(crate) dir: PathBuf,
+    pub(crate) drivers: HostDrivers,
+}
+
+impl DriverDir {
+    /// Find the host drivers for `sonames`; in host mode also stage the directory. Problems only
+    /// cost the entry its drivers and are reported as warnings.
+    pub(crate) fn prepare(sonames: &[String], run_mode: RunMode) -> Option<Self> {
+        if sonames.is_empty() {
+            return None;
+        }
+        let drivers = discover_in(Path::new("/"), sonames);
+        if drivers.libraries.is_empty() {
+            eprintln!(
+                "sidebundle launcher: warning: no host drivers found for {}",
+                sonames.join(", ")
+            );
+            return None;
+        }
+        let dir = if run_mode == RunMode::Host {
+            match stage_host(&drivers) {
+                Ok(dir) => dir,
+                Err(err) => {
+                    eprintln!("sidebundle launcher: warning: cannot expose host drivers ({err:#})");
+                    return None;
+                }
+            }
+        } else {
+            PathBuf::from(DRIVER_DIR)
+        };
+        Some(Self { dir, drivers })
+    }
+
+    /// `cache` with the driver libraries it lacks added at their paths in [`DRIVER_DIR`]; `None`
+    /// when this architecture has no cache flags.
+    pub(crate) fn extend_cache(&self, cache: &LdCache) -> Option<LdCache> {
+        let flags = NATIVE_CACHE_FLAGS?;
+        let mut entries = cache.entries().to_vec();
+        for name in self.drivers.libraries.keys() {
+            if cache.lookup(name).is_none() {
+                entries.push(LdCacheEntry {
+                    flags,
+                    soname: name.clone(),
+                    path: Path::new(DRIVER_DIR).join(name),
+                    hwcap: 0,
+                });
+            }
+        }
+        Some(LdCache::new(entries))
+    }
+
+    /// Point the ICD loaders at the vendor files. `keep` leaves variables the caller already set.
+    pub(crate) fn export(&self, env_map: &mut BTreeMap<String, String>, keep: bool) {
+        let lists = self.drivers.vars.iter().map(|(var, files)| {
+            let paths: Vec<String> = files
+                .iter()
+                .map(|file| self.dir.join(file).to_string_lossy().into_owned())
+                .collect();
+            (*var, paths.join(":"))
+        });
+        let dirs = self
+            .drivers
+            .dir_vars
+            .iter()
+            .map(|var| (*var, self.dir.to_string_lossy().into_owned()));
+        for (var, value) in lists.chain(dirs) {
+            if keep && env_map.contains_key(var) {
+                continue;
+            }
+            env_map.insert(var.to_string(), value);
+        }
+    }
+}
+
+/// Look up `sonames`, the vendor libraries their loaders use and all their dependencies on the host
+/// root mounted at `host`.
+fn discover_in(host: &Path, sonames: &[String]) -> HostDrivers {
+    let mut drivers = HostDrivers::default();
+    let Some(cache) = fs::read(sandbox::host_path(host, Path::new(HOST_CACHE)))
+        .ok()
+        .and_then(|data| LdCache::parse(&data))
+    else {
+        return drivers;
+    };
+
+    let mut queue: VecDeque<PathBuf> = VecDeque::new();
+    let mut wanted: BTreeSet<&str> = sonames.iter().map(String::as_str).collect();
+    for (loader, prefix) in VENDOR_PREFIXES {
+        if sonames.iter().any(|name| name.starts_with(loader)) {
+            let vendors = cache.entries().iter().map(|entry| entry.soname.as_str());
+            wanted.extend(vendors.filter(|name| name.starts_with(prefix)));
+        }
+    }
+    queue.extend(
+        wanted
+            .iter()
+            .filter_map(|name| cache.lookup(name))
+            .map(Path::to_path_buf),
+    );
+    for kind in ICD_KINDS {
+        if sonames.iter().any(|name| name.starts_with(kind.loader)) {
+            read_icds(host, &cache, kind, &mut drivers, &mut queue);
+        }
+    }
+
+    while let Some(library) = queue.pop_front() {
+        let Some(name) = library.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        if is_glibc(name) || drivers.libraries.contains_key(name) {
+            continue;
+        }
+        let source = sandbox::host_path(host, &library);
+        if !source.is_file() {
+            continue;
+        }
+        if let Ok(meta) = parse_elf_metadata(&source) {
+            let needed = meta.needed.iter().filter(|needed| !is_glibc(needed));
+            queue.extend(
+                needed
+                    .filter_map(|needed| cache.lookup(needed))
+                    .map(Path::to_path_buf),
+            );
+        }
+        drivers.libraries.insert(name.to_string(), library);
+    }
+    drivers
+}
+
+/// Collect `kind`'s vendor files whose library the host has, queueing the libraries.
+fn read_icds(
+    host: &Path,
+    cache: &LdCache,
+    kind: &IcdKind,
+    drivers: &mut HostDrivers,
+    queue: &mut VecDeque<PathBuf>,
+) {
+    let mut files: BTreeMap<String, PathBuf> = BTreeMap::new();
+    for dir in kind.dirs {
+        let Ok(entries) = fs::read_dir(sandbox::host_path(host, Path::new(dir))) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if Path::new(&name)
+                .extension()
+                .is_some_and(|ext| ext == kind.extension)
+            {
+                // Earlier directories override later ones.
+                files
+                    .entry(name)
+                    .or_insert_with(|| Path::new(dir).join(entry.file_name()));
+            }
+        }
+    }
+    for (name, path) in files {
+        let Ok(contents) = fs::read_to_string(sandbox::host_path(host, &path)) else {
+            continue;
+        };
+        let rewritten = if kind.extension == "icd" {
+            Some((contents.trim().to_string(), None))
+        } else {
+            serde_json::from_str::<serde_json::Value>(&contents)
+                .ok()
+                .and_then(|json| {
+                    let library = json.pointer("/ICD/library_path")?.as_str()?.to_string();
+                    Some((library, Some(json)))
+                })
+        };
+        let Some((library, json)) = rewritten else {
+            continue;
+        };
+        let resolved = if library.contains('/') {
+            let parent = path.parent().unwrap_or(Path::new("/"));
+            Some(parent.join(&library))
+        } else {
+            cache.lookup(&library).map(Path::to_path_buf)
+        };
+        let Some(resolved) = resolved.filter(|lib| sandbox::host_path(host, lib).is_file()) else {
+            continue;
+        };
+        let Some(file_name) = resolved
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+        else {
+            continue;
+        };
+        // Loaded by file name, so it is found in the driver directory whatever its path there.
+        let contents = match json {
+            Some(mut json) => {
+                json["ICD"]["library_path"] = file_name.clone().into();
+                serde_json::to_string_pretty(&json).unwrap_or_default()
+            }
+            None => format!("{file_name}\n"),
+        };
+        queue.push_back(resolved);
+        for var in kind.vars {
+            drivers.vars.entry(var).or_default().push(name.clone());
+        }
+        if let Some(var) = kind.dir_var {
+            drivers.dir_vars.insert(var);
+        }
+        drivers.icds.insert(name, contents);
+    }
+}
+
+fn is_glibc(soname: &str) -> bool {
+    GLIBC_SONAMES.contains(&soname)
+        || soname.starts_with("ld-linux")
+        || soname.starts_with("ld64.so")
+}
+
+/// The host-mode driver directory: symlinks to the host libraries plus the vendor files, named
+/// after a hash of both so later launches reuse it.
+fn stage_host(drivers: &HostDrivers) -> Result<PathBuf> {
+    let base = match env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from) {
+        Some(dir) if dir.is_absolute() => dir.join("sidebundle-drivers"),
+        _ => env::temp_dir().join(format!("sidebundle-drivers.{}", unsafe { libc::getuid() })),
+    };
+    match fs::DirBuilder::new().mode(0o700).create(&base) {
+        Ok(()) => {}
+        Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {}
+        Err(err) => {
+            return Err(err).with_context(|| format!("failed to create {}", base.display()))
+        }
+    }
+    let meta = fs::symlink_metadata(&base)?;
+    if !meta.is_dir() || meta.uid() != unsafe { libc::getuid() } {
+        bail!("{} is not a directory owned by the caller", base.display());
+    }
+
+    let mut hasher = DefaultHasher::new();
+    drivers.hash(&mut hasher);
+    let dir = base.join(format!("{:016x}", hasher.finish()));
+    if dir.is_dir() {
+        return Ok(dir);
+    }
+    let staging = base.join(format!(".staging.{}", std::process::id()));
+    let _ = fs::remove_dir_all(&staging);
+    fs::create_dir(&staging).with_context(|| format!("failed to create {}", staging.display()))?;
+    let filled = drivers
+        .libraries
+        .iter()
+        .try_for_each(|(name, path)| symlink(path, staging.join(name)))
+        .and_then(|()| {
+            drivers
+                .icds
+                .iter()
+                .try_for_each(|(name, contents)| fs::write(staging.join(name), contents))
+        });
+    // A concurrent launch may have won the rename; its directory is identical.
+    if let Err(err) = filled.and_then(|()| fs::rename(&staging, &dir)) {
+        let _ = fs::remove_dir_all(&staging);
+        if !dir.is_dir() {
+            return Err(err).with_context(|| format!("failed to populate {}", dir.display()));
+        }
+    }
+    Ok(dir)
+}
+
+/// Populate [`DRIVER_DIR`] in `root` on a fresh tmpfs, binding the libraries from the host.
+pub(crate) fn mount(root: &sandbox::StagedRoot, drivers: &HostDrivers) -> Result<()> {
+    let dir = match root.mount_point(Path::new(DRIVER_DIR), true) {
+        Ok(dir) => dir,
+        Err(err) => {
+            eprintln!("sidebundle launcher: warning: {err:#}; host drivers are unavailable");
+            return Ok(());
+        }
+    };
+    sandbox::tmpfs(&dir, "mode=0755")?;
+    for (name, contents) in &drivers.icds {
+        let target = dir.join(name);
+        fs::write(&target, contents)
+            .with_context(|| format!("failed to write {}", target.display()))?;
+    }
+    for (name, library) in &drivers.libraries {
+        let target = dir.join(name);
+        fs::File::create(&target)
+            .with_context(|| format!("failed to create {}", target.display()))?;
+        sandbox::bind(&sandbox::host_path(&root.host, library), &target)?;
+    }
+    sandbox::remount_readonly(&dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drivers_resolve_through_the_host_cache_and_icds() {
+        let Some(flags) = NATIVE_CACHE_FLAGS else {
+            return;
+        };
+        let host = tempfile::tempdir().unwrap();
+        let root = host.path();
+        let lib = Path::new("/usr/lib/gpu");
+        fs::create_dir_all(sandbox::host_path(root, lib)).unwrap();
+        // A real ELF stands in for the EGL vendor library so its DT_NEEDED entries are followed.
+        let exe = env::current_exe().unwrap();
+        let needed = parse_elf_metadata(&exe).unwrap().needed;
+        let mut sonames: Vec<String> = needed.clone();
+        sonames.extend(
+            [
+                "libEGL.so.1",
+                "libEGL_fake.so.0",
+                "libvulkan_fake.so",
+                "libGLX_fake.so.0",
+                "libcuda.so.1",
+            ]
+            .map(String::from),
+        );
+        let mut entries = Vec::new();
+        for soname in &sonames {
+            let path = lib.join(soname);
+            if soname == "libEGL_fake.so.0" {
+                fs::copy(&exe, sandbox::host_path(root, &path)).unwrap();
+            } else {
+                fs::write(sandbox::host_path(root, &path), b"fake").unwrap();
+            }
+            entries.push(LdCacheEntry {
+                flags,
+                soname: soname.clone(),
+                path,
+                hwcap: 0,
+            });
+        }
+        fs::create_dir_all(root.join("etc/glvnd/egl_vendor.d")).unwrap();
+        fs::create_dir_all(root.join("usr/share/glvnd/egl_vendor.d")).unwrap();
+        fs::create_dir_all(root.join("usr/share/vulkan/icd.d")).unwrap();
+        fs::write(
+            root.join("etc/ld.so.cache"),
+            LdCache::new(entries).to_bytes(),
+        )
+        .unwrap();
+        let egl =
+            r#"{"file_format_version": "1.0.0", "ICD": {"library_path": "libEGL_fake.so.0"}}"#;
+        fs::write(root.join("usr/share/glvnd/egl_vendor.d/50_fake.json"), egl).unwrap();
+        fs::write(
+            root.join("usr/share/glvnd/egl_vendor.d/60_gone.json"),
+            egl.replace("fake", "gone"),
+        )
+        .unwrap();
+        // Vulkan is not among the recorded loaders, so its ICD stays out.
+        fs::write(
+            root.join("usr/share/vulkan/icd.d/fake_icd.json"),
+            r#"{"ICD": {"library_path": "/usr/lib/gpu/libvulkan_fake.so"}}"#,
+        )
+        .unwrap();
+
+        let drivers = discover_in(root, &["libEGL.so.1".into(), "libGL.so.1".into()]);
+        let mut expected: BTreeSet<String> = needed
+            .iter()
+            .filter(|name| !is_glibc(name))
+            .cloned()
+            .collect();
+        expected.extend(["libEGL.so.1", "libEGL_fake.so.0", "libGLX_fake.so.0"].map(String::from));
+        assert_eq!(
+            drivers.libraries.keys().cloned().collect::<BTreeSet<_>>(),
+            expected
+        );
+        assert_eq!(drivers.libraries["libEGL.so.1"], lib.join("libEGL.so.1"));
+        assert_eq!(drivers.icds.keys().collect::<Vec<_>>(), ["50_fake.json"]);
+        assert!(drivers.icds["50_fake.json"].contains(r#""library_path": "libEGL_fake.so.0""#));
+
+        let dir = DriverDir {
+            dir: PathBuf::from(DRIVER_DIR),
+            drivers,
+        };
+        let mut env_map = BTreeMap::from([(
+            "__EGL_VENDOR_LIBRARY_FILENAMES".to_string(),
+            "/host/egl.json".to_string(),
+        )]);
+        dir.export(&mut env_map, true);
+        assert_eq!(env_map["__EGL_VENDOR_LIBRARY_FILENAMES"], "/host/egl.json");
+        dir.export(&mut env_map, false);
+        assert_eq!(
+            env_map["__EGL_VENDOR_LIBRARY_FILENAMES"],
+            "/run/sidebundle/drivers/50_fake.json"
+        );
+        assert!(!env_map.contains_key("VK_DRIVER_FILES"));
+
+        // A bundled copy wins over the host's in the extended cache.
+        let bundled = LdCache::new(vec![LdCacheEntry {
+            flags,
+            soname: "libEGL.so.1".into(),
+            path: PathBuf::from("/usr/lib/libEGL.so.1"),
+            hwcap: 0,
+        }]);
+        let extended = dir.extend_cache(&bundled).unwrap();
+        assert_eq!(extended.entries().len(), dir.drivers.libraries.len());
+        assert_eq!(
+            extended.lookup("libEGL.so.1"),
+            Some(Path::new("/usr/lib/libEGL.so.1"))
+        );
+        assert_eq!(
+            extended.lookup("libGLX_fake.so.0"),
+            Some(Path::new("/run/sidebundle/drivers/libGLX_fake.so.0"))
+        );
+    }
+}
@@ -0,0 +1,206 @@
+//! Launch-time run-mode selection.
+//!
+//! The launcher config names a run mode plus an optional fallback chain; `SIDEBUNDLE_RUN_MODE`
+//! replaces both. Candidates are probed in order and the first whose prerequisites hold on this
+//! host is used. When none does, the first candidate runs anyway so its own error explains why.
+//! The decisions are returned for the launcher's diagnostics output.
+
+use anyhow::{anyhow, Result};
+use std::env;
+use std::os::unix::fs::PermissionsExt;
+
+use sidebundle_core::{RunMode, RUN_MODE_ENV};
+
+use super::find_bwrap;
+
+/// Pick the run mode for this launch from `configured` and its `fallback` chain, along with a
+/// note per decision taken.
+pub(crate) fn select_run_mode(
+    configured: RunMode,
+    fallback: &[RunMode],
+) -> Result<(RunMode, Vec<String>)> {
+    let mut notes = Vec::new();
+    let candidates = match env::var(RUN_MODE_ENV) {
+        Ok(value) if !value.trim().is_empty() => {
+            let modes =
+                parse_modes(&value).map_err(|err| anyhow!("invalid {RUN_MODE_ENV}: {err}"))?;
+            notes.push(format!(
+                "{RUN_MODE_ENV}={value} overrides the configured run modes"
+            ));
+            modes
+        }
+        _ => chain(configured, fallback),
+    };
+    let mut probe = Probe::default();
+    let mode = choose(
+        &candidates,
+        |mode| probe.check(mode),
+        |note| notes.push(note.to_string()),
+    );
+    Ok((mode, notes))
+}
+
+/// Prerequisite check results for every run mode on this host.
+pub(crate) fn probe_all() -> Vec<(RunMode, Result<(), String>)> {
+    let mut probe = Probe::default();
+    [
+        RunMode::Host,
+        RunMode::Bwrap,
+        RunMode::Chroot,
+        RunMode::Sandbox,
+        RunMode::Overlay,
+    ]
+    .into_iter()
+    .map(|mode| (mode, probe.check(mode)))
+    .collect()
+}
+
+fn parse_modes(value: &str) -> Result<Vec<RunMode>, String> {
+    let modes = value
+        .split(',')
+        .filter(|item| !item.trim().is_empty())
+        .map(str::parse)
+        .collect::<Result<Vec<RunMode>, _>>()?;
+    if modes.is_empty() {
+        return Err("no run mode given".into());
+    }
+    Ok(modes)
+}
+
+fn chain(configured: RunMode, fallback: &[RunMode]) -> Vec<RunMode> {
+    let mut modes = vec![configured];
+    for mode in fallback {
+        if !modes.contains(mode) {
+            modes.push(*mode);
+        }
+    }
+    modes
+}
+
+/// First candidate `check` accepts. A single candidate is used without probing.
+fn choose(
+    candidates: &[RunMode],
+    mut check: impl FnMut(RunMode) -> Result<(), String>,
+    mut log: impl FnMut(&str),
+) -> RunMode {
+    if let [only] = candidates {
+        log(&format!("run_mode={only}"));
+        return *only;
+    }
+    for mode in candidates {
+        match check(*mode) {
+            Ok(()) => {
+                log(&format!("run_mode={mode} selected"));
+                return *mode;
+            }
+            Err(reason) => log(&format!("run_mode={mode} unavailable: {reason}")),
+        }
+    }
+    let first = candidates[0];
+    log(&format!(
+        "no run mode is available; trying run_mode={first}"
+    ));
+    first
+}
+
+#[derive(Default)]
+struct Probe {
+    userns: Option<bool>,
+}
+
+impl Probe {
+    fn check(&mut self, mode: RunMode) -> Result<(), String> {
+        let root = unsafe { libc::geteuid() } == 0;
+        match mode {
+            RunMode::Host => Ok(()),
+            RunMode::Bwrap => {
+                let bwrap = find_bwrap().ok_or("bwrap not found")?;
+                let setuid = std::fs::metadata(&bwrap)
+                    .map(|meta| meta.permissions().mode() & 0o4000 != 0)
+                    .unwrap_or(false);
+                if root || setuid || self.userns() {
+                    Ok(())
+                } else {
+                    Err(format!(
+                        "user namespaces are disabled and {} is not setuid",
+                        bwrap.display()
+                    ))
+                }
+            }
+            RunMode::Chroot if root || self.userns() => Ok(()),
+            RunMode::Chroot => Err("not root and user namespaces are disabled".into()),
+            RunMode::Sandbox | RunMode::Overlay if self.userns() => Ok(()),
+            RunMode::Sandbox | RunMode::Overlay => Err("user namespaces are disabled".into()),
+        }
+    }
+
+    /// Whether this process may create a user namespace; tried in a throwaway child.
+    fn userns(&mut self) -> bool {
+        *self
+            .userns
+            .get_or_insert_with(|| match unsafe { libc::fork() } {
+                -1 => false,
+                0 => unsafe {
+                    let ok = libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNS) == 0;
+                    libc::_exit(if ok { 0 } else { 1 })
+                },
+                child => {
+                    let mut status = 0;
+                    let waited = unsafe { libc::waitpid(child, &mut status, 0) } == child;
+                    waited && libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0
+                }
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    #[test]
+    fn first_viable_mode_wins_and_decisions_are_logged() {
+        let candidates = chain(
+            RunMode::Bwrap,
+            &[RunMode::Sandbox, RunMode::Bwrap, RunMode::Host],
+        );
+        assert_eq!(
+            candidates,
+            [RunMode::Bwrap, RunMode::Sandbox, RunMode::Host]
+        );
+
+        let log = RefCell::new(Vec::new());
+        let chosen = choose(
+            &candidates,
+            |mode| match mode {
+                RunMode::Host => Ok(()),
+                _ => Err("user namespaces are disabled".into()),
+            },
+            |line| log.borrow_mut().push(line.to_string()),
+        );
+        assert_eq!(chosen, RunMode::Host);
+        assert_eq!(
+            log.into_inner(),
+            [
+                "run_mode=bwrap unavailable: user namespaces are disabled",
+                "run_mode=sandbox unavailable: user namespaces are disabled",
+                "run_mode=host selected",
+            ]
+        );
+
+        let nothing = choose(&candidates, |_| Err("no".into()), |_| {});
+        assert_eq!(nothing, RunMode::Bwrap);
+        let forced = choose(&[RunMode::Chroot], |_| panic!("not probed"), |_| {});
+        assert_eq!(forced, RunMode::Chroot);
+    }
+
+    #[test]
+    fn env_override_accepts_lists_and_aliases() {
+        assert_eq!(
+            parse_modes("userns, host").unwrap(),
+            [RunMode::Sandbox, RunMode::Host]
+        );
+        assert!(parse_modes("vm").is_err());
+        assert!(parse_modes(",").is_err());
+    }
+}
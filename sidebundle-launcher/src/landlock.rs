@@ -0,0 +1,388 @@
+//! Optional Landlock confinement for `run_mode=host`.
+//!
+//! The ruleset comes from the launcher config and is applied to the launcher itself right before
+//! it execs the entry, so the entry and everything it spawns inherit it. The bundle root stays
+//! readable and executable, system configuration stays readable, and writes are limited to the
+//! caller's working directory, `$TMPDIR` and the declared directories. `data/` holds the files the
+//! payload hard-links to, so it stays read-only like the rest of the bundle. Kernels without
+//! Landlock run the entry unconfined after a warning.
+
+use anyhow::{Context, Result};
+use std::env;
+use std::fs;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::path::{Path, PathBuf};
+
+use sidebundle_core::{LandlockPolicy, DEFAULT_LANDLOCK_READ, LANDLOCK_ENV};
+
+use super::os_to_cstring;
+
+const ACCESS_EXECUTE: u64 = 1 << 0;
+const ACCESS_WRITE_FILE: u64 = 1 << 1;
+const ACCESS_READ_FILE: u64 = 1 << 2;
+const ACCESS_READ_DIR: u64 = 1 << 3;
+const ACCESS_REMOVE_DIR: u64 = 1 << 4;
+const ACCESS_REMOVE_FILE: u64 = 1 << 5;
+const ACCESS_MAKE_CHAR: u64 = 1 << 6;
+const ACCESS_MAKE_DIR: u64 = 1 << 7;
+const ACCESS_MAKE_REG: u64 = 1 << 8;
+const ACCESS_MAKE_SOCK: u64 = 1 << 9;
+const ACCESS_MAKE_FIFO: u64 = 1 << 10;
+const ACCESS_MAKE_BLOCK: u64 = 1 << 11;
+const ACCESS_MAKE_SYM: u64 = 1 << 12;
+/// ABI 2.
+const ACCESS_REFER: u64 = 1 << 13;
+/// ABI 3.
+const ACCESS_TRUNCATE: u64 = 1 << 14;
+
+/// Everything the first Landlock ABI can restrict; device creation is never granted.
+const ABI1_ACCESS: u64 = ACCESS_EXECUTE
+    | ACCESS_WRITE_FILE
+    | ACCESS_READ_FILE
+    | ACCESS_READ_DIR
+    | ACCESS_REMOVE_DIR
+    | ACCESS_REMOVE_FILE
+    | ACCESS_MAKE_CHAR
+    | ACCESS_MAKE_DIR
+    | ACCESS_MAKE_REG
+    | ACCESS_MAKE_SOCK
+    | ACCESS_MAKE_FIFO
+    | ACCESS_MAKE_BLOCK
+    | ACCESS_MAKE_SYM;
+
+/// Rights that apply to regular files; the kernel rejects directory rights on file rules.
+const FILE_ACCESS: u64 = ACCESS_EXECUTE | ACCESS_WRITE_FILE | ACCESS_READ_FILE | ACCESS_TRUNCATE;
+
+const CREATE_RULESET_VERSION: u32 = 1 << 0;
+const RULE_PATH_BENEATH: libc::c_int = 1;
+
+/// Device nodes and directories every program may write to.
+const DEV_WRITABLE: &[&str] = &[
+    "/dev/null",
+    "/dev/zero",
+    "/dev/full",
+    "/dev/tty",
+    "/dev/pts",
+    "/dev/shm",
+];
+
+#[repr(C)]
+struct RulesetAttr {
+    handled_access_fs: u64,
+}
+
+#[repr(C, packed)]
+struct PathBeneathAttr {
+    allowed_access: u64,
+    parent_fd: libc::c_int,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Access {
+    Read,
+    ReadExec,
+    Write,
+}
+
+impl Access {
+    fn bits(self) -> u64 {
+        let read = ACCESS_READ_FILE | ACCESS_READ_DIR;
+        match self {
+            Access::Read => read,
+            Access::ReadExec => read | ACCESS_EXECUTE,
+            Access::Write => {
+                read | ACCESS_WRITE_FILE
+                    | ACCESS_REMOVE_DIR
+                    | ACCESS_REMOVE_FILE
+                    | ACCESS_MAKE_DIR
+                    | ACCESS_MAKE_REG
+                    | ACCESS_MAKE_SOCK
+                    | ACCESS_MAKE_FIFO
+                    | ACCESS_MAKE_SYM
+                    | ACCESS_REFER
+                    | ACCESS_TRUNCATE
+            }
+        }
+    }
+}
+
+/// Caller state the ruleset depends on.
+struct LandlockHost {
+    cwd: Option<PathBuf>,
+    tmpdir: Option<PathBuf>,
+    /// The entry's HOME, which `~/` rules expand against.
+    home: Option<PathBuf>,
+    /// The portable home, when the entry runs with one.
+    portable: Option<PathBuf>,
+}
+
+impl LandlockHost {
+    fn current(portable: Option<&Path>) -> Self {
+        Self {
+            cwd: env::current_dir().ok(),
+            tmpdir: Some(
+                env::var_os("TMPDIR")
+                    .filter(|value| !value.is_empty())
+                    .map(PathBuf::from)
+                    .unwrap_or_else(|| PathBuf::from("/tmp")),
+            ),
+            home: portable
+                .map(Path::to_path_buf)
+                .or_else(|| env::var_os("HOME").map(PathBuf::from)),
+            portable: portable.map(Path::to_path_buf),
+        }
+    }
+}
+
+/// Confine the launcher (and the entry it is about to exec) to `policy`. Skipped with a warning
+/// when the kernel lacks Landlock, and silently when the caller sets `SIDEBUNDLE_LANDLOCK=0`.
+/// A portable home is always writable.
+pub(crate) fn restrict(
+    bundle_root: &Path,
+    policy: &LandlockPolicy,
+    portable: Option<&Path>,
+) -> Result<()> {
+    if env::var(LANDLOCK_ENV)
+        .map(|value| matches!(value.trim(), "0" | "false" | "no" | "off"))
+        .unwrap_or(false)
+    {
+        return Ok(());
+    }
+    let abi = match abi_version() {
+        Ok(abi) => abi,
+        Err(err) => {
+            eprintln!(
+                "sidebundle launcher: warning: Landlock unavailable ({err}); running without filesystem confinement"
+            );
+            return Ok(());
+        }
+    };
+    apply(
+        abi,
+        &rules(bundle_root, policy, &LandlockHost::current(portable)),
+    )
+}
+
+/// The paths the ruleset grants, in the order they are added.
+fn rules(
+    bundle_root: &Path,
+    policy: &LandlockPolicy,
+    host: &LandlockHost,
+) -> Vec<(PathBuf, Access)> {
+    let mut rules = vec![(bundle_root.to_path_buf(), Access::ReadExec)];
+    rules.extend(
+        DEFAULT_LANDLOCK_READ
+            .iter()
+            .map(|path| (PathBuf::from(path), Access::Read)),
+    );
+    rules.extend(
+        policy
+            .read
+            .iter()
+            .map(|path| (path.clone(), Access::ReadExec)),
+    );
+    rules.extend(host.portable.clone().map(|home| (home, Access::Write)));
+    rules.extend(
+        DEV_WRITABLE
+            .iter()
+            .map(|path| (PathBuf::from(path), Access::Write)),
+    );
+    if policy.write_cwd {
+        rules.extend(host.cwd.clone().map(|cwd| (cwd, Access::Write)));
+    }
+    if policy.write_tmp {
+        rules.extend(host.tmpdir.clone().map(|tmp| (tmp, Access::Write)));
+    }
+    for path in &policy.write {
+        match LandlockPolicy::expand_home(path, host.home.as_deref()) {
+            Some(path) => rules.push((path, Access::Write)),
+            None => eprintln!(
+                "sidebundle launcher: warning: HOME is unset; Landlock rule {} skipped",
+                path.display()
+            ),
+        }
+    }
+    rules
+}
+
+pub(crate) fn abi_version() -> io::Result<u32> {
+    let abi = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            std::ptr::null::<RulesetAttr>(),
+            0usize,
+            CREATE_RULESET_VERSION,
+        )
+    };
+    if abi < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(abi as u32)
+}
+
+fn handled_access(abi: u32) -> u64 {
+    let mut handled = ABI1_ACCESS;
+    if abi >= 2 {
+        handled |= ACCESS_REFER;
+    }
+    if abi >= 3 {
+        handled |= ACCESS_TRUNCATE;
+    }
+    handled
+}
+
+fn apply(abi: u32, rules: &[(PathBuf, Access)]) -> Result<()> {
+    let handled = handled_access(abi);
+    let attr = RulesetAttr {
+        handled_access_fs: handled,
+    };
+    let fd = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            &attr as *const RulesetAttr,
+            std::mem::size_of::<RulesetAttr>(),
+            0u32,
+        )
+    };
+    if fd < 0 {
+        return Err(io::Error::last_os_error()).context("landlock_create_ruleset failed");
+    }
+    let ruleset = unsafe { OwnedFd::from_raw_fd(fd as libc::c_int) };
+
+    for (path, access) in rules {
+        let Some(parent) = open_path(path)? else {
+            continue;
+        };
+        let is_dir = fs::metadata(path)
+            .map(|meta| meta.is_dir())
+            .unwrap_or(false);
+        let mut allowed = access.bits() & handled;
+        if !is_dir {
+            allowed &= FILE_ACCESS;
+        }
+        let rule = PathBeneathAttr {
+            allowed_access: allowed,
+            parent_fd: parent.as_raw_fd(),
+        };
+        let rc = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_add_rule,
+                ruleset.as_raw_fd(),
+                RULE_PATH_BENEATH,
+                &rule as *const PathBeneathAttr,
+                0u32,
+            )
+        };
+        if rc != 0 {
+            return Err(io::Error::last_os_error())
+                .with_context(|| format!("landlock_add_rule failed for {}", path.display()));
+        }
+    }
+
+    unsafe {
+        if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+            return Err(io::Error::last_os_error()).context("failed to set no_new_privs");
+        }
+        if libc::syscall(libc::SYS_landlock_restrict_self, ruleset.as_raw_fd(), 0u32) != 0 {
+            return Err(io::Error::last_os_error()).context("landlock_restrict_self failed");
+        }
+    }
+    Ok(())
+}
+
+/// `O_PATH` handle for a rule target; missing paths are skipped.
+fn open_path(path: &Path) -> Result<Option<OwnedFd>> {
+    let path_c = os_to_cstring(path.as_os_str())?;
+    let fd = unsafe { libc::open(path_c.as_ptr(), libc::O_PATH | libc::O_CLOEXEC) };
+    if fd < 0 {
+        let err = io::Error::last_os_error();
+        if matches!(err.raw_os_error(), Some(libc::ENOENT) | Some(libc::ENOTDIR)) {
+            return Ok(None);
+        }
+        return Err(err).with_context(|| format!("failed to open {} for Landlock", path.display()));
+    }
+    Ok(Some(unsafe { OwnedFd::from_raw_fd(fd) }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rules_grant_bundle_exec_and_declared_writes() {
+        let policy = LandlockPolicy {
+            read: vec![PathBuf::from("/opt/models")],
+            write: vec![PathBuf::from("~/.local/state/tool")],
+            write_cwd: false,
+            ..LandlockPolicy::default()
+        };
+        let host = LandlockHost {
+            cwd: Some(PathBuf::from("/work")),
+            tmpdir: Some(PathBuf::from("/var/tmp")),
+            home: Some(PathBuf::from("/media/usb/tool.home")),
+            portable: Some(PathBuf::from("/media/usb/tool.home")),
+        };
+        let rules = rules(Path::new("/b"), &policy, &host);
+        let find = |path: &str| {
+            rules
+                .iter()
+                .find(|(rule, _)| rule == Path::new(path))
+                .map(|(_, access)| *access)
+        };
+        assert_eq!(find("/b"), Some(Access::ReadExec));
+        assert_eq!(find("/b/data"), None);
+        assert_eq!(find("/etc"), Some(Access::Read));
+        assert_eq!(find("/opt/models"), Some(Access::ReadExec));
+        assert_eq!(find("/var/tmp"), Some(Access::Write));
+        assert_eq!(find("/media/usb/tool.home"), Some(Access::Write));
+        assert_eq!(
+            find("/media/usb/tool.home/.local/state/tool"),
+            Some(Access::Write)
+        );
+        assert_eq!(find("/work"), None);
+    }
+
+    #[test]
+    fn ruleset_blocks_writes_outside_granted_paths() {
+        let Ok(abi) = abi_version() else {
+            eprintln!("skipping: Landlock unavailable");
+            return;
+        };
+        let bundle = tempfile::tempdir().unwrap();
+        fs::create_dir_all(bundle.path().join("data")).unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        let rules = rules(
+            bundle.path(),
+            &LandlockPolicy::default(),
+            &LandlockHost {
+                cwd: None,
+                tmpdir: None,
+                home: None,
+                portable: None,
+            },
+        );
+
+        // Landlock cannot be lifted again, so confine a child and report through its status.
+        match unsafe { libc::fork() } {
+            -1 => panic!("fork failed"),
+            0 => {
+                let code = match apply(abi, &rules) {
+                    Err(_) => 10,
+                    Ok(()) if fs::write(bundle.path().join("data/state"), b"no").is_ok() => 11,
+                    Ok(()) if fs::write(bundle.path().join("escape"), b"no").is_ok() => 12,
+                    Ok(()) if fs::write(outside.path().join("escape"), b"no").is_ok() => 13,
+                    Ok(()) if fs::read_dir(bundle.path()).is_err() => 14,
+                    Ok(()) => 0,
+                };
+                unsafe { libc::_exit(code) };
+            }
+            pid => {
+                let mut status = 0;
+                unsafe { libc::waitpid(pid, &mut status, 0) };
+                assert!(libc::WIFEXITED(status));
+                assert_eq!(libc::WEXITSTATUS(status), 0);
+            }
+        }
+    }
+}
@@ -1,14 +1,37 @@
 use anyhow::{anyhow, Context, Result};
-use serde::Deserialize;
-use sidebundle_core::{RunMode, RuntimeMetadata};
+use serde::{Deserialize, Serialize};
+use sidebundle_core::{
+    glob_match, AuxvProfile, BwrapPolicy, DevicePolicy, EnvPolicy, HostExec, HostRequirements,
+    LandlockPolicy, LdCache, ProcessPolicy, RunMode, RuntimeMetadata, StateLayer,
+    DEFAULT_PASSTHROUGH,
+};
 use std::collections::BTreeMap;
 use std::env;
-use std::ffi::{CString, OsStr};
+use std::ffi::{CString, OsStr, OsString};
 use std::fs;
 use std::io;
-use std::os::unix::ffi::OsStrExt;
-use std::os::unix::fs::symlink;
+use std::io::{Seek, SeekFrom, Write};
+use std::os::fd::{FromRawFd, IntoRawFd, OwnedFd};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
 use std::path::{Path, PathBuf};
+use userland_execve::{AuxSnapshot, ExecOptions};
+
+mod accounts;
+mod devices;
+mod diagnostics;
+mod drivers;
+mod fallback;
+mod landlock;
+mod portable;
+mod preflight;
+mod process;
+mod sandbox;
+mod state;
+
+use diagnostics::{Diagnostics, ExecPlan};
+use drivers::DriverDir;
+use sandbox::{RootFile, RootLayout};
+use state::StateDirs;
 
 fn main() {
     if let Err(err) = run() {
@@ -23,10 +46,10 @@ struct EnvRemapper<'a> {
 }
 
 impl<'a> EnvRemapper<'a> {
-    fn new(bundle_root: &'a Path) -> Self {
+    fn new(bundle_root: &'a Path, payload: &Path) -> Self {
         Self {
             bundle_root,
-            payload_prefix: bundle_root.join("payload"),
+            payload_prefix: bundle_root.join(payload),
         }
     }
 
@@ -92,184 +115,377 @@ fn run() -> Result<()> {
         .ok_or_else(|| anyhow!("invalid launcher invocation"))?
         .to_string_lossy()
         .into_owned();
+    let mut user_args: Vec<OsString> = env::args_os().skip(1).collect();
+    let reset_state = state::take_reset_flag(&mut user_args);
+    let diag = Diagnostics::from_args(&mut user_args);
+    diag.host(bundle_root, &entry_name);
 
-    let config = load_config(bundle_root, &entry_name)?;
-    match config {
-        LauncherConfig::Binary {
-            dynamic,
+    let config_path = bundle_root
+        .join("launchers")
+        .join(format!("{entry_name}.json"));
+    let config = load_config(&config_path)?;
+    diag.config(&config_path, &config);
+    let LauncherConfig { entry, shared } = config;
+    if reset_state {
+        let portable = shared.portable.as_ref().map(|dir| bundle_root.join(dir));
+        return state::reset(bundle_root, shared.state.as_ref(), portable.as_deref());
+    }
+    diag.preflight(preflight::check(&shared.host_requirements))?;
+    let (run_mode, notes) = fallback::select_run_mode(shared.run_mode, &shared.run_mode_fallback)?;
+    diag.run_mode(&notes);
+    let payload = shared
+        .root
+        .clone()
+        .unwrap_or_else(|| PathBuf::from("payload"));
+    let payload_root = bundle_root.join(&payload);
+    let map = |path: &Path| map_bundle_path(bundle_root, &payload, path, run_mode);
+    // Binaries are exec'd themselves, scripts through their interpreter.
+    let (program, program_mapped, argv, loader_argv0) = match &entry {
+        EntryConfig::Binary {
             binary,
-            linker,
-            library_paths,
-            metadata,
-            run_mode,
+            loader_argv0,
         } => {
-            let payload_root = bundle_root.join("payload");
-            let entry_host = bundle_root.join(&binary);
-            let entry_mapped = map_bundle_path(bundle_root, &binary, run_mode);
-            let _linker_mapped = linker
-                .as_ref()
-                .map(|rel| map_bundle_path(bundle_root, rel, run_mode));
-            let argv = build_binary_argv(&entry_mapped)?;
-            let env_block =
-                build_env_block(bundle_root, run_mode, &library_paths, metadata.as_ref())?;
-            match run_mode {
-                RunMode::Host => {
-                    if !dynamic {
-                        exec_static(&entry_host, &argv, &env_block)?;
-                        unreachable!();
-                    }
-                    let linker_host = linker
-                        .as_ref()
-                        .map(|rel| bundle_root.join(rel))
-                        .ok_or_else(|| anyhow!("dynamic launcher missing linker path"))?;
-                    exec_dynamic(&linker_host, &entry_host, &argv, &env_block)?;
-                    unreachable!();
-                }
-                RunMode::Bwrap => {
-                    exec_bwrap(bundle_root, &payload_root, &entry_mapped, &argv, &env_block)?;
-                    unreachable!();
-                }
-                RunMode::Chroot => {
-                    exec_chroot(bundle_root, &payload_root, &entry_mapped, &argv, &env_block)?;
-                    unreachable!();
-                }
-            }
+            let mapped = map(binary);
+            let argv = build_binary_argv(&mapped, &user_args)?;
+            (binary, mapped, argv, *loader_argv0)
         }
-        LauncherConfig::Script {
-            dynamic,
+        EntryConfig::Script {
             interpreter,
             script,
             args,
-            linker,
-            library_paths,
-            metadata,
-            run_mode,
         } => {
-            let payload_root = bundle_root.join("payload");
-            let interpreter_host = bundle_root.join(&interpreter);
-            let interpreter_mapped = map_bundle_path(bundle_root, &interpreter, run_mode);
-            let script_mapped = map_bundle_path(bundle_root, &script, run_mode);
-            let _linker_mapped = linker
-                .as_ref()
-                .map(|rel| map_bundle_path(bundle_root, rel, run_mode));
-            let argv = build_script_argv(&interpreter_mapped, &script_mapped, &args)?;
-            let env_block =
-                build_env_block(bundle_root, run_mode, &library_paths, metadata.as_ref())?;
-            match run_mode {
-                RunMode::Host => {
-                    if !dynamic {
-                        exec_static(&interpreter_host, &argv, &env_block)?;
-                        unreachable!();
+            let mapped = map(interpreter);
+            let argv = build_script_argv(&mapped, &map(script), args, &user_args)?;
+            (interpreter, mapped, argv, false)
+        }
+    };
+    let program_host = bundle_root.join(program);
+    let portable = portable::prepare(bundle_root, shared.portable.as_deref());
+    let state = state::prepare(
+        bundle_root,
+        shared.state.as_ref(),
+        run_mode,
+        portable.as_deref(),
+        &payload,
+    )?;
+    diag.state(state.as_ref());
+    let extras = RootExtras::new(
+        &payload_root,
+        run_mode,
+        portable,
+        state,
+        &device_policy(&shared.devices)?,
+        &shared.host_drivers,
+        shared.ld_so_cache,
+    );
+    let own_search_path = own_search_path(
+        run_mode,
+        shared.dynamic,
+        shared.host_exec,
+        shared.auxv.as_ref(),
+        &shared.library_search_path,
+    );
+    let env_block = build_env_block(
+        bundle_root,
+        &payload,
+        run_mode,
+        env_library_paths(own_search_path, &shared.library_paths),
+        shared.metadata.as_ref(),
+        &shared.env_policy,
+        &extras,
+    )?;
+    let SharedConfig {
+        dynamic,
+        linker,
+        auxv,
+        passthrough,
+        bwrap,
+        landlock,
+        process,
+        ..
+    } = shared;
+    match run_mode {
+        RunMode::Host => {
+            let host_exec = effective_host_exec(shared.host_exec, auxv.as_ref());
+            // Userland exec and `ld.so --argv0` both hand the program argv[0] as given, so
+            // binaries get the name they were invoked as (multi-call binaries dispatch on it).
+            let pass_argv0 = dynamic && loader_argv0;
+            let argv = match (&entry, host_exec) {
+                (EntryConfig::Script { .. }, _) => argv,
+                (_, HostExec::Loader) if !pass_argv0 => argv,
+                _ => replace_argv0(argv, &invoked)?,
+            };
+            let linker_host = host_linker(bundle_root, dynamic, linker.as_deref())?;
+            let userland = if dynamic {
+                host_exec == HostExec::Userland
+            } else {
+                auxv.is_some()
+            };
+            let loader = LoaderArgs {
+                argv0: pass_argv0,
+                library_path: own_search_path
+                    .map(|dirs| {
+                        loader_library_path(bundle_root, dirs, extras.drivers.as_ref(), &env_block)
+                    })
+                    .transpose()?,
+            };
+            let plan = host_plan(
+                linker_host.as_deref(),
+                &program_host,
+                &argv,
+                &env_block,
+                userland,
+                &loader,
+            )?;
+            if !diag.should_exec(&plan) {
+                return Ok(());
+            }
+            if let Some(policy) = &landlock {
+                landlock::restrict(bundle_root, policy, extras.portable.as_deref())?;
+            }
+            process::apply(&process)?;
+            let Some(linker_host) = linker_host else {
+                match auxv.as_ref() {
+                    Some(profile) => {
+                        exec_userland(None, &program_host, &argv, &env_block, Some(profile))?
                     }
-                    let linker_host = linker
-                        .as_ref()
-                        .map(|rel| bundle_root.join(rel))
-                        .ok_or_else(|| anyhow!("dynamic launcher missing linker path"))?;
-                    exec_dynamic(&linker_host, &interpreter_host, &argv, &env_block)?;
-                    unreachable!();
+                    None => exec_static(&program_host, &argv, &env_block)?,
                 }
-                RunMode::Bwrap => {
-                    exec_bwrap(
-                        bundle_root,
-                        &payload_root,
-                        &interpreter_mapped,
-                        &argv,
-                        &env_block,
-                    )?;
-                    unreachable!();
-                }
-                RunMode::Chroot => {
-                    exec_chroot(
-                        bundle_root,
-                        &payload_root,
-                        &interpreter_mapped,
-                        &argv,
-                        &env_block,
-                    )?;
-                    unreachable!();
+                unreachable!();
+            };
+            match host_exec {
+                HostExec::Loader => {
+                    exec_dynamic(&linker_host, &program_host, &argv, &env_block, &loader)?
                 }
+                HostExec::Userland => exec_userland(
+                    Some(&linker_host),
+                    &program_host,
+                    &argv,
+                    &env_block,
+                    auxv.as_ref(),
+                )?,
+            }
+            unreachable!();
+        }
+        RunMode::Bwrap => {
+            let plan = bwrap_command(
+                bundle_root,
+                &payload_root,
+                rooted_plan(run_mode, program_mapped, argv, env_block),
+                &bwrap_policy(bwrap)?,
+                extras,
+                &process,
+            )?;
+            if !diag.should_exec(&plan) {
+                return Ok(());
+            }
+            exec_bwrap(&plan, &process)?;
+            unreachable!();
+        }
+        RunMode::Chroot => {
+            let plan = rooted_plan(run_mode, program_mapped, argv, env_block);
+            if !diag.should_exec(&plan) {
+                return Ok(());
+            }
+            exec_chroot(bundle_root, &payload_root, &plan, &extras, &process)?;
+            unreachable!();
+        }
+        RunMode::Sandbox | RunMode::Overlay => {
+            let plan = rooted_plan(run_mode, program_mapped, argv, env_block);
+            if !diag.should_exec(&plan) {
+                return Ok(());
             }
+            let passthrough = passthrough.unwrap_or_else(default_passthrough);
+            sandbox::exec_sandbox(
+                bundle_root,
+                &payload_root,
+                root_layout(run_mode, &passthrough),
+                &plan,
+                &extras,
+                &process,
+            )?;
+            unreachable!();
         }
     }
 }
 
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize)]
+struct LauncherConfig {
+    #[serde(flatten)]
+    entry: EntryConfig,
+    #[serde(flatten)]
+    shared: SharedConfig,
+}
+
+/// What the entry runs.
+#[derive(Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
-enum LauncherConfig {
+enum EntryConfig {
     Binary {
-        dynamic: bool,
         binary: PathBuf,
-        linker: Option<PathBuf>,
-        library_paths: Vec<PathBuf>,
-        metadata: Option<RuntimeMetadata>,
-        #[serde(default = "default_run_mode")]
-        run_mode: RunMode,
+        #[serde(default)]
+        loader_argv0: bool,
     },
     Script {
-        dynamic: bool,
         interpreter: PathBuf,
         script: PathBuf,
         args: Vec<String>,
-        linker: Option<PathBuf>,
-        library_paths: Vec<PathBuf>,
-        metadata: Option<RuntimeMetadata>,
-        #[serde(default = "default_run_mode")]
-        run_mode: RunMode,
     },
 }
 
+/// How the entry runs, the same for binaries and scripts.
+#[derive(Serialize, Deserialize)]
+struct SharedConfig {
+    dynamic: bool,
+    linker: Option<PathBuf>,
+    library_paths: Vec<PathBuf>,
+    /// Directories holding the entry's own libraries, in the order the loader resolved them;
+    /// passed to the loader in host mode in place of LD_LIBRARY_PATH.
+    #[serde(default)]
+    library_search_path: Vec<PathBuf>,
+    metadata: Option<RuntimeMetadata>,
+    #[serde(default = "default_run_mode")]
+    run_mode: RunMode,
+    #[serde(default)]
+    host_exec: HostExec,
+    #[serde(default)]
+    auxv: Option<AuxvProfile>,
+    #[serde(default)]
+    env_policy: EnvPolicy,
+    /// Host paths visible in overlay mode; unset means [`DEFAULT_PASSTHROUGH`].
+    #[serde(default)]
+    passthrough: Option<Vec<PathBuf>>,
+    /// Sandbox policy for bwrap mode; unset means the strict preset.
+    #[serde(default)]
+    bwrap: Option<BwrapPolicy>,
+    /// Landlock ruleset applied before exec in host mode; unset means unconfined.
+    #[serde(default)]
+    landlock: Option<LandlockPolicy>,
+    /// rlimits, umask, nice/ionice, no_new_privs and seccomp applied before exec.
+    #[serde(default)]
+    process: ProcessPolicy,
+    /// Modes tried in order when `run_mode` is unavailable on this host.
+    #[serde(default)]
+    run_mode_fallback: Vec<RunMode>,
+    /// Minimum kernel and CPU level of the bundled ELFs, checked before exec.
+    #[serde(default)]
+    host_requirements: HostRequirements,
+    /// Portable HOME/XDG directory, relative to the bundle root; unset keeps the caller's.
+    #[serde(default)]
+    portable: Option<PathBuf>,
+    /// Persistent writable layer over the payload in isolated modes; unset means none.
+    #[serde(default)]
+    state: Option<StateLayer>,
+    /// Host devices bound in isolated modes when present.
+    #[serde(default)]
+    devices: DevicePolicy,
+    /// Driver sonames left out of the bundle, taken from the host at launch.
+    #[serde(default)]
+    host_drivers: Vec<String>,
+    /// The payload's `ld.so.cache` lists the bundled libraries, so isolated modes need no
+    /// LD_LIBRARY_PATH.
+    #[serde(default)]
+    ld_so_cache: bool,
+    /// Payload root of the entry's origin when origins are kept apart; unset is `payload`.
+    #[serde(default)]
+    root: Option<PathBuf>,
+}
+
 fn default_run_mode() -> RunMode {
     RunMode::Host
 }
 
-fn load_config(bundle_root: &Path, entry_name: &str) -> Result<LauncherConfig> {
-    let path = bundle_root
-        .join("launchers")
-        .join(format!("{entry_name}.json"));
-    let data = fs::read(&path)
+fn default_passthrough() -> Vec<PathBuf> {
+    DEFAULT_PASSTHROUGH.iter().map(PathBuf::from).collect()
+}
+
+/// The configured bwrap policy with `SIDEBUNDLE_BWRAP_*` overrides from the caller applied.
+fn bwrap_policy(configured: Option<BwrapPolicy>) -> Result<BwrapPolicy> {
+    let mut policy = configured.unwrap_or_default();
+    policy
+        .apply_overrides(|name| env::var(name).ok())
+        .map_err(|err| anyhow!("invalid bwrap override: {err}"))?;
+    Ok(policy)
+}
+
+/// The configured device policy with devices from `SIDEBUNDLE_DEVICE` appended.
+fn device_policy(configured: &DevicePolicy) -> Result<DevicePolicy> {
+    let mut policy = configured.clone();
+    policy
+        .apply_overrides(|name| env::var(name).ok())
+        .map_err(|err| anyhow!("invalid device override: {err}"))?;
+    Ok(policy)
+}
+
+fn root_layout(run_mode: RunMode, passthrough: &[PathBuf]) -> RootLayout<'_> {
+    match run_mode {
+        RunMode::Overlay => RootLayout::Overlay { passthrough },
+        _ => RootLayout::Payload,
+    }
+}
+
+fn load_config(path: &Path) -> Result<LauncherConfig> {
+    let data = fs::read(path)
         .with_context(|| format!("failed to read launcher config {}", path.display()))?;
     serde_json::from_slice(&data)
         .with_context(|| format!("invalid launcher config {}", path.display()))
 }
 
-fn build_binary_argv(entry: &Path) -> Result<Vec<CString>> {
+fn build_binary_argv(entry: &Path, user_args: &[OsString]) -> Result<Vec<CString>> {
     let mut argv = Vec::new();
     argv.push(os_to_cstring(entry.as_os_str())?);
-    for arg in env::args_os().skip(1) {
-        argv.push(os_to_cstring(&arg)?);
+    for arg in user_args {
+        argv.push(os_to_cstring(arg)?);
     }
     Ok(argv)
 }
 
-fn build_script_argv(interpreter: &Path, script: &Path, args: &[String]) -> Result<Vec<CString>> {
+fn build_script_argv(
+    interpreter: &Path,
+    script: &Path,
+    args: &[String],
+    user_args: &[OsString],
+) -> Result<Vec<CString>> {
     let mut argv = Vec::new();
     argv.push(os_to_cstring(interpreter.as_os_str())?);
     for arg in args {
         argv.push(CString::new(arg.as_bytes()).map_err(|err| anyhow!("invalid arg: {err}"))?);
     }
     argv.push(os_to_cstring(script.as_os_str())?);
-    for arg in env::args_os().skip(1) {
-        argv.push(os_to_cstring(&arg)?);
+    for arg in user_args {
+        argv.push(os_to_cstring(arg)?);
+    }
+    Ok(argv)
+}
+
+fn replace_argv0(mut argv: Vec<CString>, argv0: &OsStr) -> Result<Vec<CString>> {
+    let argv0 = os_to_cstring(argv0)?;
+    match argv.first_mut() {
+        Some(first) => *first = argv0,
+        None => argv.push(argv0),
     }
     Ok(argv)
 }
 
 fn build_env_block(
     bundle_root: &Path,
+    payload: &Path,
     run_mode: RunMode,
     library_paths: &[PathBuf],
     metadata: Option<&RuntimeMetadata>,
+    policy: &EnvPolicy,
+    extras: &RootExtras,
 ) -> Result<Vec<CString>> {
-    let mut env_map: BTreeMap<String, String> = metadata
-        .cloned()
-        .map(|meta| meta.env)
-        .unwrap_or_else(|| env::vars().collect());
+    let mut env_map = policy.compose(env::vars(), metadata.map(|meta| &meta.env));
     env_map.insert(
         "SIDEBUNDLE_ROOT".into(),
         bundle_root.to_string_lossy().into_owned(),
     );
+    if let Some(home) = &extras.portable {
+        portable::redirect(&mut env_map, home);
+    }
 
-    let remapper = EnvRemapper::new(bundle_root);
+    let remapper = EnvRemapper::new(bundle_root, payload);
 
     remapper.remap_abs_into_payload(&mut env_map, "JAVA_HOME");
 
@@ -284,7 +500,7 @@ fn build_env_block(
     let mut mapped_path_entries: Vec<String> = Vec::new();
     if run_mode == RunMode::Host {
         if let Some(path) = env_map.get("PATH").cloned() {
-            mapped_path_entries = remap_path_entries(bundle_root, run_mode, &path);
+            mapped_path_entries = remap_path_entries(bundle_root, payload, run_mode, &path);
             if !mapped_path_entries.is_empty() {
                 let mut combined = mapped_path_entries.join(":");
                 if !path.is_empty() {
@@ -311,10 +527,11 @@ fn build_env_block(
             .collect();
     }
 
-    if !library_paths.is_empty() {
+    // With a bundle-local cache the loader searches exactly as it would natively.
+    if !library_paths.is_empty() && !extras.ld_so_cache {
         let mut entries: Vec<String> = library_paths
             .iter()
-            .map(|path| map_bundle_path(bundle_root, path, run_mode))
+            .map(|path| map_bundle_path(bundle_root, payload, path, run_mode))
             .map(|p| p.to_string_lossy().into_owned())
             .collect();
         if let Some(java_home) = env_map.get("JAVA_HOME") {
@@ -330,6 +547,10 @@ fn build_env_block(
                 entries.push(parent.join("lib/server").to_string_lossy().into_owned());
             }
         }
+        // Host drivers only fill in what the bundle does not ship.
+        if let Some(drivers) = &extras.drivers {
+            entries.push(drivers.dir.to_string_lossy().into_owned());
+        }
         dedup_strings(&mut entries);
         let joined_new = entries.join(":");
         if let Some(existing) = env_map.get("LD_LIBRARY_PATH").cloned() {
@@ -346,6 +567,9 @@ fn build_env_block(
             env_map.insert("LD_LIBRARY_PATH".into(), joined_new);
         }
     }
+    if let Some(drivers) = &extras.drivers {
+        drivers.export(&mut env_map, run_mode == RunMode::Host);
+    }
 
     let mut block = Vec::new();
     for (key, value) in env_map {
@@ -357,13 +581,18 @@ fn build_env_block(
     Ok(block)
 }
 
-fn remap_path_entries(bundle_root: &Path, mode: RunMode, path: &str) -> Vec<String> {
+fn remap_path_entries(
+    bundle_root: &Path,
+    payload: &Path,
+    mode: RunMode,
+    path: &str,
+) -> Vec<String> {
     path.split(':')
         .filter(|p| !p.is_empty())
         .map(PathBuf::from)
         .filter(|p| p.is_absolute())
         .map(|p| {
-            map_bundle_path(bundle_root, &p, mode)
+            map_bundle_path(bundle_root, payload, &p, mode)
                 .to_string_lossy()
                 .into_owned()
         })
@@ -375,7 +604,8 @@ fn dedup_strings(values: &mut Vec<String>) {
     values.retain(|v| seen.insert(v.clone()));
 }
 
-fn map_bundle_path(bundle_root: &Path, rel: &Path, mode: RunMode) -> PathBuf {
+/// Where bundle path `rel` appears to the entry; isolated modes see `payload` as `/`.
+fn map_bundle_path(bundle_root: &Path, payload: &Path, rel: &Path, mode: RunMode) -> PathBuf {
     match mode {
         RunMode::Host => {
             if rel.is_absolute() {
@@ -387,10 +617,13 @@ fn map_bundle_path(bundle_root: &Path, rel: &Path, mode: RunMode) -> PathBuf {
                 bundle_root.join(rel)
             }
         }
-        RunMode::Bwrap | RunMode::Chroot => {
+        RunMode::Bwrap | RunMode::Chroot | RunMode::Sandbox | RunMode::Overlay => {
             if rel.is_absolute() {
                 return rel.to_path_buf();
             }
+            if let Ok(inside) = rel.strip_prefix(payload) {
+                return Path::new("/").join(inside);
+            }
             let mut comps = rel.components();
             if let Some(first) = comps.next() {
                 if first.as_os_str() == "payload" {
@@ -408,87 +641,19 @@ fn map_bundle_path(bundle_root: &Path, rel: &Path, mode: RunMode) -> PathBuf {
     }
 }
 
-fn ensure_payload_data(bundle_root: &Path, payload_root: &Path) -> Result<()> {
-    if let Err(err) = isolate_mount_namespace() {
-        eprintln!("sidebundle launcher: warning: failed to isolate mount namespace ({err})");
-    }
-
-    let source = bundle_root.join("data");
-    if !source.exists() {
-        return Ok(());
-    }
-    let dest = payload_root.join("data");
-    if dest.exists() {
-        // If it already has contents, assume a previous run populated it.
-        if dest
-            .read_dir()
-            .map(|mut it| it.next().is_some())
-            .unwrap_or(false)
-        {
-            return Ok(());
-        }
-    }
-
-    fs::create_dir_all(&dest)
-        .with_context(|| format!("failed to prepare chroot data dir {}", dest.display()))?;
-
-    // Prefer a bind mount to avoid duplicating data; fall back to hardlink/copy if not permitted.
-    let source_c = os_to_cstring(source.as_os_str())?;
-    let dest_c = os_to_cstring(dest.as_os_str())?;
-    unsafe {
-        if libc::mount(
-            source_c.as_ptr(),
-            dest_c.as_ptr(),
-            std::ptr::null(),
-            libc::MS_BIND | libc::MS_REC,
-            std::ptr::null(),
-        ) == 0
-        {
-            return Ok(());
-        }
-    }
-    let mount_err = std::io::Error::last_os_error();
-    mirror_data_tree(&source, &dest).with_context(|| {
-        format!("failed to mirror data into chroot (bind mount failed: {mount_err})")
-    })
-}
-
-fn mirror_data_tree(source: &Path, dest: &Path) -> Result<()> {
-    for entry in fs::read_dir(source)? {
-        let entry = entry?;
-        let src_path = entry.path();
-        let dst_path = dest.join(entry.file_name());
-        let ftype = entry.file_type()?;
-        if ftype.is_dir() {
-            fs::create_dir_all(&dst_path)?;
-            mirror_data_tree(&src_path, &dst_path)?;
-        } else if ftype.is_symlink() {
-            let target = fs::read_link(&src_path)?;
-            if dst_path.exists() {
-                let _ = fs::remove_file(&dst_path);
-            }
-            symlink(target, &dst_path)?;
-        } else {
-            // Try hardlink first to save space; fall back to copy.
-            match fs::hard_link(&src_path, &dst_path) {
-                Ok(_) => {}
-                Err(_) => {
-                    fs::copy(&src_path, &dst_path)?;
-                }
-            }
-        }
+/// Isolate into a fresh mount namespace and make mounts private so the staged root and its binds
+/// don't leak back to the host namespace.
+fn isolate_mount_namespace() -> Result<()> {
+    if unsafe { libc::unshare(libc::CLONE_NEWNS) } != 0 {
+        return Err(io::Error::last_os_error())
+            .with_context(|| "failed to unshare mount namespace");
     }
-    Ok(())
+    make_mounts_private()
 }
 
-/// Best-effort: isolate into a fresh mount namespace and make mounts private so bind mounts
-/// (payload/data) don't leak back to the host namespace.
-fn isolate_mount_namespace() -> Result<()> {
+/// Stop mounts made from here on from propagating to the namespace this one was copied from.
+fn make_mounts_private() -> Result<()> {
     unsafe {
-        if libc::unshare(libc::CLONE_NEWNS) != 0 {
-            return Err(io::Error::last_os_error())
-                .with_context(|| "failed to unshare mount namespace");
-        }
         let root = CString::new("/")?;
         if libc::mount(
             std::ptr::null(),
@@ -512,7 +677,9 @@ fn map_env_path(value: &str, mode: RunMode) -> PathBuf {
     }
     match mode {
         RunMode::Host => p.to_path_buf(),
-        RunMode::Bwrap | RunMode::Chroot => Path::new("/").join(p.strip_prefix("/").unwrap_or(p)),
+        RunMode::Bwrap | RunMode::Chroot | RunMode::Sandbox | RunMode::Overlay => {
+            Path::new("/").join(p.strip_prefix("/").unwrap_or(p))
+        }
     }
 }
 
@@ -658,19 +825,29 @@ fn exec_static(entry: &Path, argv: &[CString], envp: &[CString]) -> Result<()> {
         .with_context(|| format!("execve failed for {}", entry.display()))
 }
 
-fn exec_dynamic(linker: &Path, entry: &Path, argv: &[CString], envp: &[CString]) -> Result<()> {
+/// Options for the bundled loader when it is exec'd directly.
+#[derive(Default)]
+struct LoaderArgs {
+    /// Forward `argv[0]` through `--argv0` (loaders from glibc 2.33 on); otherwise the program
+    /// sees the entry path.
+    argv0: bool,
+    /// `--library-path`, searched instead of LD_LIBRARY_PATH by this process only.
+    library_path: Option<CString>,
+}
+
+/// Exec the bundled loader with `entry` as its program.
+fn exec_dynamic(
+    linker: &Path,
+    entry: &Path,
+    argv: &[CString],
+    envp: &[CString],
+    loader: &LoaderArgs,
+) -> Result<()> {
     use std::ptr;
 
     let linker_cstr = os_to_cstring(linker.as_os_str())?;
-    let entry_cstr = os_to_cstring(entry.as_os_str())?;
-
-    let mut argv_ptrs: Vec<*const libc::c_char> = Vec::with_capacity(argv.len() + 2);
-    // Keep argv[0] as the entry to satisfy multi-call binaries that validate argv0.
-    argv_ptrs.push(entry_cstr.as_ptr());
-    argv_ptrs.push(entry_cstr.as_ptr());
-    for arg in argv.iter().skip(1) {
-        argv_ptrs.push(arg.as_ptr());
-    }
+    let args = loader_argv(entry, argv, loader)?;
+    let mut argv_ptrs: Vec<*const libc::c_char> = args.iter().map(|arg| arg.as_ptr()).collect();
     argv_ptrs.push(ptr::null());
 
     let mut env_ptrs: Vec<*const libc::c_char> = envp.iter().map(|env| env.as_ptr()).collect();
@@ -688,106 +865,675 @@ fn exec_dynamic(linker: &Path, entry: &Path, argv: &[CString], envp: &[CString])
     })
 }
 
-fn exec_bwrap(
+/// argv for the bundled loader running `entry`, as passed by [`exec_dynamic`].
+fn loader_argv(entry: &Path, argv: &[CString], loader: &LoaderArgs) -> Result<Vec<CString>> {
+    let entry_cstr = os_to_cstring(entry.as_os_str())?;
+    let mut args = Vec::with_capacity(argv.len() + 5);
+    // Keep argv[0] as the entry to satisfy multi-call binaries that validate argv0.
+    args.push(entry_cstr.clone());
+    if let Some(path) = &loader.library_path {
+        args.push(c"--library-path".to_owned());
+        args.push(path.clone());
+    }
+    if loader.argv0 {
+        if let Some(argv0) = argv.first() {
+            args.push(c"--argv0".to_owned());
+            args.push(argv0.clone());
+        }
+    }
+    args.push(entry_cstr);
+    args.extend(argv.iter().skip(1).cloned());
+    Ok(args)
+}
+
+/// The bundled loader on the host for dynamic entries.
+fn host_linker(
     bundle_root: &Path,
-    payload_root: &Path,
+    dynamic: bool,
+    linker: Option<&Path>,
+) -> Result<Option<PathBuf>> {
+    if !dynamic {
+        return Ok(None);
+    }
+    linker
+        .map(|rel| Some(bundle_root.join(rel)))
+        .ok_or_else(|| anyhow!("dynamic launcher missing linker path"))
+}
+
+/// What host mode execs: `entry` directly when static, otherwise through the bundled `linker`,
+/// either exec'd explicitly or mapped in-process with `userland`.
+fn host_plan(
+    linker: Option<&Path>,
     entry: &Path,
     argv: &[CString],
     envp: &[CString],
+    userland: bool,
+    loader: &LoaderArgs,
+) -> Result<ExecPlan> {
+    let (method, program, argv) = match (linker, userland) {
+        (None, false) => ("execve".to_string(), entry, argv.to_vec()),
+        (None, true) => ("userland exec".to_string(), entry, argv.to_vec()),
+        (Some(linker), false) => (
+            "execve of the bundled loader".to_string(),
+            linker,
+            loader_argv(entry, argv, loader)?,
+        ),
+        (Some(linker), true) => (
+            format!("userland exec with interpreter {}", linker.display()),
+            entry,
+            argv.to_vec(),
+        ),
+    };
+    Ok(ExecPlan {
+        method,
+        program: program.to_path_buf(),
+        argv,
+        envp: envp.to_vec(),
+    })
+}
+
+/// The entry's own library search path when host mode can hand it to the bundled loader, which
+/// then stands in for the bundle-wide LD_LIBRARY_PATH. Userland exec cannot pass loader options,
+/// and configs from older packagers have no list.
+fn own_search_path<'a>(
+    run_mode: RunMode,
+    dynamic: bool,
+    host_exec: HostExec,
+    auxv: Option<&AuxvProfile>,
+    search_path: &'a [PathBuf],
+) -> Option<&'a [PathBuf]> {
+    let loader = effective_host_exec(host_exec, auxv) == HostExec::Loader;
+    let usable = run_mode == RunMode::Host && dynamic && loader && !search_path.is_empty();
+    usable.then_some(search_path)
+}
+
+/// The bundle-wide library directories LD_LIBRARY_PATH is built from: none when the loader is
+/// handed the entry's own search path instead.
+fn env_library_paths<'a>(
+    own_search_path: Option<&[PathBuf]>,
+    library_paths: &'a [PathBuf],
+) -> &'a [PathBuf] {
+    if own_search_path.is_some() {
+        &[]
+    } else {
+        library_paths
+    }
+}
+
+/// `--library-path` for an entry with its own search path: its directories, the host drivers, then
+/// the LD_LIBRARY_PATH in `envp`, which the option would otherwise hide from the entry.
+fn loader_library_path(
+    bundle_root: &Path,
+    search_path: &[PathBuf],
+    drivers: Option<&DriverDir>,
+    envp: &[CString],
+) -> Result<CString> {
+    let mut entries: Vec<Vec<u8>> = search_path
+        .iter()
+        .map(|dir| bundle_root.join(dir).into_os_string().into_vec())
+        .collect();
+    if let Some(drivers) = drivers {
+        entries.push(drivers.dir.clone().into_os_string().into_vec());
+    }
+    let inherited = envp
+        .iter()
+        .find_map(|pair| pair.to_bytes().strip_prefix(b"LD_LIBRARY_PATH="));
+    if let Some(inherited) = inherited.filter(|value| !value.is_empty()) {
+        entries.push(inherited.to_vec());
+    }
+    CString::new(entries.join(&b':')).map_err(|err| anyhow!("invalid library path: {err}"))
+}
+
+/// The loader's cache, relative to the root.
+const LD_SO_CACHE: &str = "etc/ld.so.cache";
+
+/// What the isolated run modes bring into the new root besides the payload.
+pub(crate) struct RootExtras {
+    /// Portable home, bound at its host path.
+    pub(crate) portable: Option<PathBuf>,
+    /// State layer mounted over the payload.
+    pub(crate) state: Option<StateDirs>,
+    /// passwd/group with the caller added and the `ld.so.cache` with the host drivers added,
+    /// mounted read-only over the payload's.
+    pub(crate) files: Vec<RootFile>,
+    /// Host devices from the device policy that exist on this host.
+    pub(crate) devices: Vec<PathBuf>,
+    /// Host GPU drivers standing in for the libraries the bundle left out.
+    pub(crate) drivers: Option<DriverDir>,
+    /// The loader finds every library through the root's `ld.so.cache`.
+    pub(crate) ld_so_cache: bool,
+}
+
+impl RootExtras {
+    fn new(
+        payload_root: &Path,
+        run_mode: RunMode,
+        portable: Option<PathBuf>,
+        state: Option<StateDirs>,
+        devices: &DevicePolicy,
+        host_drivers: &[String],
+        ld_so_cache: bool,
+    ) -> Self {
+        let drivers = DriverDir::prepare(host_drivers, run_mode);
+        if run_mode == RunMode::Host {
+            return Self {
+                portable,
+                state,
+                files: Vec::new(),
+                devices: Vec::new(),
+                drivers,
+                ld_so_cache: false,
+            };
+        }
+        let home = portable
+            .clone()
+            .or_else(|| env::var_os("HOME").map(PathBuf::from));
+        let mut files = accounts::runtime_files(payload_root, state.as_ref(), home.as_deref());
+        let cache = ld_so_cache
+            .then(|| {
+                fs::read(state::current_path(
+                    payload_root,
+                    state.as_ref(),
+                    LD_SO_CACHE,
+                ))
+                .ok()
+            })
+            .flatten()
+            .and_then(|data| LdCache::parse(&data));
+        let ld_so_cache = match (&cache, &drivers) {
+            (None, _) => false,
+            (Some(_), None) => true,
+            // Drivers are found through the cache too, after the bundled libraries.
+            (Some(cache), Some(drivers)) => match drivers.extend_cache(cache) {
+                Some(extended) => {
+                    files.push(RootFile {
+                        path: LD_SO_CACHE,
+                        contents: extended.to_bytes(),
+                    });
+                    true
+                }
+                None => false,
+            },
+        };
+        Self {
+            files,
+            devices: devices::resolve(devices),
+            drivers,
+            ld_so_cache,
+            portable,
+            state,
+        }
+    }
+}
+
+/// What chroot, sandbox and overlay modes exec once inside the payload root.
+fn rooted_plan(
+    run_mode: RunMode,
+    entry: PathBuf,
+    argv: Vec<CString>,
+    envp: Vec<CString>,
+) -> ExecPlan {
+    ExecPlan {
+        method: format!("execve inside the payload root (run_mode={run_mode})"),
+        program: entry,
+        argv,
+        envp,
+    }
+}
+
+/// Where an isolated entry starts, given the caller's working directory.
+#[derive(Debug, PartialEq, Eq)]
+enum CwdPlan {
+    /// Not visible inside the sandbox; start in `/`.
+    Root,
+    /// Already visible at this path inside the sandbox.
+    Enter(PathBuf),
+    /// Bind the host directory at the same path, then start there.
+    Bind(PathBuf),
+}
+
+impl CwdPlan {
+    /// The directory to start in, if not `/`.
+    fn target(&self) -> Option<&Path> {
+        match self {
+            CwdPlan::Root => None,
+            CwdPlan::Enter(path) | CwdPlan::Bind(path) => Some(path),
+        }
+    }
+
+    /// Perform a pending bind into `root` before it is sealed; on failure, such as when the mount
+    /// point would have to be added to the payload, the entry starts in `/` and the caller is told
+    /// which run mode keeps host files visible.
+    fn bind_into(self, root: &sandbox::StagedRoot, mode: RunMode) -> CwdPlan {
+        let CwdPlan::Bind(cwd) = self else {
+            return self;
+        };
+        let source = sandbox::host_path(&root.host, &cwd);
+        let bound = root
+            .mount_point(&cwd, true)
+            .and_then(|target| sandbox::bind(&source, &target));
+        match bound {
+            Ok(()) => CwdPlan::Enter(cwd),
+            Err(err) => {
+                eprintln!(
+                    "sidebundle launcher: warning: cannot make working directory {} visible in \
+                     run_mode={mode} ({err:#}); starting in /. Use --run-mode overlay or host to \
+                     work on host files.",
+                    cwd.display()
+                );
+                CwdPlan::Root
+            }
+        }
+    }
+
+    /// chdir into the planned directory once inside the new root, falling back to `/`.
+    fn enter(&self) -> Result<()> {
+        if let Some(dir) = self.target() {
+            let dir_c = os_to_cstring(dir.as_os_str())?;
+            if unsafe { libc::chdir(dir_c.as_ptr()) } == 0 {
+                return Ok(());
+            }
+            eprintln!(
+                "sidebundle launcher: warning: cannot enter working directory {} ({}); starting in /",
+                dir.display(),
+                io::Error::last_os_error()
+            );
+        }
+        if unsafe { libc::chdir(c"/".as_ptr()) } != 0 {
+            return Err(io::Error::last_os_error()).context("chdir to new root failed");
+        }
+        Ok(())
+    }
+}
+
+/// Decide how the caller's working directory `cwd` stays usable in a root built from
+/// `payload_root`. Paths the payload already provides are entered as-is rather than shadowed, so
+/// running from e.g. `/usr` does not hide bundled libraries; `hint` explains how to enable
+/// binding when `bind` is off.
+fn plan_cwd(payload_root: &Path, cwd: Option<&Path>, bind: bool, hint: &str) -> CwdPlan {
+    let Some(cwd) = cwd else {
+        eprintln!("sidebundle launcher: warning: working directory is unavailable; starting in /");
+        return CwdPlan::Root;
+    };
+    if let Ok(inside) = cwd.strip_prefix(payload_root) {
+        return CwdPlan::Enter(Path::new("/").join(inside));
+    }
+    let rel = cwd.strip_prefix("/").unwrap_or(cwd);
+    if rel.as_os_str().is_empty() {
+        return CwdPlan::Root;
+    }
+    if holds_payload_files(&payload_root.join(rel)) {
+        eprintln!(
+            "sidebundle launcher: warning: working directory {} is provided by the bundle payload; \
+             relative paths resolve against the bundled copy",
+            cwd.display()
+        );
+        return CwdPlan::Enter(cwd.to_path_buf());
+    }
+    if !bind {
+        eprintln!(
+            "sidebundle launcher: warning: working directory {} is not visible in the sandbox; \
+             starting in /. {hint}",
+            cwd.display()
+        );
+        return CwdPlan::Root;
+    }
+    CwdPlan::Bind(cwd.to_path_buf())
+}
+
+/// Whether the payload ships anything at `path`. Chains of empty directories are mount points,
+/// shipped by the packager or left behind by older launchers, and do not count.
+fn holds_payload_files(path: &Path) -> bool {
+    match fs::symlink_metadata(path) {
+        Ok(meta) if meta.is_dir() => fs::read_dir(path)
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .any(|entry| holds_payload_files(&entry.path()))
+            })
+            .unwrap_or(true),
+        Ok(_) => true,
+        Err(_) => false,
+    }
+}
+
+/// Replaying an auxv profile is only possible when the launcher builds the initial stack itself.
+fn effective_host_exec(host_exec: HostExec, auxv: Option<&AuxvProfile>) -> HostExec {
+    if auxv.is_some() {
+        HostExec::Userland
+    } else {
+        host_exec
+    }
+}
+
+/// Map the entry and the bundled loader into the launcher process (userland execve) instead of
+/// exec'ing `ld-linux` explicitly, so the program sees the argv0 it was given rather than the
+/// loader invocation. `linker` is `None` for static entries. When `auxv` is set, its platform and
+/// hwcaps replace the host values on the new stack.
+fn exec_userland(
+    linker: Option<&Path>,
+    entry: &Path,
+    argv: &[CString],
+    envp: &[CString],
+    auxv: Option<&AuxvProfile>,
 ) -> Result<()> {
+    // The loader panics on unreadable inputs; surface those as regular launcher errors first.
+    for path in linker.into_iter().chain([entry]) {
+        fs::File::open(path)
+            .with_context(|| format!("userland exec cannot open {}", path.display()))?;
+    }
+    let mut options = ExecOptions::new(entry);
+    options
+        .args(argv)
+        .env_pairs(envp)
+        .override_interpreter(linker);
+    if let Some(profile) = auxv {
+        options.aux_snapshot(aux_snapshot(profile));
+    }
+    userland_execve::exec_with_options(options)
+}
+
+fn aux_snapshot(profile: &AuxvProfile) -> AuxSnapshot {
+    let mut entries = Vec::new();
+    if let Some(hwcap) = profile.hwcap {
+        entries.push((libc::AT_HWCAP, hwcap));
+    }
+    if let Some(hwcap2) = profile.hwcap2 {
+        entries.push((libc::AT_HWCAP2, hwcap2));
+    }
+    AuxSnapshot::new(entries).with_platform(profile.platform.clone())
+}
+
+/// The bwrap invocation that runs `inner` in the payload root. The seccomp program, if any, is
+/// already in an inherited memfd.
+fn bwrap_command(
+    bundle_root: &Path,
+    payload_root: &Path,
+    inner: ExecPlan,
+    policy: &BwrapPolicy,
+    extras: RootExtras,
+    process: &ProcessPolicy,
+) -> Result<ExecPlan> {
     let bwrap_bin = find_bwrap()
         .context("bubblewrap (bwrap) not found in PATH; required for run_mode=bwrap")?;
 
-    let mut args: Vec<CString> = vec![
-        os_to_cstring(bwrap_bin.as_os_str())?,
-        CString::new("--bind")?,
-        CString::new(payload_root.to_string_lossy().to_string())?,
-        CString::new("/")?,
-    ];
     let data_root = bundle_root.join("data");
     if data_root.exists() {
         // ensure target mountpoint exists under new root (/data)
         let _ = std::fs::create_dir_all(payload_root.join("data"));
-        args.push(CString::new("--bind")?);
-        args.push(CString::new(data_root.to_string_lossy().to_string())?);
-        args.push(CString::new("/data")?);
     }
-    args.push(CString::new("--proc")?);
-    args.push(CString::new("/proc")?);
+    let data = bwrap_data(&extras)?;
+    let host = BwrapHost {
+        cwd: plan_cwd(
+            payload_root,
+            env::current_dir().ok().as_deref(),
+            policy.bind_cwd,
+            "Rebuild without --bwrap-no-bind-cwd or set SIDEBUNDLE_BWRAP_BIND_CWD=1 to bind it.",
+        ),
+        home: env::var_os("HOME").map(PathBuf::from),
+        portable: extras.portable,
+        state: extras.state,
+        data,
+        drivers: extras
+            .drivers
+            .iter()
+            .flat_map(|drivers| &drivers.drivers.libraries)
+            .map(|(name, library)| (library.clone(), Path::new(drivers::DRIVER_DIR).join(name)))
+            .collect(),
+        devices: extras.devices,
+    };
+    let mut args = vec![os_to_cstring(bwrap_bin.as_os_str())?];
+    args.extend(bwrap_args(bundle_root, payload_root, policy, &host)?);
+    if let Some(fd) = process::seccomp_memfd(process)? {
+        // Stays open across the exec for bwrap to read.
+        args.push(CString::new("--seccomp")?);
+        args.push(CString::new(fd.into_raw_fd().to_string())?);
+    }
+    args.push(CString::new("--")?);
+
+    // For bwrap/chroot we rely on the kernel to use the bundled PT_INTERP inside the sandbox.
+    args.push(os_to_cstring(inner.program.as_os_str())?);
+    args.extend(inner.argv.into_iter().skip(1));
+    let envp = passthrough_env(&inner.envp, &policy.env_passthrough, env::vars_os())?;
+    Ok(ExecPlan {
+        method: "bubblewrap".to_string(),
+        program: bwrap_bin,
+        argv: args,
+        envp,
+    })
+}
+
+/// Generated root files and driver vendor files as inherited memfds, for `--ro-bind-data`.
+fn bwrap_data(extras: &RootExtras) -> Result<Vec<(i32, PathBuf)>> {
+    let files = extras
+        .files
+        .iter()
+        .map(|file| (Path::new("/").join(file.path), file.contents.as_slice()));
+    let icds = extras.drivers.iter().flat_map(|drivers| {
+        let dir = Path::new(drivers::DRIVER_DIR);
+        let icds = drivers.drivers.icds.iter();
+        icds.map(move |(name, contents)| (dir.join(name), contents.as_bytes()))
+    });
+    files
+        .chain(icds)
+        .map(|(dest, contents)| Ok((data_memfd(&dest, contents)?.into_raw_fd(), dest)))
+        .collect()
+}
+
+/// An inheritable memfd holding `contents`, for bwrap to read and bind at `dest`.
+fn data_memfd(dest: &Path, contents: &[u8]) -> Result<OwnedFd> {
+    // Left inheritable on purpose: bwrap reads the contents from this descriptor.
+    let fd = unsafe { libc::memfd_create(c"sidebundle-data".as_ptr(), 0) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error())
+            .with_context(|| format!("memfd_create failed for {}", dest.display()));
+    }
+    let mut memfd = fs::File::from(unsafe { OwnedFd::from_raw_fd(fd) });
+    memfd
+        .write_all(contents)
+        .with_context(|| format!("failed to write {}", dest.display()))?;
+    memfd.seek(SeekFrom::Start(0))?;
+    Ok(memfd.into())
+}
+
+fn exec_bwrap(plan: &ExecPlan, process: &ProcessPolicy) -> Result<()> {
+    process::apply_limits(process)?;
+    exec_static(&plan.program, &plan.argv, &plan.envp)
+        .context("execve failed for bubblewrap launcher")
+}
+
+/// Caller state a bwrap policy may expose inside the sandbox.
+struct BwrapHost {
+    cwd: CwdPlan,
+    home: Option<PathBuf>,
+    /// Portable home, always bound read-write at its host path.
+    portable: Option<PathBuf>,
+    /// State layer mounted over the payload root.
+    state: Option<StateDirs>,
+    /// Inherited memfds holding generated files, with their path inside the sandbox.
+    data: Vec<(i32, PathBuf)>,
+    /// Host driver libraries with their path inside the sandbox.
+    drivers: Vec<(PathBuf, PathBuf)>,
+    /// Host devices from the device policy, bound at the same path.
+    devices: Vec<PathBuf>,
+}
+
+/// bwrap options (up to, not including, `--`) for `policy`. Later binds stack on earlier ones, so
+/// the policy's binds come after the tmpfs mounts they may land in.
+fn bwrap_args(
+    bundle_root: &Path,
+    payload_root: &Path,
+    policy: &BwrapPolicy,
+    host: &BwrapHost,
+) -> Result<Vec<CString>> {
+    let mut args: Vec<CString> = Vec::new();
+    let mut push = |parts: &[&OsStr]| -> Result<()> {
+        for part in parts {
+            args.push(os_to_cstring(part)?);
+        }
+        Ok(())
+    };
+    let os = |value: &'static str| OsStr::new(value);
+
+    match &host.state {
+        // Needs bwrap 0.7 or newer.
+        Some(state) => push(&[
+            os("--overlay-src"),
+            payload_root.as_os_str(),
+            os("--overlay"),
+            state.upper.as_os_str(),
+            state.work.as_os_str(),
+            os("/"),
+        ])?,
+        None => push(&[os("--bind"), payload_root.as_os_str(), os("/")])?,
+    }
+    let data_root = bundle_root.join("data");
+    if data_root.exists() {
+        push(&[os("--bind"), data_root.as_os_str(), os("/data")])?;
+    }
+    push(&[os("--proc"), os("/proc")])?;
     // minimal device/tempo mounts
-    args.push(CString::new("--dev-bind")?);
-    args.push(CString::new("/dev/null")?);
-    args.push(CString::new("/dev/null")?);
-    args.push(CString::new("--dev-bind")?);
-    args.push(CString::new("/dev/zero")?);
-    args.push(CString::new("/dev/zero")?);
-    args.push(CString::new("--dev-bind")?);
-    args.push(CString::new("/dev/tty")?);
-    args.push(CString::new("/dev/tty")?);
-    args.push(CString::new("--dev-bind")?);
-    args.push(CString::new("/dev/urandom")?);
-    args.push(CString::new("/dev/urandom")?);
-    args.push(CString::new("--tmpfs")?);
-    args.push(CString::new("/tmp")?);
-    args.push(CString::new("--tmpfs")?);
-    args.push(CString::new("/run")?);
-    args.push(CString::new("--tmpfs")?);
-    args.push(CString::new("/dev/shm")?);
+    for dev in ["/dev/null", "/dev/zero", "/dev/tty", "/dev/urandom"] {
+        push(&[os("--dev-bind"), os(dev), os(dev)])?;
+    }
+    for dir in ["/tmp", "/run", "/dev/shm"] {
+        push(&[os("--tmpfs"), os(dir)])?;
+    }
     // DNS/hosts
     for file in ["/etc/resolv.conf", "/etc/hosts"] {
-        args.push(CString::new("--ro-bind")?);
-        args.push(CString::new(file)?);
-        args.push(CString::new(file)?);
+        push(&[os("--ro-bind"), os(file), os(file)])?;
+    }
+    for (source, dest) in &host.drivers {
+        push(&[os("--ro-bind"), source.as_os_str(), dest.as_os_str()])?;
+    }
+    for (fd, dest) in &host.data {
+        push(&[
+            os("--ro-bind-data"),
+            OsStr::new(&fd.to_string()),
+            dest.as_os_str(),
+        ])?;
     }
-    args.push(CString::new("--die-with-parent")?);
-    args.push(CString::new("--unshare-all")?);
-    args.push(CString::new("--")?);
 
-    // For bwrap/chroot we rely on the kernel to use the bundled PT_INTERP inside the sandbox.
-    args.push(os_to_cstring(entry.as_os_str())?);
-    for arg in argv.iter().skip(1) {
-        args.push(arg.clone());
+    if policy.bind_home {
+        if let Some(home) = host.home.as_deref().filter(|home| home.is_absolute()) {
+            push(&[os("--bind"), home.as_os_str(), home.as_os_str()])?;
+        }
+    }
+    if let Some(home) = &host.portable {
+        push(&[os("--bind"), home.as_os_str(), home.as_os_str()])?;
+    }
+    if let CwdPlan::Bind(cwd) = &host.cwd {
+        push(&[os("--bind"), cwd.as_os_str(), cwd.as_os_str()])?;
+    }
+    for bind in &policy.ro_binds {
+        push(&[
+            os("--ro-bind"),
+            bind.source.as_os_str(),
+            bind.dest.as_os_str(),
+        ])?;
+    }
+    for bind in &policy.rw_binds {
+        push(&[os("--bind"), bind.source.as_os_str(), bind.dest.as_os_str()])?;
+    }
+    for device in &host.devices {
+        push(&[os("--dev-bind-try"), device.as_os_str(), device.as_os_str()])?;
+    }
+    if let Some(cwd) = host.cwd.target() {
+        push(&[os("--chdir"), cwd.as_os_str()])?;
     }
 
-    let mut argv_ptrs: Vec<*const libc::c_char> = args.iter().map(|c| c.as_ptr()).collect();
-    argv_ptrs.push(std::ptr::null());
-    let mut env_ptrs: Vec<*const libc::c_char> = envp.iter().map(|e| e.as_ptr()).collect();
-    env_ptrs.push(std::ptr::null());
+    push(&[os("--die-with-parent"), os("--unshare-all")])?;
+    if policy.share_net {
+        push(&[os("--share-net")])?;
+    }
+    Ok(args)
+}
 
-    unsafe {
-        libc::execve(args[0].as_ptr(), argv_ptrs.as_ptr(), env_ptrs.as_ptr());
+/// Copy host vars matching `patterns` into `envp`, replacing values the env policy produced.
+fn passthrough_env(
+    envp: &[CString],
+    patterns: &[String],
+    host: impl IntoIterator<Item = (std::ffi::OsString, std::ffi::OsString)>,
+) -> Result<Vec<CString>> {
+    if patterns.is_empty() {
+        return Ok(envp.to_vec());
+    }
+    let passed: BTreeMap<Vec<u8>, Vec<u8>> = host
+        .into_iter()
+        .filter(|(key, _)| {
+            let key = key.to_string_lossy();
+            patterns.iter().any(|pattern| glob_match(pattern, &key))
+        })
+        .map(|(key, value)| (key.as_bytes().to_vec(), value.as_bytes().to_vec()))
+        .collect();
+    let mut block: Vec<CString> = envp
+        .iter()
+        .filter(|entry| {
+            let bytes = entry.as_bytes();
+            let key = bytes.split(|b| *b == b'=').next().unwrap_or(bytes);
+            !passed.contains_key(key)
+        })
+        .cloned()
+        .collect();
+    for (key, value) in passed {
+        let mut entry = key;
+        entry.push(b'=');
+        entry.extend(value);
+        block.push(CString::new(entry).map_err(|err| anyhow!("invalid env var: {err}"))?);
     }
-    Err(std::io::Error::last_os_error()).with_context(|| "execve failed for bubblewrap launcher")
+    Ok(block)
 }
 
+/// Run `plan` in a root built from `payload_root` without new pid, ipc or uts namespaces, as root or
+/// inside a user namespace. Only returns on setup errors.
 fn exec_chroot(
     bundle_root: &Path,
     payload_root: &Path,
-    entry: &Path,
-    argv: &[CString],
-    envp: &[CString],
+    plan: &ExecPlan,
+    extras: &RootExtras,
+    process: &ProcessPolicy,
 ) -> Result<()> {
-    ensure_payload_data(bundle_root, payload_root)?;
-    unsafe {
-        let root_c = os_to_cstring(payload_root.as_os_str())?;
-        if libc::chroot(root_c.as_ptr()) != 0 {
-            return Err(std::io::Error::last_os_error())
-                .with_context(|| "chroot failed for launcher");
-        }
-        if libc::chdir(c"/".as_ptr() as *const libc::c_char) != 0 {
-            return Err(std::io::Error::last_os_error())
-                .with_context(|| "chdir after chroot failed");
+    let mut unshared = false;
+    if unsafe { libc::geteuid() } != 0 {
+        // Ordinary users may build and enter a root inside a user namespace mapped to their own uid.
+        // When the kernel refuses one, fall through to the privileged path and its error.
+        match sandbox::unshare_user_namespace(libc::CLONE_NEWNS) {
+            Ok((uid, gid)) => {
+                sandbox::write_id_maps(uid, gid)?;
+                unshared = true;
+            }
+            Err(err) => eprintln!(
+                "sidebundle launcher: warning: user namespace unavailable ({err}); trying privileged chroot"
+            ),
         }
     }
-    // Once chrooted, the PT_INTERP inside the payload points to bundled ld-linux; exec the entry
+    // Every mount below goes into this one namespace.
+    if unshared {
+        make_mounts_private()?;
+    } else {
+        isolate_mount_namespace()?;
+    }
+    if let Some(state) = &extras.state {
+        state::mount(payload_root, state)?;
+    }
+    sandbox::mount_files(payload_root, &extras.files)?;
+    let cwd = plan_cwd(payload_root, env::current_dir().ok().as_deref(), true, "");
+    let root = sandbox::StagedRoot::new(payload_root)?;
+    sandbox::mount_system(&root, bundle_root, extras, RunMode::Chroot)?;
+    let cwd = cwd.bind_into(&root, RunMode::Chroot);
+    root.seal()?;
+    cwd.enter()?;
+    process::apply(process)?;
+    // Once rooted, the PT_INTERP inside the payload points to bundled ld-linux; exec the entry
     // directly so /proc/self/exe matches the intended binary (important for multi-call binaries).
-    exec_static(entry, argv, envp)
+    exec_static(&plan.program, &plan.argv, &plan.envp)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{EnvRemapper, RunMode};
+    use super::{map_bundle_path, AuxvProfile, EnvRemapper, RunMode};
     use std::collections::BTreeMap;
+    use std::ffi::{CString, OsStr};
     use std::path::Path;
 
     #[test]
@@ -815,7 +1561,7 @@ mod tests {
     #[test]
     fn remap_abs_into_payload_is_idempotent() {
         let bundle_root = Path::new("/tmp/bundle");
-        let remapper = EnvRemapper::new(bundle_root);
+        let remapper = EnvRemapper::new(bundle_root, Path::new("payload"));
         let mut env_map: BTreeMap<String, String> = BTreeMap::new();
 
         env_map.insert("JAVA_HOME".into(), "/usr/lib/jvm/java".into());
@@ -835,7 +1581,7 @@ mod tests {
     #[test]
     fn remap_abs_list_into_payload_keeps_relative_entries() {
         let bundle_root = Path::new("/tmp/bundle");
-        let remapper = EnvRemapper::new(bundle_root);
+        let remapper = EnvRemapper::new(bundle_root, Path::new("payload"));
         let mut env_map: BTreeMap<String, String> = BTreeMap::new();
         env_map.insert("PYTHONPATH".into(), "rel:/usr/lib/python3.10:/x".into());
 
@@ -848,4 +1594,626 @@ mod tests {
             "rel:/tmp/bundle/payload/usr/lib/python3.10:/tmp/bundle/payload/x"
         );
     }
+
+    #[test]
+    fn replace_argv0_keeps_remaining_args() {
+        let argv = vec![
+            CString::new("/bundle/payload/bin/busybox").unwrap(),
+            CString::new("--help").unwrap(),
+        ];
+        let argv = super::replace_argv0(argv, OsStr::new("/bundle/bin/ls")).unwrap();
+        assert_eq!(argv[0].to_str().unwrap(), "/bundle/bin/ls");
+        assert_eq!(argv[1].to_str().unwrap(), "--help");
+    }
+
+    /// Runs `exec` in a forked child and returns what it wrote to stdout.
+    fn capture_child_stdout(exec: impl FnOnce()) -> String {
+        use std::io::Read;
+        use std::os::fd::FromRawFd;
+
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let pid = unsafe { libc::fork() };
+        assert!(pid >= 0, "fork failed");
+        if pid == 0 {
+            unsafe {
+                libc::dup2(fds[1], libc::STDOUT_FILENO);
+                libc::close(fds[0]);
+                libc::close(fds[1]);
+            }
+            let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(exec));
+            unsafe { libc::_exit(127) };
+        }
+        unsafe { libc::close(fds[1]) };
+        let mut output = String::new();
+        let mut reader = unsafe { std::fs::File::from_raw_fd(fds[0]) };
+        reader.read_to_string(&mut output).unwrap();
+        let mut status = 0;
+        unsafe { libc::waitpid(pid, &mut status, 0) };
+        output
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn userland_exec_preserves_argv0_for_applet_dispatch() {
+        let linker = Path::new("/lib64/ld-linux-x86-64.so.2");
+        let Ok(shell) = std::fs::canonicalize("/bin/sh") else {
+            return;
+        };
+        if !linker.exists() {
+            return;
+        }
+        // Busybox-style dispatch: the applet is chosen from the basename of argv0.
+        let dispatch =
+            r#"case "${0##*/}" in hello) echo applet:hello ;; *) echo applet:none ;; esac"#;
+        let run = |argv0: &str| {
+            let argv = vec![
+                CString::new(argv0).unwrap(),
+                CString::new("-c").unwrap(),
+                CString::new(dispatch).unwrap(),
+            ];
+            let envp = vec![CString::new("PATH=/usr/bin:/bin").unwrap()];
+            capture_child_stdout(|| {
+                let _ = super::exec_userland(Some(linker), &shell, &argv, &envp, None);
+            })
+        };
+
+        assert_eq!(run("/opt/bundle/bin/hello").trim(), "applet:hello");
+        assert_eq!(run("/opt/bundle/payload/bin/busybox").trim(), "applet:none");
+    }
+
+    #[test]
+    fn origin_roots_map_to_the_isolated_root() {
+        let bundle = Path::new("/opt/bundle");
+        let root = Path::new("payload/.origins/image-app");
+        let binary = root.join("usr/bin/app");
+        assert_eq!(
+            map_bundle_path(bundle, root, &binary, RunMode::Chroot),
+            Path::new("/usr/bin/app")
+        );
+        assert_eq!(
+            map_bundle_path(bundle, root, &binary, RunMode::Host),
+            bundle.join(&binary)
+        );
+        assert_eq!(
+            map_bundle_path(
+                bundle,
+                Path::new("payload"),
+                Path::new("payload/usr/lib"),
+                RunMode::Bwrap
+            ),
+            Path::new("/usr/lib")
+        );
+    }
+
+    #[test]
+    fn global_library_path_is_dropped_only_for_own_search_path() {
+        use super::{EntryConfig, LauncherConfig, RootExtras};
+
+        let bundle = Path::new("/b");
+        let payload = Path::new("payload");
+        let ld_library_path = |config: serde_json::Value| {
+            let LauncherConfig { entry, shared } = serde_json::from_value(config).unwrap();
+            assert!(matches!(
+                entry,
+                EntryConfig::Binary {
+                    loader_argv0: true,
+                    ..
+                }
+            ));
+            let own = super::own_search_path(
+                shared.run_mode,
+                shared.dynamic,
+                shared.host_exec,
+                shared.auxv.as_ref(),
+                &shared.library_search_path,
+            );
+            let extras = RootExtras::new(
+                &bundle.join(payload),
+                RunMode::Host,
+                None,
+                None,
+                &Default::default(),
+                &[],
+                false,
+            );
+            let envp = super::build_env_block(
+                bundle,
+                payload,
+                RunMode::Host,
+                super::env_library_paths(own, &shared.library_paths),
+                None,
+                &shared.env_policy,
+                &extras,
+            )
+            .unwrap();
+            envp.iter()
+                .find_map(|pair| pair.to_str().ok()?.strip_prefix("LD_LIBRARY_PATH="))
+                .is_some_and(|value| value.split(':').any(|dir| dir == "/b/payload/usr/lib"))
+        };
+        let config = serde_json::json!({
+            "type": "binary",
+            "binary": "payload/usr/bin/app",
+            "loader_argv0": true,
+            "dynamic": true,
+            "linker": "payload/lib64/ld-linux-x86-64.so.2",
+            "library_paths": ["payload/usr/lib"],
+            "library_search_path": ["payload/opt/app/lib"],
+            "metadata": null,
+        });
+        assert!(!ld_library_path(config.clone()));
+
+        // Userland exec, also implied by an auxv profile, cannot pass `--library-path`.
+        let mut userland = config.clone();
+        userland["host_exec"] = "userland".into();
+        assert!(ld_library_path(userland));
+        let mut auxv = config.clone();
+        auxv["auxv"] = serde_json::json!({ "platform": "x86_64" });
+        assert!(ld_library_path(auxv));
+        // Configs from packagers that did not record the entry's own search path.
+        let mut legacy = config;
+        legacy
+            .as_object_mut()
+            .unwrap()
+            .remove("library_search_path");
+        assert!(ld_library_path(legacy));
+    }
+
+    #[test]
+    fn host_loader_gets_the_entry_search_path() {
+        use super::{HostExec, LoaderArgs};
+        use std::path::PathBuf;
+
+        let search = [PathBuf::from("payload/opt/app/lib")];
+        let own = |run_mode, host_exec| {
+            super::own_search_path(run_mode, true, host_exec, None, &search).is_some()
+        };
+        assert!(own(RunMode::Host, HostExec::Loader));
+        assert!(!own(RunMode::Host, HostExec::Userland));
+        assert!(!own(RunMode::Chroot, HostExec::Loader));
+        assert!(super::own_search_path(RunMode::Host, true, HostExec::Loader, None, &[]).is_none());
+
+        let envp = [CString::new("LD_LIBRARY_PATH=/opt/extra").unwrap()];
+        let path = super::loader_library_path(Path::new("/b"), &search, None, &envp).unwrap();
+        assert_eq!(path.to_str().unwrap(), "/b/payload/opt/app/lib:/opt/extra");
+        let loader = LoaderArgs {
+            argv0: true,
+            library_path: Some(path),
+        };
+        let argv = [CString::new("app").unwrap(), CString::new("-v").unwrap()];
+        let args = super::loader_argv(Path::new("/b/payload/app"), &argv, &loader).unwrap();
+        let expected = [
+            "/b/payload/app",
+            "--library-path",
+            "/b/payload/opt/app/lib:/opt/extra",
+            "--argv0",
+            "app",
+            "/b/payload/app",
+            "-v",
+        ]
+        .map(|arg| CString::new(arg).unwrap());
+        assert_eq!(args, expected);
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn loader_exec_forwards_argv0() {
+        let linker = Path::new("/lib64/ld-linux-x86-64.so.2");
+        let Ok(shell) = std::fs::canonicalize("/bin/sh") else {
+            return;
+        };
+        let supported = std::fs::read(linker)
+            .map(|bytes| bytes.windows(7).any(|window| window == b"--argv0"))
+            .unwrap_or(false);
+        if !supported {
+            return;
+        }
+        let argv = vec![
+            CString::new("/opt/bundle/bin/hello").unwrap(),
+            CString::new("-c").unwrap(),
+            CString::new("echo $0").unwrap(),
+        ];
+        let envp = vec![CString::new("PATH=/usr/bin:/bin").unwrap()];
+        let run = |argv0: bool| {
+            let loader = super::LoaderArgs {
+                argv0,
+                library_path: None,
+            };
+            capture_child_stdout(|| {
+                let _ = super::exec_dynamic(linker, &shell, &argv, &envp, &loader);
+            })
+        };
+
+        assert_eq!(run(true).trim(), "/opt/bundle/bin/hello");
+        assert_eq!(run(false).trim(), shell.to_str().unwrap());
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn userland_exec_replays_auxv_profile() {
+        let linker = Path::new("/lib64/ld-linux-x86-64.so.2");
+        let Ok(shell) = std::fs::canonicalize("/bin/sh") else {
+            return;
+        };
+        if !linker.exists() {
+            return;
+        }
+        let profile = AuxvProfile {
+            platform: Some("sbtest".into()),
+            hwcap: Some(0x1234),
+            hwcap2: Some(0x2),
+        };
+        let argv = vec![
+            CString::new("sh").unwrap(),
+            CString::new("-c").unwrap(),
+            CString::new("true").unwrap(),
+        ];
+        // ld.so prints the auxv it was handed, which is the replayed one.
+        let envp = vec![CString::new("LD_SHOW_AUXV=1").unwrap()];
+        let output = capture_child_stdout(|| {
+            let _ = super::exec_userland(Some(linker), &shell, &argv, &envp, Some(&profile));
+        });
+        let field = |name: &str| {
+            output
+                .lines()
+                .find(|line| line.starts_with(name))
+                .map(|line| line[name.len()..].trim().to_string())
+        };
+        assert_eq!(field("AT_PLATFORM:").as_deref(), Some("sbtest"));
+        assert_eq!(field("AT_HWCAP:").as_deref(), Some("1234"));
+        assert_eq!(field("AT_HWCAP2:").as_deref(), Some("0x2"));
+    }
+
+    #[test]
+    fn bwrap_policy_extends_strict_arguments() {
+        use sidebundle_core::{BwrapPolicy, BwrapPreset};
+
+        let render = |policy: &BwrapPolicy, cwd: super::CwdPlan| -> Vec<String> {
+            let host = super::BwrapHost {
+                cwd,
+                home: Some("/home/dev".into()),
+                portable: None,
+                state: None,
+                data: Vec::new(),
+                drivers: Vec::new(),
+                devices: Vec::new(),
+            };
+            super::bwrap_args(Path::new("/b"), Path::new("/b/payload"), policy, &host)
+                .unwrap()
+                .into_iter()
+                .map(|arg| arg.into_string().unwrap())
+                .collect()
+        };
+
+        let strict = render(&BwrapPolicy::default(), super::CwdPlan::Root);
+        assert_eq!(&strict[..3], ["--bind", "/b/payload", "/"]);
+        assert_eq!(
+            &strict[strict.len() - 2..],
+            ["--die-with-parent", "--unshare-all"]
+        );
+        assert!(!strict
+            .iter()
+            .any(|arg| arg == "/home/dev" || arg == "--chdir"));
+        let entered = render(
+            &BwrapPolicy::default(),
+            super::CwdPlan::Enter("/usr/share".into()),
+        );
+        assert_eq!(
+            &entered[strict.len() - 2..strict.len()],
+            ["--chdir", "/usr/share"]
+        );
+
+        let mut user = BwrapPolicy::preset(BwrapPreset::User);
+        user.ro_binds.push("/opt/models:/models".parse().unwrap());
+        let args = render(&user, super::CwdPlan::Bind("/work/project".into()));
+        assert_eq!(&args[..strict.len() - 2], &strict[..strict.len() - 2]);
+        assert_eq!(
+            &args[strict.len() - 2..],
+            [
+                "--bind",
+                "/home/dev",
+                "/home/dev",
+                "--bind",
+                "/work/project",
+                "/work/project",
+                "--ro-bind",
+                "/opt/models",
+                "/models",
+                "--chdir",
+                "/work/project",
+                "--die-with-parent",
+                "--unshare-all",
+                "--share-net",
+            ]
+        );
+
+        let host = super::BwrapHost {
+            cwd: super::CwdPlan::Root,
+            home: None,
+            portable: None,
+            state: None,
+            data: vec![(7, "/etc/passwd".into())],
+            drivers: vec![(
+                "/usr/lib/libEGL.so.1".into(),
+                "/run/sidebundle/drivers/libEGL.so.1".into(),
+            )],
+            devices: vec!["/dev/dri".into(), "/dev/kvm".into()],
+        };
+        let args: Vec<String> =
+            super::bwrap_args(Path::new("/b"), Path::new("/b/payload"), &user, &host)
+                .unwrap()
+                .into_iter()
+                .map(|arg| arg.into_string().unwrap())
+                .collect();
+        let has = |window: &[&str]| args.windows(window.len()).any(|w| w == window);
+        assert!(has(&[
+            "--ro-bind",
+            "/usr/lib/libEGL.so.1",
+            "/run/sidebundle/drivers/libEGL.so.1",
+            "--ro-bind-data",
+            "7",
+            "/etc/passwd"
+        ]));
+        assert!(has(&[
+            "--dev-bind-try",
+            "/dev/dri",
+            "/dev/dri",
+            "--dev-bind-try",
+            "/dev/kvm",
+            "/dev/kvm"
+        ]));
+    }
+
+    #[test]
+    fn cwd_plan_binds_only_paths_the_payload_lacks() {
+        use super::{plan_cwd, CwdPlan};
+
+        let bundle = tempfile::tempdir().unwrap();
+        let payload = bundle.path().join("payload");
+        std::fs::create_dir_all(payload.join("usr/share/doc")).unwrap();
+        std::fs::write(payload.join("usr/share/doc/README"), b"doc").unwrap();
+        let plan = |cwd: &str, bind: bool| plan_cwd(&payload, Some(Path::new(cwd)), bind, "");
+
+        assert_eq!(
+            plan("/home/dev/project", true),
+            CwdPlan::Bind("/home/dev/project".into())
+        );
+        assert_eq!(plan("/home/dev/project", false), CwdPlan::Root);
+        // Empty mount points in the payload do not count as provided.
+        std::fs::create_dir_all(payload.join("tmp/work/stub")).unwrap();
+        assert_eq!(plan("/tmp", true), CwdPlan::Bind("/tmp".into()));
+        assert_eq!(plan("/tmp/work", true), CwdPlan::Bind("/tmp/work".into()));
+        assert_eq!(
+            plan("/usr/share", true),
+            CwdPlan::Enter("/usr/share".into())
+        );
+        assert_eq!(plan("/", true), CwdPlan::Root);
+        let inside = payload.join("usr/share");
+        assert_eq!(
+            plan_cwd(&payload, Some(&inside), true, ""),
+            CwdPlan::Enter("/usr/share".into())
+        );
+        assert_eq!(plan_cwd(&payload, None, true, ""), CwdPlan::Root);
+    }
+
+    #[test]
+    fn bwrap_env_passthrough_overrides_policy_values() {
+        let envp = vec![
+            CString::new("PATH=/usr/bin").unwrap(),
+            CString::new("DISPLAY=:9").unwrap(),
+        ];
+        let host = [
+            ("DISPLAY", ":0"),
+            ("XAUTHORITY", "/run/user/1000/xauth"),
+            ("SECRET", "x"),
+        ]
+        .map(|(k, v)| (k.into(), v.into()));
+        let block =
+            super::passthrough_env(&envp, &["DISPLAY".into(), "XAUTH*".into()], host).unwrap();
+        let block: Vec<&str> = block.iter().map(|e| e.to_str().unwrap()).collect();
+        assert_eq!(
+            block,
+            [
+                "PATH=/usr/bin",
+                "DISPLAY=:0",
+                "XAUTHORITY=/run/user/1000/xauth"
+            ]
+        );
+    }
+
+    #[test]
+    fn userland_exec_reports_missing_linker() {
+        let argv = vec![CString::new("demo").unwrap()];
+        let err = super::exec_userland(
+            Some(Path::new("/nonexistent/ld-linux-x86-64.so.2")),
+            Path::new("/bin/sh"),
+            &argv,
+            &[],
+            None,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("userland exec cannot open"));
+    }
+
+    /// A bundle whose payload holds just `/bin/sh`, the libraries it needs and the account files
+    /// the packager always writes, plus a `data/` marker. `None` when the host layout differs
+    /// from the one this copies.
+    #[cfg(target_arch = "x86_64")]
+    fn shell_bundle() -> Option<tempfile::TempDir> {
+        let libs = [
+            Path::new("/lib64/ld-linux-x86-64.so.2"),
+            Path::new("/lib/x86_64-linux-gnu/libc.so.6"),
+        ];
+        if libs.iter().any(|lib| !lib.exists()) {
+            return None;
+        }
+        let bundle = tempfile::tempdir().unwrap();
+        let payload = bundle.path().join("payload");
+        for file in libs.iter().copied().chain([Path::new("/bin/sh")]) {
+            let dest = payload.join(file.strip_prefix("/").unwrap());
+            std::fs::create_dir_all(dest.parent().unwrap()).unwrap();
+            std::fs::copy(file, &dest).unwrap();
+        }
+        std::fs::create_dir_all(payload.join("etc")).unwrap();
+        for file in ["etc/passwd", "etc/group"] {
+            std::fs::write(payload.join(file), b"root:x:0:0::/root:/bin/sh\n").unwrap();
+        }
+        std::fs::create_dir_all(bundle.path().join("data")).unwrap();
+        std::fs::write(bundle.path().join("data/marker"), b"data").unwrap();
+        Some(bundle)
+    }
+
+    /// Every path below `dir`, to check that a launch left the payload untouched.
+    fn tree(dir: &Path) -> Vec<std::path::PathBuf> {
+        let mut paths = Vec::new();
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() && !path.is_symlink() {
+                paths.extend(tree(&path));
+            }
+            paths.push(path);
+        }
+        paths.sort();
+        paths
+    }
+
+    /// Run `script` with the bundled shell under `layout`; `None` when user namespaces are
+    /// disabled on this host.
+    #[cfg(target_arch = "x86_64")]
+    fn run_sandboxed(bundle: &Path, layout: super::RootLayout<'_>, script: &str) -> Option<String> {
+        let argv = vec![
+            CString::new("/bin/sh").unwrap(),
+            CString::new("-c").unwrap(),
+            CString::new(script).unwrap(),
+        ];
+        let envp = vec![CString::new("PATH=/bin").unwrap()];
+        let plan = super::rooted_plan(RunMode::Sandbox, "/bin/sh".into(), argv, envp);
+        let payload = bundle.join("payload");
+        let extras = super::RootExtras::new(
+            &payload,
+            RunMode::Sandbox,
+            None,
+            None,
+            &Default::default(),
+            &[],
+            false,
+        );
+        let output = capture_child_stdout(|| {
+            if let Err(err) = super::sandbox::exec_sandbox(
+                bundle,
+                &payload,
+                layout,
+                &plan,
+                &extras,
+                &Default::default(),
+            ) {
+                println!("unavailable: {err:#}");
+            }
+        });
+        (!output.starts_with("unavailable:")).then_some(output)
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn sandbox_runs_entry_inside_payload_root() {
+        let Some(bundle) = shell_bundle() else {
+            return;
+        };
+        // The host-side bundle path must not be visible once the payload is the root.
+        let script = format!(
+            "echo pid:$$; [ -e /dev/null ] && echo dev; [ -d /proc/self ] && echo proc; \
+             echo hi > /tmp/x && echo tmp; [ -e /data/marker ] && echo data; \
+             [ ! -e {} ] && echo rooted; IFS=: read name pw uid rest < /etc/passwd; \
+             [ \"$uid\" = {} ] && echo user",
+            bundle.path().display(),
+            unsafe { libc::getuid() }
+        );
+        let payload = tree(&bundle.path().join("payload"));
+        let Some(output) = run_sandboxed(bundle.path(), super::RootLayout::Payload, &script) else {
+            return;
+        };
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(
+            lines,
+            ["pid:2", "dev", "proc", "tmp", "data", "rooted", "user"]
+        );
+        // Mount points, including the one for the working directory, live on the root's tmpfs.
+        assert_eq!(tree(&bundle.path().join("payload")), payload);
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn chroot_runs_entry_inside_payload_root() {
+        let Some(bundle) = shell_bundle() else {
+            return;
+        };
+        let argv = vec![
+            CString::new("/bin/sh").unwrap(),
+            CString::new("-c").unwrap(),
+            CString::new(format!(
+                "[ -e /data/marker ] && echo data; [ -e /dev/null ] && echo dev; \
+                 [ ! -e {} ] && echo rooted",
+                bundle.path().display()
+            ))
+            .unwrap(),
+        ];
+        let envp = vec![CString::new("PATH=/bin").unwrap()];
+        let plan = super::rooted_plan(RunMode::Chroot, "/bin/sh".into(), argv, envp);
+        let payload = bundle.path().join("payload");
+        let shipped = tree(&payload);
+        let extras = super::RootExtras::new(
+            &payload,
+            RunMode::Chroot,
+            None,
+            None,
+            &Default::default(),
+            &[],
+            false,
+        );
+        let output = capture_child_stdout(|| {
+            if let Err(err) =
+                super::exec_chroot(bundle.path(), &payload, &plan, &extras, &Default::default())
+            {
+                println!("unavailable: {err:#}");
+            }
+        });
+        // Neither root nor a user namespace is available here.
+        if output.starts_with("unavailable:") {
+            return;
+        }
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines, ["data", "dev", "rooted"]);
+        // The binds lived in the child's namespace only, on mount points outside the payload.
+        assert_eq!(tree(&payload), shipped);
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn overlay_passes_host_trees_through() {
+        let Some(bundle) = shell_bundle() else {
+            return;
+        };
+        let shared = tempfile::tempdir().unwrap();
+        std::fs::write(shared.path().join("notes.txt"), b"host").unwrap();
+        let passthrough = vec![shared.path().to_path_buf()];
+        let script = format!(
+            "[ -e {shared}/notes.txt ] && echo shared; echo out > {shared}/out && echo write; \
+             [ -x /bin/sh ] && echo payload; [ -e /data/marker ] && echo data; \
+             [ ! -e {bundle} ] && echo rooted; touch /new 2>/dev/null || echo sealed",
+            shared = shared.path().display(),
+            bundle = bundle.path().display()
+        );
+        let layout = super::RootLayout::Overlay {
+            passthrough: &passthrough,
+        };
+        let Some(output) = run_sandboxed(bundle.path(), layout, &script) else {
+            return;
+        };
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(
+            lines,
+            ["shared", "write", "payload", "data", "rooted", "sealed"]
+        );
+        assert!(shared.path().join("out").exists());
+    }
 }
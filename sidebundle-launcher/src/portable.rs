@@ -0,0 +1,83 @@
+//! Portable mode: the entry's HOME and XDG base directories live in a directory inside or beside
+//! the bundle, so tool state travels with it (USB sticks, shared NFS mounts).
+//!
+//! The directory is created on first run and used at the same absolute path in every run mode;
+//! isolated modes bind it into the new root. The caller's values stay available as
+//! `SIDEBUNDLE_HOST_<VAR>`.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const HOST_PREFIX: &str = "SIDEBUNDLE_HOST_";
+
+/// Variables redirected into the portable home, with their location below it.
+const REDIRECTED: &[(&str, &str)] = &[
+    ("HOME", ""),
+    ("XDG_CONFIG_HOME", ".config"),
+    ("XDG_CACHE_HOME", ".cache"),
+    ("XDG_DATA_HOME", ".local/share"),
+    ("XDG_STATE_HOME", ".local/state"),
+];
+
+/// Create the portable home `configured` (relative to the bundle root) and its XDG directories,
+/// returning its canonical path. When it cannot be created the entry keeps the caller's HOME.
+pub(crate) fn prepare(bundle_root: &Path, configured: Option<&Path>) -> Option<PathBuf> {
+    let home = bundle_root.join(configured?);
+    let created = REDIRECTED
+        .iter()
+        .try_for_each(|(_, rel)| fs::create_dir_all(home.join(rel)))
+        .and_then(|()| fs::canonicalize(&home));
+    match created {
+        Ok(home) => Some(home),
+        Err(err) => {
+            eprintln!(
+                "sidebundle launcher: warning: cannot create portable home {} ({err}); using the \
+                 caller's HOME",
+                home.display()
+            );
+            None
+        }
+    }
+}
+
+/// Point HOME and the XDG base directories at `home`, keeping the caller's values under
+/// `SIDEBUNDLE_HOST_<VAR>`.
+pub(crate) fn redirect(env_map: &mut BTreeMap<String, String>, home: &Path) {
+    for (key, rel) in REDIRECTED {
+        let dir = match rel.is_empty() {
+            true => home.to_path_buf(),
+            false => home.join(rel),
+        };
+        if let Some(previous) = env_map.insert(key.to_string(), dir.to_string_lossy().into_owned())
+        {
+            env_map.insert(format!("{HOST_PREFIX}{key}"), previous);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redirect_keeps_caller_values_aside() {
+        let dir = tempfile::tempdir().unwrap();
+        let bundle = dir.path().join("bundle");
+        let home = prepare(&bundle, Some(Path::new("../bundle.home"))).unwrap();
+        assert_eq!(home, dir.path().canonicalize().unwrap().join("bundle.home"));
+        assert!(home.join(".local/share").is_dir());
+
+        let mut env = BTreeMap::from([
+            ("HOME".to_string(), "/home/dev".to_string()),
+            ("XDG_CACHE_HOME".to_string(), "/var/cache/dev".to_string()),
+        ]);
+        redirect(&mut env, Path::new("/media/usb/tool.home"));
+        assert_eq!(env["HOME"], "/media/usb/tool.home");
+        assert_eq!(env["XDG_CONFIG_HOME"], "/media/usb/tool.home/.config");
+        assert_eq!(env["XDG_CACHE_HOME"], "/media/usb/tool.home/.cache");
+        assert_eq!(env["SIDEBUNDLE_HOST_HOME"], "/home/dev");
+        assert_eq!(env["SIDEBUNDLE_HOST_XDG_CACHE_HOME"], "/var/cache/dev");
+        assert!(!env.contains_key("SIDEBUNDLE_HOST_XDG_CONFIG_HOME"));
+    }
+}
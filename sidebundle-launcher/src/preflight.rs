@@ -0,0 +1,141 @@
+//! Host compatibility checks run before the entry is exec'd.
+//!
+//! The packager records the newest kernel ABI and the highest x86-64 ISA level the bundled ELFs
+//! declare. Running such a bundle on an older kernel or CPU otherwise ends in glibc's
+//! "FATAL: kernel too old" or a bare SIGILL, so the launcher compares them with `uname` and CPUID
+//! first and explains the mismatch. `SIDEBUNDLE_SKIP_PREFLIGHT=1` skips the checks.
+
+use anyhow::{bail, Result};
+use std::env;
+use std::ffi::CStr;
+
+use sidebundle_core::{HostRequirements, KernelVersion, PREFLIGHT_ENV};
+
+pub(crate) fn check(requirements: &HostRequirements) -> Result<()> {
+    if env::var_os(PREFLIGHT_ENV).is_some_and(|value| !value.is_empty() && value != "0") {
+        return Ok(());
+    }
+    let mut problems = Vec::new();
+    if let Some(required) = &requirements.kernel {
+        let release = kernel_release();
+        match KernelVersion::from_release(&release) {
+            Some(host) if host < required.version => problems.push(format!(
+                "{} requires Linux {} or newer, but this host runs {release}; run the bundle on a \
+                 newer kernel or rebuild it from an older base image",
+                required.required_by.display(),
+                required.version
+            )),
+            _ => {}
+        }
+    }
+    if let (Some(required), Some(host)) = (&requirements.x86_isa, host_isa_level()) {
+        if host < required.level {
+            problems.push(format!(
+                "{} requires an x86-64-v{} CPU, but this CPU only supports {}; run the bundle on a \
+                 newer CPU or rebuild it from an image built for the x86-64 baseline",
+                required.required_by.display(),
+                required.level,
+                isa_name(host)
+            ));
+        }
+    }
+    if problems.is_empty() {
+        return Ok(());
+    }
+    bail!(
+        "this host cannot run the bundle:\n  - {}\n(set {PREFLIGHT_ENV}=1 to try anyway)",
+        problems.join("\n  - ")
+    )
+}
+
+pub(crate) fn kernel_release() -> String {
+    let mut name: libc::utsname = unsafe { std::mem::zeroed() };
+    if unsafe { libc::uname(&mut name) } != 0 {
+        return String::new();
+    }
+    unsafe { CStr::from_ptr(name.release.as_ptr()) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+pub(crate) fn isa_name(level: u8) -> String {
+    match level {
+        1 => "x86-64 baseline".to_string(),
+        level => format!("x86-64-v{level}"),
+    }
+}
+
+/// The x86-64 microarchitecture level this CPU (and kernel, for AVX state) supports; `None` on
+/// other architectures. LAHF/SAHF, also part of v2, has no feature probe here and is assumed.
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn host_isa_level() -> Option<u8> {
+    use std::arch::is_x86_feature_detected as has;
+
+    let v2 = has!("cmpxchg16b")
+        && has!("popcnt")
+        && has!("sse3")
+        && has!("ssse3")
+        && has!("sse4.1")
+        && has!("sse4.2");
+    let v3 = v2
+        && has!("avx")
+        && has!("avx2")
+        && has!("bmi1")
+        && has!("bmi2")
+        && has!("f16c")
+        && has!("fma")
+        && has!("lzcnt")
+        && has!("movbe");
+    let v4 = v3
+        && has!("avx512f")
+        && has!("avx512bw")
+        && has!("avx512cd")
+        && has!("avx512dq")
+        && has!("avx512vl");
+    Some(1 + u8::from(v2) + u8::from(v3) + u8::from(v4))
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub(crate) fn host_isa_level() -> Option<u8> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sidebundle_core::{IsaRequirement, KernelRequirement};
+    use std::path::PathBuf;
+
+    #[test]
+    fn mismatches_name_the_file_and_the_host() {
+        let release = kernel_release();
+        let host = KernelVersion::from_release(&release).unwrap();
+        let fits = HostRequirements {
+            kernel: Some(KernelRequirement {
+                version: KernelVersion::new(2, 6, 32),
+                required_by: PathBuf::from("payload/lib/libc.so.6"),
+            }),
+            x86_isa: None,
+        };
+        assert!(check(&fits).is_ok());
+
+        let too_new = HostRequirements {
+            kernel: Some(KernelRequirement {
+                version: KernelVersion::new(host.major + 1, 0, 0),
+                required_by: PathBuf::from("payload/lib/libc.so.6"),
+            }),
+            x86_isa: host_isa_level()
+                .filter(|level| *level < 4)
+                .map(|level| IsaRequirement {
+                    level: level + 1,
+                    required_by: PathBuf::from("payload/lib/libz.so.1"),
+                }),
+        };
+        let message = check(&too_new).unwrap_err().to_string();
+        assert!(message.contains("payload/lib/libc.so.6 requires Linux"));
+        assert!(message.contains(&release));
+        if too_new.x86_isa.is_some() {
+            assert!(message.contains("payload/lib/libz.so.1 requires an x86-64-v"));
+        }
+    }
+}
@@ -0,0 +1,389 @@
+//! Process policy applied by the launcher right before it execs the entry, in every run mode:
+//! rlimits, umask, nice/ionice, `PR_SET_NO_NEW_PRIVS` and a seccomp syscall denylist.
+//!
+//! In bwrap mode the launcher execs bwrap rather than the entry, so only the inherited settings
+//! are applied up front; the seccomp program is handed to bwrap through `--seccomp FD` (bwrap
+//! sets no_new_privs itself), otherwise it would also deny the syscalls bwrap needs.
+
+use anyhow::{anyhow, Context, Result};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::os::fd::{FromRawFd, OwnedFd};
+
+use sidebundle_core::{IoClass, ProcessPolicy, RlimitResource};
+
+const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+
+/// Offsets into `struct seccomp_data`.
+const SECCOMP_DATA_NR: u32 = 0;
+const SECCOMP_DATA_ARCH: u32 = 4;
+
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: Option<u32> = Some(0xc000_003e);
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: Option<u32> = Some(0xc000_00b7);
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+const AUDIT_ARCH: Option<u32> = None;
+
+/// x32 syscalls share the x86_64 audit arch and would slip past a denylist of x86_64 numbers.
+#[cfg(target_arch = "x86_64")]
+const X32_SYSCALL_BIT: Option<u32> = Some(0x4000_0000);
+#[cfg(not(target_arch = "x86_64"))]
+const X32_SYSCALL_BIT: Option<u32> = None;
+
+/// Apply the whole policy to the launcher process; the exec'd entry inherits it.
+pub(crate) fn apply(policy: &ProcessPolicy) -> Result<()> {
+    apply_limits(policy)?;
+    if policy.no_new_privs || !policy.deny_syscalls.is_empty() {
+        set_no_new_privs()?;
+    }
+    if !policy.deny_syscalls.is_empty() {
+        let program = seccomp_program(&policy.deny_syscalls)?;
+        let fprog = libc::sock_fprog {
+            len: program.len() as u16,
+            filter: program.as_ptr() as *mut libc::sock_filter,
+        };
+        let rc = unsafe {
+            libc::prctl(
+                libc::PR_SET_SECCOMP,
+                libc::SECCOMP_MODE_FILTER,
+                &fprog as *const libc::sock_fprog,
+            )
+        };
+        if rc != 0 {
+            return Err(io::Error::last_os_error()).context("failed to install seccomp filter");
+        }
+    }
+    Ok(())
+}
+
+/// The settings a child inherits across exec: rlimits, umask, nice and ionice. Hard limits are
+/// never raised, which unprivileged callers may not do: `unlimited` and values above the current
+/// hard limit stop at it.
+pub(crate) fn apply_limits(policy: &ProcessPolicy) -> Result<()> {
+    for limit in &policy.rlimits {
+        let resource = match limit.resource {
+            RlimitResource::Nofile => libc::RLIMIT_NOFILE,
+            RlimitResource::As => libc::RLIMIT_AS,
+            RlimitResource::Core => libc::RLIMIT_CORE,
+        };
+        let mut current = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        if unsafe { libc::getrlimit(resource, &mut current) } != 0 {
+            return Err(io::Error::last_os_error())
+                .with_context(|| format!("failed to read rlimit {}", limit.resource));
+        }
+        let hard = limit.hard.map_or(current.rlim_max, |hard| {
+            if hard > current.rlim_max {
+                eprintln!(
+                    "sidebundle launcher: warning: rlimit {} hard limit {hard} exceeds the current \
+                     {}; keeping it",
+                    limit.resource, current.rlim_max
+                );
+            }
+            hard.min(current.rlim_max)
+        });
+        let value = libc::rlimit {
+            rlim_cur: limit.soft.map_or(hard, |soft| soft.min(hard)),
+            rlim_max: hard,
+        };
+        if unsafe { libc::setrlimit(resource, &value) } != 0 {
+            return Err(io::Error::last_os_error())
+                .with_context(|| format!("failed to set rlimit {}", limit.resource));
+        }
+    }
+    if let Some(mask) = policy.umask {
+        unsafe { libc::umask(mask as libc::mode_t) };
+    }
+    if let Some(nice) = policy.nice {
+        if unsafe { libc::setpriority(libc::PRIO_PROCESS as _, 0, nice) } != 0 {
+            eprintln!(
+                "sidebundle launcher: warning: failed to set nice {nice} ({})",
+                io::Error::last_os_error()
+            );
+        }
+    }
+    if let Some(ionice) = policy.ionice {
+        let (class, level) = match ionice.class {
+            IoClass::Realtime => (1, ionice.level),
+            IoClass::BestEffort => (2, ionice.level),
+            IoClass::Idle => (3, 0),
+        };
+        let prio = (class << IOPRIO_CLASS_SHIFT) | libc::c_int::from(level);
+        if unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, prio) } != 0 {
+            eprintln!(
+                "sidebundle launcher: warning: failed to set ionice ({})",
+                io::Error::last_os_error()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// The seccomp program in a memfd for `bwrap --seccomp FD`; `None` without a denylist.
+pub(crate) fn seccomp_memfd(policy: &ProcessPolicy) -> Result<Option<OwnedFd>> {
+    if policy.deny_syscalls.is_empty() {
+        return Ok(None);
+    }
+    let program = seccomp_program(&policy.deny_syscalls)?;
+    // Left inheritable on purpose: bwrap reads the program from this descriptor.
+    let fd = unsafe { libc::memfd_create(c"sidebundle-seccomp".as_ptr(), 0) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error()).context("memfd_create failed for seccomp");
+    }
+    let mut file = std::fs::File::from(unsafe { OwnedFd::from_raw_fd(fd) });
+    for insn in &program {
+        let mut raw = [0u8; 8];
+        raw[..2].copy_from_slice(&insn.code.to_ne_bytes());
+        raw[2] = insn.jt;
+        raw[3] = insn.jf;
+        raw[4..].copy_from_slice(&insn.k.to_ne_bytes());
+        file.write_all(&raw)
+            .context("failed to write seccomp program")?;
+    }
+    file.seek(SeekFrom::Start(0))?;
+    Ok(Some(file.into()))
+}
+
+fn set_no_new_privs() -> Result<()> {
+    if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } != 0 {
+        return Err(io::Error::last_os_error()).context("failed to set no_new_privs");
+    }
+    Ok(())
+}
+
+/// Classic BPF: foreign-arch and x32 syscalls and every denied syscall fail with `EPERM`,
+/// everything else is allowed.
+fn seccomp_program(deny: &[String]) -> Result<Vec<libc::sock_filter>> {
+    let arch =
+        AUDIT_ARCH.ok_or_else(|| anyhow!("seccomp denylist unsupported on this architecture"))?;
+    let deny_ret = libc::SECCOMP_RET_ERRNO | (libc::EPERM as u32 & libc::SECCOMP_RET_DATA);
+    let mut program = vec![
+        stmt(
+            libc::BPF_LD | libc::BPF_W | libc::BPF_ABS,
+            SECCOMP_DATA_ARCH,
+        ),
+        jump(libc::BPF_JEQ, arch, 1, 0),
+        stmt(libc::BPF_RET | libc::BPF_K, deny_ret),
+        stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, SECCOMP_DATA_NR),
+    ];
+    if let Some(bit) = X32_SYSCALL_BIT {
+        program.push(jump(libc::BPF_JGE, bit, 0, 1));
+        program.push(stmt(libc::BPF_RET | libc::BPF_K, deny_ret));
+    }
+    for name in deny {
+        let Some(nr) = syscall_number(name) else {
+            eprintln!(
+                "sidebundle launcher: warning: syscall `{name}` does not exist on this architecture; not denied"
+            );
+            continue;
+        };
+        program.push(jump(libc::BPF_JEQ, nr as u32, 0, 1));
+        program.push(stmt(libc::BPF_RET | libc::BPF_K, deny_ret));
+    }
+    program.push(stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_ALLOW));
+    Ok(program)
+}
+
+fn stmt(code: u32, k: u32) -> libc::sock_filter {
+    libc::sock_filter {
+        code: code as u16,
+        jt: 0,
+        jf: 0,
+        k,
+    }
+}
+
+fn jump(op: u32, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+    libc::sock_filter {
+        code: (libc::BPF_JMP | op | libc::BPF_K) as u16,
+        jt,
+        jf,
+        k,
+    }
+}
+
+/// Numbers for [`sidebundle_core::SECCOMP_SYSCALLS`] on the launcher's architecture.
+fn syscall_number(name: &str) -> Option<libc::c_long> {
+    let nr = match name {
+        "acct" => libc::SYS_acct,
+        "add_key" => libc::SYS_add_key,
+        "adjtimex" => libc::SYS_adjtimex,
+        "bpf" => libc::SYS_bpf,
+        "chroot" => libc::SYS_chroot,
+        "clock_adjtime" => libc::SYS_clock_adjtime,
+        "clock_settime" => libc::SYS_clock_settime,
+        "delete_module" => libc::SYS_delete_module,
+        "fanotify_init" => libc::SYS_fanotify_init,
+        "finit_module" => libc::SYS_finit_module,
+        "init_module" => libc::SYS_init_module,
+        "io_uring_enter" => libc::SYS_io_uring_enter,
+        "io_uring_register" => libc::SYS_io_uring_register,
+        "io_uring_setup" => libc::SYS_io_uring_setup,
+        #[cfg(target_arch = "x86_64")]
+        "ioperm" => libc::SYS_ioperm,
+        #[cfg(target_arch = "x86_64")]
+        "iopl" => libc::SYS_iopl,
+        "kexec_file_load" => libc::SYS_kexec_file_load,
+        "kexec_load" => libc::SYS_kexec_load,
+        "keyctl" => libc::SYS_keyctl,
+        "mount" => libc::SYS_mount,
+        "move_mount" => libc::SYS_move_mount,
+        "name_to_handle_at" => libc::SYS_name_to_handle_at,
+        "open_by_handle_at" => libc::SYS_open_by_handle_at,
+        "open_tree" => libc::SYS_open_tree,
+        "perf_event_open" => libc::SYS_perf_event_open,
+        "personality" => libc::SYS_personality,
+        "pivot_root" => libc::SYS_pivot_root,
+        "process_vm_readv" => libc::SYS_process_vm_readv,
+        "process_vm_writev" => libc::SYS_process_vm_writev,
+        "ptrace" => libc::SYS_ptrace,
+        "quotactl" => libc::SYS_quotactl,
+        "reboot" => libc::SYS_reboot,
+        "request_key" => libc::SYS_request_key,
+        "setdomainname" => libc::SYS_setdomainname,
+        "sethostname" => libc::SYS_sethostname,
+        "setns" => libc::SYS_setns,
+        "settimeofday" => libc::SYS_settimeofday,
+        "swapoff" => libc::SYS_swapoff,
+        "swapon" => libc::SYS_swapon,
+        "syslog" => libc::SYS_syslog,
+        "umount2" => libc::SYS_umount2,
+        "unshare" => libc::SYS_unshare,
+        "userfaultfd" => libc::SYS_userfaultfd,
+        _ => return None,
+    };
+    Some(nr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sidebundle_core::{Rlimit, SECCOMP_SYSCALLS};
+
+    #[test]
+    fn every_known_syscall_maps_on_this_arch() {
+        let missing: Vec<_> = SECCOMP_SYSCALLS
+            .iter()
+            .filter(|name| syscall_number(name).is_none())
+            .collect();
+        #[cfg(target_arch = "x86_64")]
+        assert!(missing.is_empty(), "unmapped: {missing:?}");
+        #[cfg(not(target_arch = "x86_64"))]
+        assert!(missing.iter().all(|name| name.starts_with("io")));
+    }
+
+    #[test]
+    fn policy_limits_and_denies_syscalls() {
+        let policy = ProcessPolicy {
+            rlimits: vec!["nofile=64:128".parse::<Rlimit>().unwrap()],
+            umask: Some(0o077),
+            deny_syscalls: vec!["unshare".into(), "personality".into()],
+            ..ProcessPolicy::default()
+        };
+        // Seccomp filters and no_new_privs are permanent, so check them from a child.
+        match unsafe { libc::fork() } {
+            -1 => panic!("fork failed"),
+            0 => {
+                let code = if apply(&policy).is_err() {
+                    10
+                } else {
+                    let mut limit = libc::rlimit {
+                        rlim_cur: 0,
+                        rlim_max: 0,
+                    };
+                    unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) };
+                    let old_mask = unsafe { libc::umask(0o022) };
+                    let nnp = unsafe { libc::prctl(libc::PR_GET_NO_NEW_PRIVS, 0, 0, 0, 0) };
+                    let unshare = unsafe { libc::unshare(libc::CLONE_NEWUTS) };
+                    let unshare_err = io::Error::last_os_error().raw_os_error();
+                    // Syscalls outside the deny list still go through.
+                    let mut name: libc::utsname = unsafe { std::mem::zeroed() };
+                    let uname = unsafe { libc::uname(&mut name) };
+                    if (limit.rlim_cur, limit.rlim_max) != (64, 128) {
+                        11
+                    } else if old_mask != 0o077 {
+                        12
+                    } else if nnp != 1 {
+                        13
+                    } else if unshare != -1 || unshare_err != Some(libc::EPERM) {
+                        14
+                    } else if uname != 0 {
+                        15
+                    } else {
+                        0
+                    }
+                };
+                unsafe { libc::_exit(code) };
+            }
+            pid => {
+                let mut status = 0;
+                unsafe { libc::waitpid(pid, &mut status, 0) };
+                assert!(libc::WIFEXITED(status));
+                assert_eq!(libc::WEXITSTATUS(status), 0);
+            }
+        }
+    }
+
+    #[test]
+    fn unlimited_hard_limits_stay_at_the_current_one() {
+        let policy = ProcessPolicy {
+            rlimits: ["core=0:unlimited", "nofile=unlimited", "as=1G:2G"]
+                .map(|limit| limit.parse::<Rlimit>().unwrap())
+                .to_vec(),
+            ..ProcessPolicy::default()
+        };
+        let get = |resource| {
+            let mut limit = libc::rlimit {
+                rlim_cur: 0,
+                rlim_max: 0,
+            };
+            unsafe { libc::getrlimit(resource, &mut limit) };
+            (limit.rlim_cur, limit.rlim_max)
+        };
+        match unsafe { libc::fork() } {
+            -1 => panic!("fork failed"),
+            0 => {
+                let lowered = [
+                    (libc::RLIMIT_CORE, 4096),
+                    (libc::RLIMIT_NOFILE, 256),
+                    (libc::RLIMIT_AS, 1 << 30),
+                ];
+                for (resource, max) in lowered {
+                    let limit = libc::rlimit {
+                        rlim_cur: max,
+                        rlim_max: max,
+                    };
+                    unsafe { libc::setrlimit(resource, &limit) };
+                }
+                // Run as an unprivileged user, who may not raise hard limits.
+                if unsafe { libc::geteuid() } == 0
+                    && unsafe { libc::setgid(65534) != 0 || libc::setuid(65534) != 0 }
+                {
+                    unsafe { libc::_exit(0) };
+                }
+                let code = if let Err(err) = apply_limits(&policy) {
+                    eprintln!("{err:#}");
+                    10
+                } else if get(libc::RLIMIT_CORE) != (0, 4096) {
+                    11
+                } else if get(libc::RLIMIT_NOFILE) != (256, 256) {
+                    12
+                } else if get(libc::RLIMIT_AS) != (1 << 30, 1 << 30) {
+                    13
+                } else {
+                    0
+                };
+                unsafe { libc::_exit(code) };
+            }
+            pid => {
+                let mut status = 0;
+                unsafe { libc::waitpid(pid, &mut status, 0) };
+                assert!(libc::WIFEXITED(status));
+                assert_eq!(libc::WEXITSTATUS(status), 0);
+            }
+        }
+    }
+}
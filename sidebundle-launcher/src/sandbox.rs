@@ -0,0 +1,544 @@
+//! `run_mode=sandbox` and `run_mode=overlay`: bwrap-like isolation done by the launcher itself.
+//!
+//! The launcher unshares user/mount/pid/ipc/uts namespaces, maps the invoking uid/gid, assembles
+//! a root (plus `/proc`, a minimal `/dev`, tmpfs `/tmp` and `/run`, host DNS files) and
+//! pivot_roots into it. The network namespace is shared with the host.
+//!
+//! The root is a tmpfs holding the payload's top-level entries, so mount points for the working
+//! directory and the system mounts are created there rather than in a payload that may be
+//! read-only. Overlay mode additionally binds selected host trees over it; overlayfs cannot be
+//! used since the payload lives inside the host root it would be stacked on. Chroot mode builds
+//! the same root without the namespaces it cannot have.
+
+use anyhow::{anyhow, Context, Result};
+use std::env;
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::os::unix::fs::{symlink, MetadataExt};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicI32, Ordering};
+
+use sidebundle_core::{ProcessPolicy, RunMode};
+
+use super::{
+    devices, drivers, exec_static, os_to_cstring, plan_cwd, process, state, CwdPlan, ExecPlan,
+    RootExtras,
+};
+
+/// Host files bind-mounted read-only so name resolution keeps working.
+const HOST_FILES: &[&str] = &["etc/resolv.conf", "etc/hosts"];
+
+/// Where the isolated modes stage their tmpfs root and, after pivot_root, keep the host root
+/// until everything they need has been bound.
+const ROOT_STAGING: &str = "/tmp";
+const OLD_ROOT: &str = ".oldroot";
+
+/// Signals the launcher (and the sandbox init) pass on to their child. Terminal-generated
+/// SIGINT/SIGQUIT already reach the whole foreground process group and are ignored instead.
+const FORWARDED_SIGNALS: &[libc::c_int] =
+    &[libc::SIGTERM, libc::SIGHUP, libc::SIGUSR1, libc::SIGUSR2];
+
+static FORWARD_TO: AtomicI32 = AtomicI32::new(0);
+
+/// How the sandbox root is assembled.
+pub(crate) enum RootLayout<'a> {
+    /// Just the payload's top-level entries (`run_mode=sandbox`).
+    Payload,
+    /// The payload's top-level entries in front, with these host paths passed through
+    /// (`run_mode=overlay`).
+    Overlay { passthrough: &'a [PathBuf] },
+}
+
+/// Run `plan` inside a fresh user namespace whose root is built from `payload_root` according
+/// to `layout`, with `extras` (state layer, account files, portable home) mounted into it. Only
+/// returns on setup errors; otherwise the launcher exits with the status of
+/// the sandboxed process.
+pub(crate) fn exec_sandbox(
+    bundle_root: &Path,
+    payload_root: &Path,
+    layout: RootLayout<'_>,
+    plan: &ExecPlan,
+    extras: &RootExtras,
+    process: &ProcessPolicy,
+) -> Result<()> {
+    let (uid, gid) = unshare_user_namespace(
+        libc::CLONE_NEWNS | libc::CLONE_NEWPID | libc::CLONE_NEWIPC | libc::CLONE_NEWUTS,
+    )
+    .context("failed to create user namespace (are unprivileged user namespaces enabled?)")?;
+    write_id_maps(uid, gid)?;
+    let cwd = plan_cwd(
+        payload_root,
+        std::env::current_dir().ok().as_deref(),
+        true,
+        "",
+    );
+
+    // The first child becomes PID 1 of the new pid namespace, which `/proc` needs.
+    match unsafe { libc::fork() } {
+        -1 => Err(io::Error::last_os_error()).context("fork failed for sandbox init"),
+        0 => {
+            let exec_entry = || {
+                process::apply(process)?;
+                exec_static(&plan.program, &plan.argv, &plan.envp)
+            };
+            let err = match sandbox_init(bundle_root, payload_root, layout, extras, cwd, exec_entry)
+            {
+                Ok(()) => unreachable!(),
+                Err(err) => err,
+            };
+            eprintln!("sidebundle launcher: {err:#}");
+            unsafe { libc::_exit(127) }
+        }
+        child => {
+            forward_signals(child);
+            std::process::exit(wait_exit_code(child, false)?);
+        }
+    }
+}
+
+/// Unshare a new user namespace together with `extra` namespaces. Returns the caller's uid/gid as
+/// seen before the switch, for [`write_id_maps`].
+pub(crate) fn unshare_user_namespace(extra: libc::c_int) -> io::Result<(libc::uid_t, libc::gid_t)> {
+    let uid = unsafe { libc::getuid() };
+    let gid = unsafe { libc::getgid() };
+    if unsafe { libc::unshare(libc::CLONE_NEWUSER | extra) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok((uid, gid))
+}
+
+/// Map `uid`/`gid` onto themselves in the user namespace just entered.
+pub(crate) fn write_id_maps(uid: libc::uid_t, gid: libc::gid_t) -> Result<()> {
+    // Unprivileged gid maps require setgroups to be denied first (kernels >= 3.19).
+    match fs::write("/proc/self/setgroups", "deny") {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err).context("failed to deny setgroups in user namespace"),
+    }
+    fs::write("/proc/self/uid_map", format!("{uid} {uid} 1\n"))
+        .context("failed to write uid_map")?;
+    fs::write("/proc/self/gid_map", format!("{gid} {gid} 1\n"))
+        .context("failed to write gid_map")?;
+    Ok(())
+}
+
+/// PID 1 of the sandbox: build the root, then run `exec_entry` in a child and reap until it
+/// exits.
+fn sandbox_init(
+    bundle_root: &Path,
+    payload_root: &Path,
+    layout: RootLayout<'_>,
+    extras: &RootExtras,
+    cwd: CwdPlan,
+    exec_entry: impl FnOnce() -> Result<()>,
+) -> Result<()> {
+    unsafe {
+        libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL);
+    }
+    mount(None, Path::new("/"), None, libc::MS_REC | libc::MS_PRIVATE)
+        .context("failed to make mounts private")?;
+    if let Some(state) = &extras.state {
+        state::mount(payload_root, state)?;
+    }
+    // Bound below the payload, so the recursive binds below carry them into the root.
+    mount_files(payload_root, &extras.files)?;
+    let (mode, passthrough) = match layout {
+        RootLayout::Payload => (RunMode::Sandbox, &[][..]),
+        RootLayout::Overlay { passthrough } => (RunMode::Overlay, passthrough),
+    };
+    let root = StagedRoot::new(payload_root)?;
+    mount_system(&root, bundle_root, extras, mode)?;
+    pass_through(&root, passthrough)?;
+    let cwd = cwd.bind_into(&root, mode);
+    root.seal()?;
+    cwd.enter()?;
+
+    match unsafe { libc::fork() } {
+        -1 => Err(io::Error::last_os_error()).context("fork failed for sandbox entry"),
+        0 => exec_entry(),
+        child => {
+            forward_signals(child);
+            let code = wait_exit_code(child, true)?;
+            unsafe { libc::_exit(code) }
+        }
+    }
+}
+
+/// Mount `/data`, `/proc`, `/dev` (with the policy's devices), `/tmp`, `/run` (with the host
+/// drivers), the DNS files and the portable home into `root`. Chroot mode stays in the caller's
+/// pid namespace and keeps the payload's own `/proc` and DNS files.
+pub(crate) fn mount_system(
+    root: &StagedRoot,
+    bundle_root: &Path,
+    extras: &RootExtras,
+    mode: RunMode,
+) -> Result<()> {
+    let full = mode != RunMode::Chroot;
+    let data_root = host_path(&root.host, &bundle_root.join("data"));
+    if data_root.is_dir() {
+        bind(&data_root, &root.mount_point(Path::new("data"), true)?)?;
+    }
+    if full {
+        mount(
+            Some(Path::new("proc")),
+            &root.mount_point(Path::new("proc"), true)?,
+            Some("proc"),
+            libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOEXEC,
+        )
+        .context("failed to mount /proc")?;
+    }
+
+    let dev = root.mount_point(Path::new("dev"), true)?;
+    tmpfs(&dev, "mode=0755")?;
+    let base = devices::BASE_DEVICES.iter().map(Path::new);
+    devices::bind(
+        root,
+        base.chain(extras.devices.iter().map(PathBuf::as_path)),
+    )?;
+    for (link, target) in [
+        ("fd", "/proc/self/fd"),
+        ("stdin", "/proc/self/fd/0"),
+        ("stdout", "/proc/self/fd/1"),
+        ("stderr", "/proc/self/fd/2"),
+    ] {
+        symlink(target, dev.join(link)).with_context(|| format!("failed to create /dev/{link}"))?;
+    }
+    tmpfs(&root.mount_point(Path::new("dev/shm"), true)?, "mode=1777")?;
+    tmpfs(&root.mount_point(Path::new("tmp"), true)?, "mode=1777")?;
+    tmpfs(&root.mount_point(Path::new("run"), true)?, "mode=0755")?;
+    if let Some(drivers) = &extras.drivers {
+        drivers::mount(root, &drivers.drivers)?;
+    }
+
+    let host_files: &[&str] = if full { HOST_FILES } else { &[] };
+    for file in host_files {
+        let host_file = root.host.join(file);
+        if !host_file.exists() || Path::new("/").join(file).is_symlink() {
+            // A payload symlink would be resolved against the host root; leave it alone.
+            continue;
+        }
+        let Ok(target) = root.mount_point(Path::new(file), false) else {
+            continue;
+        };
+        bind(&host_file, &target)?;
+        remount_readonly(&target)?;
+    }
+    if let Some(home) = &extras.portable {
+        bind(&host_path(&root.host, home), &root.mount_point(home, true)?)?;
+    }
+    Ok(())
+}
+
+/// A tmpfs root holding the payload's top-level entries, already pivoted into. The host root
+/// stays reachable at [`StagedRoot::host`] until [`StagedRoot::seal`].
+pub(crate) struct StagedRoot {
+    /// Where the old root is mounted.
+    pub(crate) host: PathBuf,
+    /// Device the payload lives on, which mount points are never created on.
+    payload_dev: u64,
+}
+
+impl StagedRoot {
+    /// Pivot into a fresh tmpfs and fill it with the payload's top-level entries, reached through
+    /// the old root. The caller must be in a private mount namespace.
+    pub(crate) fn new(payload_root: &Path) -> Result<Self> {
+        let staging = Path::new(ROOT_STAGING);
+        tmpfs(staging, "mode=0755")?;
+        fs::create_dir(staging.join(OLD_ROOT)).context("failed to create old root mount point")?;
+        let staging_c = os_to_cstring(staging.as_os_str())?;
+        let old_root_c = CString::new(OLD_ROOT)?;
+        unsafe {
+            if libc::chdir(staging_c.as_ptr()) != 0 {
+                return Err(io::Error::last_os_error()).context("chdir to root staging failed");
+            }
+            if libc::syscall(libc::SYS_pivot_root, c".".as_ptr(), old_root_c.as_ptr()) != 0 {
+                return Err(io::Error::last_os_error()).context("pivot_root into staging failed");
+            }
+        }
+        let root = Path::new("/");
+        let host = root.join(OLD_ROOT);
+
+        let payload = host_path(&host, payload_root);
+        let payload_dev = fs::metadata(&payload)
+            .with_context(|| format!("failed to stat payload {}", payload_root.display()))?
+            .dev();
+        let entries = fs::read_dir(&payload)
+            .with_context(|| format!("failed to read payload {}", payload_root.display()))?;
+        for entry in entries {
+            let entry = entry?;
+            let source = entry.path();
+            let target = root.join(entry.file_name());
+            let file_type = entry.file_type()?;
+            if file_type.is_symlink() {
+                // Merged-usr links such as `/lib -> usr/lib` resolve inside the new root.
+                symlink(fs::read_link(&source)?, &target)
+                    .with_context(|| format!("failed to link {}", target.display()))?;
+                continue;
+            }
+            if file_type.is_dir() {
+                fs::create_dir(&target)
+            } else {
+                fs::File::create(&target).map(drop)
+            }
+            .with_context(|| format!("failed to create {}", target.display()))?;
+            bind(&source, &target)?;
+        }
+        Ok(Self { host, payload_dev })
+    }
+
+    /// Ensure `path` (absolute or relative to the root) exists as a directory, or a file when
+    /// `dir` is false, so it can be mounted over. Missing components are only created on the
+    /// launcher's own tmpfs mounts; a path that would have to be added to the payload is an error.
+    pub(crate) fn mount_point(&self, path: &Path, dir: bool) -> Result<PathBuf> {
+        let target = host_path(Path::new("/"), path);
+        if target.exists() {
+            return Ok(target);
+        }
+        let existing = target
+            .ancestors()
+            .skip(1)
+            .find(|ancestor| ancestor.exists())
+            .unwrap_or(Path::new("/"));
+        let on_payload = fs::metadata(existing)
+            .map(|meta| meta.dev() == self.payload_dev)
+            .unwrap_or(true);
+        if on_payload {
+            return Err(anyhow!(
+                "cannot create mount point {}: {} belongs to the bundle payload",
+                target.display(),
+                existing.display()
+            ));
+        }
+        if dir {
+            fs::create_dir_all(&target)
+        } else {
+            target
+                .parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|()| fs::File::create(&target).map(drop))
+        }
+        .with_context(|| format!("failed to create mount point {}", target.display()))?;
+        Ok(target)
+    }
+
+    /// Drop the old root and seal the tmpfs read-only.
+    pub(crate) fn seal(self) -> Result<()> {
+        let old_root = os_to_cstring(self.host.as_os_str())?;
+        unsafe {
+            if libc::umount2(old_root.as_ptr(), libc::MNT_DETACH) != 0 {
+                return Err(io::Error::last_os_error()).context("failed to detach old root");
+            }
+        }
+        fs::remove_dir(&self.host).ok();
+        mount(
+            None,
+            Path::new("/"),
+            None,
+            libc::MS_BIND | libc::MS_REMOUNT | libc::MS_RDONLY | libc::MS_NOSUID | libc::MS_NODEV,
+        )
+        .context("failed to seal root read-only")
+    }
+}
+
+/// Bind the host paths in `passthrough` over the root (`run_mode=overlay`).
+fn pass_through(root: &StagedRoot, passthrough: &[PathBuf]) -> Result<()> {
+    for path in passthrough {
+        if !path.is_absolute() {
+            eprintln!(
+                "sidebundle launcher: warning: ignoring relative passthrough path {}",
+                path.display()
+            );
+            continue;
+        }
+        let source = host_path(&root.host, path);
+        let Ok(meta) = fs::metadata(&source) else {
+            continue;
+        };
+        if path.is_symlink() {
+            eprintln!(
+                "sidebundle launcher: warning: {} is a symlink in the payload; not passed through",
+                path.display()
+            );
+            continue;
+        }
+        let target = match root.mount_point(path, meta.is_dir()) {
+            Ok(target) => target,
+            Err(err) => {
+                eprintln!("sidebundle launcher: warning: {err:#}; not passed through");
+                continue;
+            }
+        };
+        bind(&source, &target)?;
+    }
+    Ok(())
+}
+
+/// A file generated at launch to cover its counterpart below the root.
+pub(crate) struct RootFile {
+    /// Path relative to the root, e.g. `etc/passwd`.
+    pub(crate) path: &'static str,
+    pub(crate) contents: Vec<u8>,
+}
+
+/// Mount `files` read-only over their counterparts below `root`, which the packager creates. Files
+/// the payload lacks are skipped rather than added to it. The caller must be in a private mount
+/// namespace.
+pub(crate) fn mount_files(root: &Path, files: &[RootFile]) -> Result<()> {
+    if files.is_empty() {
+        return Ok(());
+    }
+    let staging = env::temp_dir().join(format!("sidebundle-files.{}", std::process::id()));
+    fs::create_dir_all(&staging)
+        .with_context(|| format!("failed to create {}", staging.display()))?;
+    // The mounts keep the staged files alive after the directory is removed.
+    let mounted = files.iter().try_for_each(|file| {
+        let staged = staging.join(file.path.replace('/', "_"));
+        fs::write(&staged, &file.contents)
+            .with_context(|| format!("failed to write {}", staged.display()))?;
+        let target = root.join(file.path);
+        if !target.is_file() {
+            eprintln!(
+                "sidebundle launcher: warning: the payload has no /{} to mount over; rebuild the \
+                 bundle to add it",
+                file.path
+            );
+            return Ok(());
+        }
+        bind(&staged, &target)?;
+        remount_readonly(&target)
+    });
+    let _ = fs::remove_dir_all(&staging);
+    mounted
+}
+
+/// `path` as seen through a host root mounted at `host`.
+pub(crate) fn host_path(host: &Path, path: &Path) -> PathBuf {
+    host.join(path.strip_prefix("/").unwrap_or(path))
+}
+
+pub(crate) fn bind(source: &Path, target: &Path) -> Result<()> {
+    mount(Some(source), target, None, libc::MS_BIND | libc::MS_REC).with_context(|| {
+        format!(
+            "failed to bind {} to {}",
+            source.display(),
+            target.display()
+        )
+    })
+}
+
+pub(crate) fn tmpfs(target: &Path, options: &str) -> Result<()> {
+    let target_c = os_to_cstring(target.as_os_str())?;
+    let options_c = CString::new(options)?;
+    let rc = unsafe {
+        libc::mount(
+            c"tmpfs".as_ptr(),
+            target_c.as_ptr(),
+            c"tmpfs".as_ptr(),
+            libc::MS_NOSUID | libc::MS_NODEV,
+            options_c.as_ptr() as *const libc::c_void,
+        )
+    };
+    if rc != 0 {
+        return Err(io::Error::last_os_error())
+            .with_context(|| format!("failed to mount tmpfs on {}", target.display()));
+    }
+    Ok(())
+}
+
+/// Remount a bind read-only. Flags the kernel locked on the source mount (nosuid, nodev, ...) must
+/// be carried over or the remount is refused inside a user namespace.
+pub(crate) fn remount_readonly(target: &Path) -> Result<()> {
+    let target_c = os_to_cstring(target.as_os_str())?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(target_c.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error())
+            .with_context(|| format!("statvfs failed for {}", target.display()));
+    }
+    let mut flags = libc::MS_BIND | libc::MS_REMOUNT | libc::MS_RDONLY;
+    for (st, ms) in [
+        (libc::ST_NOSUID, libc::MS_NOSUID),
+        (libc::ST_NODEV, libc::MS_NODEV),
+        (libc::ST_NOEXEC, libc::MS_NOEXEC),
+        (libc::ST_NOATIME, libc::MS_NOATIME),
+        (libc::ST_NODIRATIME, libc::MS_NODIRATIME),
+        (libc::ST_RELATIME, libc::MS_RELATIME),
+    ] {
+        if stat.f_flag & st != 0 {
+            flags |= ms;
+        }
+    }
+    mount(None, target, None, flags)
+        .with_context(|| format!("failed to remount {} read-only", target.display()))
+}
+
+fn mount(
+    source: Option<&Path>,
+    target: &Path,
+    fstype: Option<&str>,
+    flags: libc::c_ulong,
+) -> Result<()> {
+    let source = source
+        .map(|path| os_to_cstring(path.as_os_str()))
+        .transpose()?;
+    let fstype = fstype.map(CString::new).transpose()?;
+    let target_c = os_to_cstring(target.as_os_str())?;
+    let rc = unsafe {
+        libc::mount(
+            source.as_ref().map_or(std::ptr::null(), |s| s.as_ptr()),
+            target_c.as_ptr(),
+            fstype.as_ref().map_or(std::ptr::null(), |s| s.as_ptr()),
+            flags,
+            std::ptr::null(),
+        )
+    };
+    if rc != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+extern "C" fn forward_signal(signal: libc::c_int) {
+    let pid = FORWARD_TO.load(Ordering::Relaxed);
+    if pid > 0 {
+        unsafe {
+            libc::kill(pid, signal);
+        }
+    }
+}
+
+fn forward_signals(child: libc::pid_t) {
+    FORWARD_TO.store(child, Ordering::Relaxed);
+    unsafe {
+        for &signal in FORWARDED_SIGNALS {
+            libc::signal(signal, forward_signal as *const () as libc::sighandler_t);
+        }
+        libc::signal(libc::SIGINT, libc::SIG_IGN);
+        libc::signal(libc::SIGQUIT, libc::SIG_IGN);
+    }
+}
+
+/// Wait for `child` and translate its status into an exit code (128 + signal when killed). As
+/// PID 1, `reap` also collects orphans re-parented to the sandbox init.
+fn wait_exit_code(child: libc::pid_t, reap: bool) -> Result<i32> {
+    loop {
+        let mut status = 0;
+        let pid = unsafe { libc::waitpid(if reap { -1 } else { child }, &mut status, 0) };
+        if pid == -1 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(anyhow!(err).context("waitpid failed for sandboxed process"));
+        }
+        if pid != child {
+            continue;
+        }
+        if libc::WIFEXITED(status) {
+            return Ok(libc::WEXITSTATUS(status));
+        }
+        if libc::WIFSIGNALED(status) {
+            return Ok(128 + libc::WTERMSIG(status));
+        }
+    }
+}
@@ -0,0 +1,199 @@
+//! Persistent writable layer for the isolated run modes.
+//!
+//! bwrap, chroot, sandbox and overlay mode mount an overlayfs with `payload/` as the lower layer
+//! and a per-user state directory as the upper one, so writes to `/etc`, `/var/lib` or a program's
+//! own install dir survive across runs while the bundle itself stays untouched. A leading
+//! `--sidebundle-reset-state` argument deletes the state; `SIDEBUNDLE_STATE_DIR` moves it.
+
+use anyhow::{anyhow, bail, Context, Result};
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use sidebundle_core::{RunMode, StateLayer, STATE_DIR_ENV};
+
+use super::os_to_cstring;
+
+const RESET_ARG: &str = "--sidebundle-reset-state";
+
+/// The overlayfs upper and work directories of a state directory.
+pub(crate) struct StateDirs {
+    pub(crate) upper: PathBuf,
+    pub(crate) work: PathBuf,
+}
+
+/// Consume a leading `--sidebundle-reset-state` from the entry's arguments.
+pub(crate) fn take_reset_flag(args: &mut Vec<OsString>) -> bool {
+    let reset = args.first().is_some_and(|arg| arg == RESET_ARG);
+    if reset {
+        args.remove(0);
+    }
+    reset
+}
+
+/// Where this bundle keeps its state; `portable` is the portable home, which then also holds it.
+pub(crate) fn locate(
+    bundle_root: &Path,
+    layer: &StateLayer,
+    portable: Option<&Path>,
+) -> Result<PathBuf> {
+    if let Some(dir) = env::var_os(STATE_DIR_ENV).filter(|dir| !dir.is_empty()) {
+        return Ok(PathBuf::from(dir));
+    }
+    let home = portable
+        .map(Path::to_path_buf)
+        .or_else(|| env::var_os("HOME").map(PathBuf::from));
+    let state_home = match portable {
+        Some(home) => Some(home.join(".local/state")),
+        None => env::var_os("XDG_STATE_HOME").map(PathBuf::from),
+    };
+    // The default directory is keyed on the bundle's path, so resolve symlinks first.
+    let bundle_root = fs::canonicalize(bundle_root).unwrap_or_else(|_| bundle_root.to_path_buf());
+    layer
+        .locate(&bundle_root, home.as_deref(), state_home.as_deref())
+        .ok_or_else(|| anyhow!("cannot locate the state directory; set {STATE_DIR_ENV}"))
+}
+
+/// Create the state layer for `run_mode`; host mode runs without one. An entry with its own
+/// payload root gets its own layer under `origins/`, since the roots hold different trees.
+pub(crate) fn prepare(
+    bundle_root: &Path,
+    layer: Option<&StateLayer>,
+    run_mode: RunMode,
+    portable: Option<&Path>,
+    payload: &Path,
+) -> Result<Option<StateDirs>> {
+    let Some(layer) = layer.filter(|_| run_mode != RunMode::Host) else {
+        return Ok(None);
+    };
+    let mut dir = locate(bundle_root, layer, portable)?;
+    if payload != Path::new("payload") {
+        if let Some(name) = payload.file_name() {
+            dir = dir.join("origins").join(name);
+        }
+    }
+    let dirs = StateDirs {
+        upper: dir.join("upper"),
+        work: dir.join("work"),
+    };
+    for path in [&dirs.upper, &dirs.work] {
+        fs::create_dir_all(path)
+            .with_context(|| format!("failed to create state directory {}", path.display()))?;
+    }
+    Ok(Some(dirs))
+}
+
+/// `rel` as the entry would see it: the state layer's copy if it has one, else the payload's.
+pub(crate) fn current_path(payload_root: &Path, state: Option<&StateDirs>, rel: &str) -> PathBuf {
+    state
+        .map(|state| state.upper.join(rel))
+        .filter(|upper| upper.is_file())
+        .unwrap_or_else(|| payload_root.join(rel))
+}
+
+/// Mount the state layer over `payload_root` in place. The caller must already be in a private
+/// mount namespace.
+pub(crate) fn mount(payload_root: &Path, dirs: &StateDirs) -> Result<()> {
+    let mut options = String::new();
+    for (key, path) in [
+        ("lowerdir", payload_root),
+        ("upperdir", &dirs.upper),
+        ("workdir", &dirs.work),
+    ] {
+        let path = path
+            .to_str()
+            .filter(|path| !path.contains([',', ':', '\\']))
+            .ok_or_else(|| {
+                anyhow!(
+                    "{} cannot be used in overlayfs options; set {STATE_DIR_ENV} to a plain path",
+                    path.display()
+                )
+            })?;
+        if !options.is_empty() {
+            options.push(',');
+        }
+        options.push_str(&format!("{key}={path}"));
+    }
+    let target = os_to_cstring(payload_root.as_os_str())?;
+    let options = os_to_cstring(options.as_ref())?;
+    let rc = unsafe {
+        libc::mount(
+            c"overlay".as_ptr(),
+            target.as_ptr(),
+            c"overlay".as_ptr(),
+            0,
+            options.as_ptr() as *const libc::c_void,
+        )
+    };
+    if rc != 0 {
+        return Err(io::Error::last_os_error()).with_context(|| {
+            format!(
+                "failed to mount the state layer from {} over the payload (unprivileged overlayfs \
+                 needs Linux 5.11+ and an upper directory on a local filesystem; set \
+                 {STATE_DIR_ENV} to move it)",
+                dirs.upper.display()
+            )
+        });
+    }
+    Ok(())
+}
+
+/// Delete the bundle's state directory.
+pub(crate) fn reset(
+    bundle_root: &Path,
+    layer: Option<&StateLayer>,
+    portable: Option<&Path>,
+) -> Result<()> {
+    let Some(layer) = layer else {
+        bail!("this bundle has no state layer to reset");
+    };
+    let dir = locate(bundle_root, layer, portable)?;
+    if !dir.exists() {
+        println!("no state at {}", dir.display());
+        return Ok(());
+    }
+    remove_tree(&dir).with_context(|| format!("failed to remove {}", dir.display()))?;
+    println!("removed {}", dir.display());
+    Ok(())
+}
+
+/// `remove_dir_all` that first opens up directories overlayfs left without permissions (its
+/// `work/work` is mode 000).
+fn remove_tree(path: &Path) -> io::Result<()> {
+    let meta = fs::symlink_metadata(path)?;
+    if !meta.is_dir() {
+        return fs::remove_file(path);
+    }
+    if meta.permissions().mode() & 0o700 != 0o700 {
+        fs::set_permissions(path, fs::Permissions::from_mode(0o700))?;
+    }
+    for entry in fs::read_dir(path)? {
+        remove_tree(&entry?.path())?;
+    }
+    fs::remove_dir(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reset_removes_locked_overlay_work_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = dir.path().join("state");
+        fs::create_dir_all(state.join("upper/etc")).unwrap();
+        fs::write(state.join("upper/etc/tool.conf"), b"x").unwrap();
+        fs::create_dir_all(state.join("work/work/index")).unwrap();
+        fs::set_permissions(state.join("work/work"), fs::Permissions::from_mode(0o000)).unwrap();
+        remove_tree(&state).unwrap();
+        assert!(!state.exists());
+
+        let mut args = vec![OsString::from(RESET_ARG), OsString::from("-v")];
+        assert!(take_reset_flag(&mut args));
+        assert_eq!(args, [OsString::from("-v")]);
+        assert!(!take_reset_flag(&mut args));
+    }
+}
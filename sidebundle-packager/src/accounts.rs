@@ -0,0 +1,132 @@
+//! Minimal `/etc` identity files written in place of the build host's copies.
+//!
+//! Host-origin `passwd`, `group`, `nsswitch.conf` and `hosts` would carry the build machine's user
+//! list and host names into every bundle, so unless [`crate::Packager::with_host_accounts`] asks
+//! for them they are dropped and these stand-ins written instead. Files taken from an image are
+//! part of that image and kept. Launchers add the running user in isolated run modes.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::{write_generated, ManifestFile, PackagerError};
+
+const PASSWD: &str = "\
+root:x:0:0:root:/root:/bin/sh
+nobody:x:65534:65534:nobody:/nonexistent:/usr/sbin/nologin
+";
+
+const GROUP: &str = "\
+root:x:0:
+nogroup:x:65534:
+";
+
+const NSSWITCH: &str = "\
+passwd: files
+group: files
+shadow: files
+hosts: files dns
+networks: files
+protocols: files
+services: files
+ethers: files
+rpc: files
+";
+
+const HOSTS: &str = "\
+127.0.0.1 localhost
+::1 localhost ip6-localhost ip6-loopback
+";
+
+/// Account and host-name files, by path below a payload root.
+const ACCOUNT_FILES: &[(&str, &str)] = &[
+    ("etc/passwd", PASSWD),
+    ("etc/group", GROUP),
+    ("etc/nsswitch.conf", NSSWITCH),
+    ("etc/hosts", HOSTS),
+];
+
+/// Whether `source` is the build host's own copy of an account file bundled at `destination`
+/// below one of `roots`.
+pub(crate) fn is_host_copy(roots: &BTreeSet<PathBuf>, source: &Path, destination: &Path) -> bool {
+    ACCOUNT_FILES.iter().any(|(rel, _)| {
+        source == host_path(rel) && roots.iter().any(|root| destination == root.join(rel))
+    })
+}
+
+/// Whether `runtime` (an absolute path) names an account file.
+pub(crate) fn is_account_path(runtime: &Path) -> bool {
+    ACCOUNT_FILES
+        .iter()
+        .any(|(rel, _)| runtime == host_path(rel))
+}
+
+/// Write the synthetic files the payload `root` does not already have.
+pub(crate) fn write_synthetic(
+    bundle_root: &Path,
+    data_dir: &Path,
+    root: &Path,
+) -> Result<Vec<ManifestFile>, PackagerError> {
+    let mut written = Vec::new();
+    for (rel, contents) in ACCOUNT_FILES {
+        let rel = root.join(rel);
+        if fs::symlink_metadata(bundle_root.join(&rel)).is_ok() {
+            continue;
+        }
+        written.push(write_generated(
+            bundle_root,
+            data_dir,
+            &rel,
+            contents.as_bytes(),
+            "synthetic",
+        )?);
+    }
+    Ok(written)
+}
+
+fn host_path(rel: &str) -> PathBuf {
+    Path::new("/").join(rel)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sidebundle_core::Origin;
+
+    #[test]
+    fn synthetic_files_fill_only_missing_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let bundle = dir.path();
+        fs::create_dir_all(bundle.join("data")).unwrap();
+        fs::create_dir_all(bundle.join("payload/etc")).unwrap();
+        fs::write(bundle.join("payload/etc/group"), "staff:x:50:\n").unwrap();
+
+        let written = write_synthetic(bundle, &bundle.join("data"), Path::new("payload")).unwrap();
+        assert_eq!(written.len(), 3);
+        let passwd = fs::read_to_string(bundle.join("payload/etc/passwd")).unwrap();
+        assert!(passwd.starts_with("root:x:0:0:"));
+        assert_eq!(
+            fs::read_to_string(bundle.join("payload/etc/group")).unwrap(),
+            "staff:x:50:\n"
+        );
+
+        let roots = BTreeSet::from([PathBuf::from("payload"), Origin::Host.payload_root()]);
+        assert!(is_host_copy(
+            &roots,
+            Path::new("/etc/passwd"),
+            Path::new("payload/etc/passwd")
+        ));
+        assert!(is_host_copy(
+            &roots,
+            Path::new("/etc/passwd"),
+            Path::new("payload/.origins/host/etc/passwd")
+        ));
+        assert!(!is_host_copy(
+            &roots,
+            Path::new("/tmp/rootfs/etc/passwd"),
+            Path::new("payload/etc/passwd")
+        ));
+        assert!(is_account_path(Path::new("/etc/nsswitch.conf")));
+        assert!(!is_account_path(Path::new("/etc/resolv.conf")));
+    }
+}
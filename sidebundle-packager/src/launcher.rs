@@ -1,22 +1,97 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::fs;
 use std::path::{Path, PathBuf};
 
+use log::warn;
 use serde::Serialize;
-use sidebundle_core::{EntryBundlePlan, Origin, RunMode, RuntimeMetadata};
+use sidebundle_core::{
+    hwcaps_isa_level, parse_elf_metadata, AuxvProfile, BwrapPolicy, DevicePolicy, EntryBundlePlan,
+    EnvPolicy, HostExec, HostRequirements, LandlockPolicy, Origin, ProcessPolicy, RunMode,
+    RuntimeMetadata, SecretRedactor, StateLayer, DEFAULT_PASSTHROUGH,
+};
 
-use crate::PackagerError;
+use crate::{ldconfig, PackagerError};
 
 const LAUNCHER_BYTES: &[u8] = include_bytes!(env!("SIDEBUNDLE_LAUNCHER_BIN"));
 const CONFIG_DIR: &str = "launchers";
 const BINARY_NAME: &str = ".sidebundle-launcher";
 const CONFIG_EXT: &str = "json";
+/// Option accepted by glibc >= 2.33 loaders to set the program's argv[0].
+const LOADER_ARGV0_OPTION: &[u8] = b"--argv0";
 
+/// Bundle-wide launcher settings written into every entry config.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct LauncherOptions {
+    pub host_exec: HostExec,
+    /// auxv replayed at launch, keyed by the origin the entries came from.
+    pub auxv: HashMap<Origin, AuxvProfile>,
+    /// Env policy per origin; origins without one use [`EnvPolicy::for_origin`].
+    pub env_policy: HashMap<Origin, EnvPolicy>,
+    /// Strips credentials from build-time env snapshots before they are written.
+    pub secrets: SecretRedactor,
+    /// Fail instead of dropping secrets, for CI.
+    pub strict_secrets: bool,
+    /// Host paths overlay-mode entries pass through; `None` means [`DEFAULT_PASSTHROUGH`].
+    pub passthrough: Option<Vec<PathBuf>>,
+    /// Extra passthrough paths keyed by entry name.
+    pub entry_passthrough: HashMap<String, Vec<PathBuf>>,
+    /// Sandbox policy for bwrap-mode entries.
+    pub bwrap: BwrapPolicy,
+    /// Landlock ruleset for host-mode entries; `None` leaves them unconfined.
+    pub landlock: Option<LandlockPolicy>,
+    /// Process constraints applied before exec in every run mode.
+    pub process: ProcessPolicy,
+    /// Run modes launchers try, in order, when the entry's own mode is unavailable.
+    pub run_mode_fallback: Vec<RunMode>,
+    /// Portable HOME/XDG directory relative to the bundle root; `None` keeps the caller's HOME.
+    pub portable: Option<PathBuf>,
+    /// Persistent writable layer isolated-mode entries mount over the payload.
+    pub state: Option<StateLayer>,
+    /// Host devices isolated-mode entries bind when present.
+    pub devices: DevicePolicy,
+}
+
+/// Write the launcher binary and one config per entry. `host_drivers` are the driver sonames the
+/// closure left out, which launchers look up on the host; entries in `cache_conflicts` keep
+/// LD_LIBRARY_PATH even where their root has an `ld.so.cache`. Returns the secret env vars
+/// dropped from build-time snapshots as `entry:KEY`.
 pub fn write_launchers(
     bundle_root: &Path,
     plans: &[EntryBundlePlan],
     metadata: &HashMap<Origin, RuntimeMetadata>,
-) -> Result<(), PackagerError> {
+    host_drivers: &BTreeSet<String>,
+    cache_conflicts: &BTreeSet<String>,
+    options: &LauncherOptions,
+) -> Result<Vec<String>, PackagerError> {
+    // Redact first so strict mode fails before any launcher or config is written.
+    let mut leaked: Vec<String> = Vec::new();
+    let runtimes: Vec<Option<RuntimeMetadata>> = plans
+        .iter()
+        .map(|plan| {
+            let mut runtime = metadata.get(plan.origin()).cloned();
+            if let Some(runtime) = runtime.as_mut() {
+                let dropped = options.secrets.redact(&mut runtime.env);
+                if !dropped.is_empty() {
+                    warn!(
+                        "packager: dropped {} secret env var(s) from `{}` runtime metadata: {}",
+                        dropped.len(),
+                        plan.display_name(),
+                        dropped.join(", ")
+                    );
+                    leaked.extend(
+                        dropped
+                            .into_iter()
+                            .map(|key| format!("{}:{key}", plan.display_name())),
+                    );
+                }
+            }
+            runtime
+        })
+        .collect();
+    if options.strict_secrets && !leaked.is_empty() {
+        return Err(PackagerError::Secrets(leaked.join(", ")));
+    }
+
     let bin_dir = bundle_root.join("bin");
     fs::create_dir_all(&bin_dir).map_err(|source| PackagerError::Io {
         path: bin_dir.clone(),
@@ -35,69 +110,201 @@ pub fn write_launchers(
         source,
     })?;
 
-    for plan in plans {
-        let runtime = metadata.get(plan.origin()).cloned();
-        write_config(&config_dir, plan, runtime)?;
+    let mut argv0_support: HashMap<PathBuf, bool> = HashMap::new();
+    let mut elf_requirements: HashMap<PathBuf, HostRequirements> = HashMap::new();
+    for (plan, runtime) in plans.iter().zip(runtimes) {
+        let auxv = options.auxv.get(plan.origin()).cloned();
+        let loader_argv0 = match plan {
+            EntryBundlePlan::Binary(binary) if binary.requires_linker => *argv0_support
+                .entry(binary.linker_destination.clone())
+                .or_insert_with(|| {
+                    loader_supports_argv0(&bundle_root.join(&binary.linker_destination))
+                }),
+            _ => false,
+        };
+        let env_policy = options
+            .env_policy
+            .get(plan.origin())
+            .cloned()
+            .unwrap_or_else(|| EnvPolicy::for_origin(plan.origin()));
+        // Mode-specific settings are written for every mode the launcher may fall back to.
+        let modes: Vec<RunMode> = std::iter::once(plan.run_mode())
+            .chain(options.run_mode_fallback.iter().copied())
+            .collect();
+        let passthrough = modes.contains(&RunMode::Overlay).then(|| {
+            let mut paths = options
+                .passthrough
+                .clone()
+                .unwrap_or_else(|| DEFAULT_PASSTHROUGH.iter().map(PathBuf::from).collect());
+            if let Some(extra) = options.entry_passthrough.get(plan.display_name()) {
+                paths.extend(extra.iter().cloned());
+            }
+            paths
+        });
+        let bwrap = modes
+            .contains(&RunMode::Bwrap)
+            .then(|| options.bwrap.clone());
+        let landlock = options
+            .landlock
+            .clone()
+            .filter(|_| modes.contains(&RunMode::Host));
+        let entry = EntryOptions {
+            auxv,
+            loader_argv0,
+            env_policy,
+            passthrough,
+            bwrap,
+            landlock,
+            host_requirements: host_requirements(bundle_root, plan, &mut elf_requirements),
+            ld_so_cache: ldconfig::uses_cache(plan)
+                && !cache_conflicts.contains(plan.display_name()),
+        };
+        write_config(&config_dir, plan, runtime, entry, host_drivers, options)?;
         link_entry(&bin_dir, plan.display_name())?;
     }
-    Ok(())
+    Ok(leaked)
+}
+
+#[derive(Serialize)]
+struct LauncherConfig {
+    #[serde(flatten)]
+    entry: EntryConfig,
+    #[serde(flatten)]
+    shared: SharedConfig,
 }
 
+/// What the entry runs.
 #[derive(Serialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
-enum LauncherConfig {
+enum EntryConfig {
     Binary {
-        dynamic: bool,
         binary: PathBuf,
-        linker: Option<PathBuf>,
-        library_paths: Vec<PathBuf>,
-        metadata: Option<RuntimeMetadata>,
-        run_mode: RunMode,
+        loader_argv0: bool,
     },
     Script {
-        dynamic: bool,
         interpreter: PathBuf,
         script: PathBuf,
         args: Vec<String>,
-        linker: Option<PathBuf>,
-        library_paths: Vec<PathBuf>,
-        metadata: Option<RuntimeMetadata>,
-        run_mode: RunMode,
     },
 }
 
+/// How the entry runs, the same for binaries and scripts.
+#[derive(Serialize)]
+struct SharedConfig {
+    dynamic: bool,
+    linker: Option<PathBuf>,
+    library_paths: Vec<PathBuf>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    library_search_path: Vec<PathBuf>,
+    metadata: Option<RuntimeMetadata>,
+    run_mode: RunMode,
+    host_exec: HostExec,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    auxv: Option<AuxvProfile>,
+    env_policy: EnvPolicy,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    passthrough: Option<Vec<PathBuf>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bwrap: Option<BwrapPolicy>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    landlock: Option<LandlockPolicy>,
+    #[serde(skip_serializing_if = "ProcessPolicy::is_empty")]
+    process: ProcessPolicy,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    run_mode_fallback: Vec<RunMode>,
+    #[serde(skip_serializing_if = "HostRequirements::is_empty")]
+    host_requirements: HostRequirements,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    portable: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    state: Option<StateLayer>,
+    #[serde(skip_serializing_if = "DevicePolicy::is_empty")]
+    devices: DevicePolicy,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    host_drivers: Vec<String>,
+    ld_so_cache: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    root: Option<PathBuf>,
+}
+
+/// Launcher settings resolved for a single entry.
+struct EntryOptions {
+    auxv: Option<AuxvProfile>,
+    loader_argv0: bool,
+    env_policy: EnvPolicy,
+    passthrough: Option<Vec<PathBuf>>,
+    bwrap: Option<BwrapPolicy>,
+    landlock: Option<LandlockPolicy>,
+    host_requirements: HostRequirements,
+    /// The loader finds the entry's libraries through its root's `ld.so.cache`.
+    ld_so_cache: bool,
+}
+
 fn write_config(
     dir: &Path,
     plan: &EntryBundlePlan,
     metadata: Option<RuntimeMetadata>,
+    entry: EntryOptions,
+    host_drivers: &BTreeSet<String>,
+    options: &LauncherOptions,
 ) -> Result<(), PackagerError> {
     let config_path = dir.join(format!("{}.{}", plan.display_name(), CONFIG_EXT));
-    let config = match plan {
-        EntryBundlePlan::Binary(plan) => LauncherConfig::Binary {
-            dynamic: plan.requires_linker,
-            binary: plan.binary_destination.clone(),
-            linker: if plan.requires_linker {
-                Some(plan.linker_destination.clone())
-            } else {
-                None
+    let root = Some(plan.payload_root()).filter(|root| root != Path::new("payload"));
+    let (entry_config, metadata) = match plan {
+        EntryBundlePlan::Binary(plan) => (
+            EntryConfig::Binary {
+                binary: plan.binary_destination.clone(),
+                loader_argv0: entry.loader_argv0,
             },
-            library_paths: plan.library_dirs.clone(),
             metadata,
-            run_mode: plan.run_mode.unwrap_or(RunMode::Host),
-        },
-        EntryBundlePlan::Script(plan) => LauncherConfig::Script {
-            dynamic: plan.requires_linker,
-            interpreter: plan.interpreter_destination.clone(),
-            script: plan.script_destination.clone(),
-            args: plan.interpreter_args.clone(),
-            linker: if plan.requires_linker {
-                Some(plan.linker_destination.clone())
-            } else {
-                None
+        ),
+        EntryBundlePlan::Script(script) => (
+            EntryConfig::Script {
+                interpreter: script.interpreter_destination.clone(),
+                script: script.script_destination.clone(),
+                args: script.interpreter_args.clone(),
             },
-            library_paths: plan.library_dirs.clone(),
-            metadata: inject_script_metadata(plan, metadata),
-            run_mode: plan.run_mode.unwrap_or(RunMode::Host),
+            inject_script_metadata(script, metadata),
+        ),
+    };
+    let (requires_linker, linker, library_dirs, library_search_path) = match plan {
+        EntryBundlePlan::Binary(plan) => (
+            plan.requires_linker,
+            &plan.linker_destination,
+            &plan.library_dirs,
+            &plan.library_search_path,
+        ),
+        EntryBundlePlan::Script(plan) => (
+            plan.requires_linker,
+            &plan.linker_destination,
+            &plan.library_dirs,
+            &plan.library_search_path,
+        ),
+    };
+    let config = LauncherConfig {
+        entry: entry_config,
+        shared: SharedConfig {
+            dynamic: requires_linker,
+            linker: requires_linker.then(|| linker.clone()),
+            library_paths: library_dirs.clone(),
+            library_search_path: library_search_path.clone(),
+            metadata,
+            run_mode: plan.run_mode(),
+            host_exec: options.host_exec,
+            auxv: entry.auxv,
+            env_policy: entry.env_policy,
+            passthrough: entry.passthrough,
+            bwrap: entry.bwrap,
+            landlock: entry.landlock,
+            process: options.process.clone(),
+            run_mode_fallback: options.run_mode_fallback.clone(),
+            host_requirements: entry.host_requirements,
+            portable: options.portable.clone(),
+            state: options.state.clone(),
+            devices: options.devices.clone(),
+            host_drivers: host_drivers.iter().cloned().collect(),
+            ld_so_cache: entry.ld_so_cache,
+            root,
         },
     };
     let data = serde_json::to_vec_pretty(&config).map_err(PackagerError::Manifest)?;
@@ -108,6 +315,84 @@ fn write_config(
     Ok(())
 }
 
+/// Kernel and x86-64 level the entry needs: the strictest of its program, the bundled loader and
+/// the shared libraries in its search path. `cache` holds per-file results across entries.
+fn host_requirements(
+    bundle_root: &Path,
+    plan: &EntryBundlePlan,
+    cache: &mut HashMap<PathBuf, HostRequirements>,
+) -> HostRequirements {
+    let (program, linker, library_dirs, requires_linker) = match plan {
+        EntryBundlePlan::Binary(plan) => (
+            &plan.binary_destination,
+            &plan.linker_destination,
+            &plan.library_dirs,
+            plan.requires_linker,
+        ),
+        EntryBundlePlan::Script(plan) => (
+            &plan.interpreter_destination,
+            &plan.linker_destination,
+            &plan.library_dirs,
+            plan.requires_linker,
+        ),
+    };
+    let mut files = vec![program.clone()];
+    if requires_linker {
+        files.push(linker.clone());
+    }
+    for dir in library_dirs {
+        let Ok(entries) = fs::read_dir(bundle_root.join(dir)) else {
+            continue;
+        };
+        let mut libraries: Vec<PathBuf> = entries
+            .flatten()
+            .filter(|entry| entry.file_name().to_string_lossy().contains(".so"))
+            .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_file()))
+            .map(|entry| dir.join(entry.file_name()))
+            .collect();
+        libraries.sort();
+        files.extend(libraries);
+    }
+
+    let mut requirements = HostRequirements::default();
+    for file in files {
+        let found = cache.entry(file.clone()).or_insert_with(|| {
+            let mut found = HostRequirements::default();
+            if let Ok(meta) = parse_elf_metadata(&bundle_root.join(&file)) {
+                if let Some(version) = meta.min_kernel {
+                    found.require_kernel(version, &file);
+                }
+                if let Some(level) = meta.x86_isa_needed {
+                    found.require_x86_isa(level, &file);
+                }
+            }
+            if let Some(level) = hwcaps_isa_level(&file) {
+                found.require_x86_isa(level, &file);
+            }
+            found
+        });
+        if let Some(kernel) = &found.kernel {
+            requirements.require_kernel(kernel.version, &kernel.required_by);
+        }
+        if let Some(isa) = &found.x86_isa {
+            requirements.require_x86_isa(isa.level, &isa.required_by);
+        }
+    }
+    requirements
+}
+
+/// Whether the bundled loader understands `--argv0`. The option name is only present in loaders
+/// that parse it, so scanning the binary works without running it (or matching its arch).
+fn loader_supports_argv0(linker: &Path) -> bool {
+    fs::read(linker)
+        .map(|bytes| {
+            bytes
+                .windows(LOADER_ARGV0_OPTION.len())
+                .any(|window| window == LOADER_ARGV0_OPTION)
+        })
+        .unwrap_or(false)
+}
+
 fn link_entry(bin_dir: &Path, name: &str) -> Result<(), PackagerError> {
     let entry_path = bin_dir.join(name);
     if entry_path.exists() {
@@ -207,6 +492,7 @@ mod tests {
             linker_destination: PathBuf::new(),
             interpreter_args: Vec::new(),
             library_dirs: Vec::new(),
+            library_search_path: Vec::new(),
             requires_linker: false,
             origin: sidebundle_core::Origin::Host,
             run_mode: None,
@@ -235,6 +521,73 @@ mod tests {
         );
     }
 
+    #[test]
+    fn detects_loader_argv0_support() {
+        let dir = tempfile::tempdir().unwrap();
+        let modern = dir.path().join("ld-modern.so");
+        let legacy = dir.path().join("ld-legacy.so");
+        fs::write(&modern, b"\0--list\0--argv0 STRING\0").unwrap();
+        fs::write(&legacy, b"\0--list\0--library-path\0").unwrap();
+        assert!(loader_supports_argv0(&modern));
+        assert!(!loader_supports_argv0(&legacy));
+        assert!(!loader_supports_argv0(&dir.path().join("missing.so")));
+    }
+
+    #[test]
+    fn secrets_are_dropped_from_launcher_configs() {
+        let dir = tempfile::tempdir().unwrap();
+        let plans = vec![EntryBundlePlan::Binary(sidebundle_core::BinaryEntryPlan {
+            display_name: "demo".into(),
+            binary_source: PathBuf::from("/bin/demo"),
+            binary_destination: PathBuf::from("payload/bin/demo"),
+            linker_source: PathBuf::new(),
+            linker_destination: PathBuf::new(),
+            library_dirs: Vec::new(),
+            library_search_path: Vec::new(),
+            requires_linker: false,
+            origin: Origin::Host,
+            run_mode: None,
+        })];
+        let mut runtime = RuntimeMetadata::default();
+        runtime.env.insert("GITHUB_TOKEN".into(), "ghp_leak".into());
+        runtime.env.insert("APP_MODE".into(), "prod".into());
+        let metadata = HashMap::from([(Origin::Host, runtime)]);
+
+        let options = LauncherOptions::default();
+        let dropped = write_launchers(
+            dir.path(),
+            &plans,
+            &metadata,
+            &BTreeSet::new(),
+            &BTreeSet::new(),
+            &options,
+        )
+        .unwrap();
+        assert_eq!(dropped, ["demo:GITHUB_TOKEN"]);
+        let config = fs::read_to_string(dir.path().join("launchers/demo.json")).unwrap();
+        assert!(config.contains("APP_MODE"));
+        assert!(!config.contains("GITHUB_TOKEN"));
+        assert!(!config.contains("ghp_leak"));
+
+        let strict = LauncherOptions {
+            strict_secrets: true,
+            ..LauncherOptions::default()
+        };
+        let fresh = tempfile::tempdir().unwrap();
+        let err = write_launchers(
+            fresh.path(),
+            &plans,
+            &metadata,
+            &BTreeSet::new(),
+            &BTreeSet::new(),
+            &strict,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("demo:GITHUB_TOKEN"));
+        assert!(!fresh.path().join("bin").exists());
+        assert!(!fresh.path().join("launchers").exists());
+    }
+
     #[test]
     fn non_node_scripts_untouched() {
         let plan = script_plan_with_interpreter("/usr/bin/python3");
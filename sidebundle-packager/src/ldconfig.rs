@@ -0,0 +1,347 @@
+//! A bundle-local `ld.so.cache` and `ld.so.conf`.
+//!
+//! In bwrap, chroot, sandbox and overlay mode the bundled glibc loader reads the payload's
+//! `/etc/ld.so.cache`. A copy taken from the host or an image describes another tree, so it is
+//! dropped and replaced by a cache listing exactly the shared libraries in the entries' library
+//! directories, searched in each entry's own order; isolated launchers then run without
+//! LD_LIBRARY_PATH, except for entries the shared cache would hand a different copy of a library.
+//! Bundles without a glibc loader get neither file. With per-origin payload roots each root gets
+//! its own pair.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::{debug, warn};
+use sidebundle_core::{parse_elf_metadata, EntryBundlePlan, LdCache, LdCacheEntry};
+
+use crate::{compute_digest, write_generated, ManifestFile, PackagerError};
+
+const CACHE: &str = "etc/ld.so.cache";
+const CONF: &str = "etc/ld.so.conf";
+
+/// Whether `destination` is one of the files written here for a payload root in `roots`.
+pub(crate) fn is_generated(roots: &BTreeSet<PathBuf>, destination: &Path) -> bool {
+    roots
+        .iter()
+        .any(|root| destination == root.join(CACHE) || destination == root.join(CONF))
+}
+
+/// The payload roots holding an entry that starts through glibc's loader.
+pub(crate) fn cache_roots(plans: &[EntryBundlePlan]) -> BTreeSet<PathBuf> {
+    plans
+        .iter()
+        .filter(|plan| uses_cache(plan))
+        .map(EntryBundlePlan::payload_root)
+        .collect()
+}
+
+/// Whether `plan` starts through glibc's loader, which consults the cache.
+pub(crate) fn uses_cache(plan: &EntryBundlePlan) -> bool {
+    let (requires_linker, linker) = match plan {
+        EntryBundlePlan::Binary(plan) => (plan.requires_linker, &plan.linker_destination),
+        EntryBundlePlan::Script(plan) => (plan.requires_linker, &plan.linker_destination),
+    };
+    let name = linker.file_name().and_then(|name| name.to_str());
+    requires_linker
+        && name.is_some_and(|name| name.starts_with("ld-linux") || name.starts_with("ld64.so"))
+}
+
+/// What [`write`] produced.
+#[derive(Default)]
+pub(crate) struct Written {
+    pub(crate) files: Vec<ManifestFile>,
+    /// Entries for which their root's cache picks a different copy of one of their libraries than
+    /// their own search order does; their launchers keep LD_LIBRARY_PATH.
+    pub(crate) conflicted: BTreeSet<String>,
+}
+
+/// Cacheable libraries by soname and cache flags.
+type Libraries = BTreeMap<(String, i32), PathBuf>;
+
+/// Write the cache and conf of every root in [`cache_roots`] for the libraries in the library
+/// directories of that root's entries, taken entry by entry in each entry's own search order.
+pub(crate) fn write(
+    bundle_root: &Path,
+    data_dir: &Path,
+    plans: &[EntryBundlePlan],
+) -> Result<Written, PackagerError> {
+    let mut written = Written::default();
+    let mut listings = HashMap::new();
+    for root in cache_roots(plans) {
+        let root_plans: Vec<&EntryBundlePlan> = plans
+            .iter()
+            .filter(|plan| plan.payload_root() == root)
+            .collect();
+        let mut lib_dirs: Vec<&Path> = Vec::new();
+        for dir in root_plans.iter().flat_map(|plan| search_order(plan)) {
+            if !lib_dirs.contains(&dir) {
+                lib_dirs.push(dir);
+            }
+        }
+        let cached = resolve(bundle_root, &lib_dirs, &mut listings);
+        for plan in root_plans.iter().filter(|plan| uses_cache(plan)) {
+            let own = resolve(bundle_root, &search_order(plan), &mut listings);
+            let conflict = own.iter().find_map(|(key, path)| {
+                let chosen = cached.get(key)?;
+                (!same_contents(bundle_root, chosen, path)).then_some((&key.0, chosen, path))
+            });
+            if let Some((soname, chosen, path)) = conflict {
+                warn!(
+                    "packager: {} ld.so.cache resolves {soname} to {} but `{}` loads {}; \
+                     keeping LD_LIBRARY_PATH for it",
+                    root.display(),
+                    chosen.display(),
+                    plan.display_name(),
+                    path.display()
+                );
+                written.conflicted.insert(plan.display_name().to_string());
+            }
+        }
+        let conf_dirs: Vec<&Path> = lib_dirs
+            .iter()
+            .copied()
+            .filter(|dir| listings.get(dir).is_some_and(|names| !names.is_empty()))
+            .collect();
+        written.files.extend(write_root(
+            bundle_root,
+            data_dir,
+            &root,
+            &conf_dirs,
+            &cached,
+        )?);
+    }
+    Ok(written)
+}
+
+/// The library directories of `plan` inside its root: its search path, in the order the loader
+/// resolved it, then any other directory holding one of its libraries.
+fn search_order(plan: &EntryBundlePlan) -> Vec<&Path> {
+    let (search_path, library_dirs) = match plan {
+        EntryBundlePlan::Binary(plan) => (&plan.library_search_path, &plan.library_dirs),
+        EntryBundlePlan::Script(plan) => (&plan.library_search_path, &plan.library_dirs),
+    };
+    let root = plan.payload_root();
+    let mut dirs: Vec<&Path> = Vec::new();
+    for dir in search_path.iter().chain(library_dirs) {
+        if dir.starts_with(&root) && !dirs.contains(&dir.as_path()) {
+            dirs.push(dir);
+        }
+    }
+    dirs
+}
+
+/// The cacheable libraries in `dirs`, the first directory providing a name winning as in
+/// ldconfig. `listings` remembers what each directory holds.
+fn resolve<'a>(
+    bundle_root: &Path,
+    dirs: &[&'a Path],
+    listings: &mut HashMap<&'a Path, Vec<(String, i32)>>,
+) -> Libraries {
+    let mut libraries = Libraries::new();
+    for dir in dirs {
+        let names = listings
+            .entry(*dir)
+            .or_insert_with(|| list_libraries(&bundle_root.join(dir)));
+        for (name, flags) in names.iter() {
+            libraries
+                .entry((name.clone(), *flags))
+                .or_insert_with(|| dir.join(name));
+        }
+    }
+    libraries
+}
+
+/// The shared libraries directly in `dir` with their cache flags, by name.
+fn list_libraries(dir: &Path) -> Vec<(String, i32)> {
+    let Ok(listing) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = listing
+        .flatten()
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .filter(|name| name.contains(".so"))
+        .collect();
+    names.sort();
+    names
+        .into_iter()
+        .filter_map(|name| {
+            let path = dir.join(&name);
+            if !path.is_file() {
+                return None;
+            }
+            let flags = parse_elf_metadata(&path).ok()?.cache_flags?;
+            Some((name, flags))
+        })
+        .collect()
+}
+
+/// Whether the bundle files `a` and `b` are the same library.
+fn same_contents(bundle_root: &Path, a: &Path, b: &Path) -> bool {
+    a == b
+        || matches!(
+            (compute_digest(&bundle_root.join(a)), compute_digest(&bundle_root.join(b))),
+            (Ok(a), Ok(b)) if a == b
+        )
+}
+
+fn write_root(
+    bundle_root: &Path,
+    data_dir: &Path,
+    root: &Path,
+    conf_dirs: &[&Path],
+    libraries: &Libraries,
+) -> Result<Vec<ManifestFile>, PackagerError> {
+    let runtime_path = |path: &Path| Path::new("/").join(path.strip_prefix(root).unwrap_or(path));
+    let entries: Vec<LdCacheEntry> = libraries
+        .iter()
+        .map(|((soname, flags), path)| LdCacheEntry {
+            flags: *flags,
+            path: runtime_path(path),
+            soname: soname.clone(),
+            hwcap: 0,
+        })
+        .collect();
+    let mut conf = String::new();
+    for dir in conf_dirs {
+        conf.push_str(&runtime_path(dir).to_string_lossy());
+        conf.push('\n');
+    }
+    debug!(
+        "packager: {} ld.so.cache lists {} libraries",
+        root.display(),
+        entries.len()
+    );
+    let cache = LdCache::new(entries).to_bytes();
+    Ok(vec![
+        write_generated(
+            bundle_root,
+            data_dir,
+            &root.join(CONF),
+            conf.as_bytes(),
+            "ldconfig",
+        )?,
+        write_generated(bundle_root, data_dir, &root.join(CACHE), &cache, "ldconfig")?,
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sidebundle_core::{BinaryEntryPlan, Origin, NATIVE_CACHE_FLAGS};
+
+    fn plan(name: &str, library_dirs: &[&str], library_search_path: &[&str]) -> EntryBundlePlan {
+        EntryBundlePlan::Binary(BinaryEntryPlan {
+            display_name: name.into(),
+            binary_source: PathBuf::from("/usr/bin/app"),
+            binary_destination: PathBuf::from("payload/usr/bin/app"),
+            linker_source: PathBuf::from("/lib64/ld-linux-x86-64.so.2"),
+            linker_destination: PathBuf::from("payload/lib64/ld-linux-x86-64.so.2"),
+            library_dirs: library_dirs.iter().map(PathBuf::from).collect(),
+            library_search_path: library_search_path.iter().map(PathBuf::from).collect(),
+            requires_linker: true,
+            origin: Origin::Host,
+            run_mode: None,
+        })
+    }
+
+    fn cache_listing(payload: &Path) -> Vec<(String, PathBuf)> {
+        let cache = LdCache::parse(&fs::read(payload.join(CACHE)).unwrap()).unwrap();
+        let mut listed: Vec<(String, PathBuf)> = cache
+            .entries()
+            .iter()
+            .map(|entry| (entry.soname.clone(), entry.path.clone()))
+            .collect();
+        listed.sort();
+        listed
+    }
+
+    #[test]
+    fn cache_lists_libraries_in_entry_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let bundle = dir.path();
+        let exe = std::env::current_exe().unwrap();
+        for rel in [
+            "payload/usr/lib",
+            "payload/opt/app/lib",
+            "payload/usr/bin",
+            "data",
+        ] {
+            fs::create_dir_all(bundle.join(rel)).unwrap();
+        }
+        // Any ELF will do; the cache does not look at the soname.
+        fs::copy(&exe, bundle.join("payload/usr/lib/libfoo.so.1")).unwrap();
+        fs::copy(&exe, bundle.join("payload/opt/app/lib/libfoo.so.1")).unwrap();
+        fs::copy(&exe, bundle.join("payload/opt/app/lib/libbar.so")).unwrap();
+        fs::write(bundle.join("payload/opt/app/lib/libnote.so.txt"), b"text").unwrap();
+        fs::copy(&exe, bundle.join("payload/usr/bin/app")).unwrap();
+
+        let plan = plan(
+            "app",
+            &["payload/usr/bin", "payload/usr/lib", "payload/opt/app/lib"],
+            &[],
+        );
+        assert!(uses_cache(&plan));
+        let plans = [plan];
+        let roots = cache_roots(&plans);
+        assert_eq!(roots, BTreeSet::from([PathBuf::from("payload")]));
+        let written = write(bundle, &bundle.join("data"), &plans).unwrap();
+        assert_eq!(written.files.len(), 2);
+        assert!(is_generated(&roots, &written.files[1].destination));
+        // Both copies of libfoo are identical, so the entry can rely on the cache.
+        assert!(written.conflicted.is_empty());
+
+        let payload = bundle.join("payload");
+        let conf = fs::read_to_string(payload.join(CONF)).unwrap();
+        assert_eq!(conf, "/usr/lib\n/opt/app/lib\n");
+        assert_eq!(
+            cache_listing(&payload),
+            [
+                ("libbar.so".into(), PathBuf::from("/opt/app/lib/libbar.so")),
+                ("libfoo.so.1".into(), PathBuf::from("/usr/lib/libfoo.so.1")),
+            ]
+        );
+        if NATIVE_CACHE_FLAGS.is_some() {
+            let cache = LdCache::parse(&fs::read(payload.join(CACHE)).unwrap()).unwrap();
+            assert_eq!(
+                cache.lookup("libfoo.so.1"),
+                Some(Path::new("/usr/lib/libfoo.so.1"))
+            );
+        }
+    }
+
+    #[test]
+    fn entries_whose_libraries_the_cache_shadows_keep_their_own_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let bundle = dir.path();
+        let exe = fs::read(std::env::current_exe().unwrap()).unwrap();
+        for rel in ["payload/usr/lib", "payload/opt/app/lib", "data"] {
+            fs::create_dir_all(bundle.join(rel)).unwrap();
+        }
+        // Two different builds of the same soname.
+        fs::write(bundle.join("payload/usr/lib/libfoo.so.1"), &exe).unwrap();
+        let mut patched = exe.clone();
+        patched.push(0);
+        fs::write(bundle.join("payload/opt/app/lib/libfoo.so.1"), patched).unwrap();
+
+        let both = ["payload/usr/lib", "payload/opt/app/lib"];
+        let plans = [
+            plan("app", &both, &["payload/opt/app/lib", "payload/usr/lib"]),
+            plan("tool", &both, &["payload/usr/lib"]),
+        ];
+        let written = write(bundle, &bundle.join("data"), &plans).unwrap();
+        // The first entry's order decides the cache; the second one loads the other copy.
+        assert_eq!(written.conflicted, BTreeSet::from(["tool".to_string()]));
+
+        let payload = bundle.join("payload");
+        let conf = fs::read_to_string(payload.join(CONF)).unwrap();
+        assert_eq!(conf, "/opt/app/lib\n/usr/lib\n");
+        assert_eq!(
+            cache_listing(&payload),
+            [(
+                "libfoo.so.1".into(),
+                PathBuf::from("/opt/app/lib/libfoo.so.1")
+            )]
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::ffi::{CString, OsStr};
 use std::fmt::Write as FmtWrite;
 use std::fs::{self, File};
@@ -17,12 +17,18 @@ use path_clean::PathClean;
 use pathdiff::diff_paths;
 use serde::Serialize;
 use sha2::{Digest, Sha256};
-use sidebundle_core::{BundleSpec, DependencyClosure, TracedFile};
+use sidebundle_core::{
+    AuxvProfile, BundleSpec, BwrapPolicy, DependencyClosure, DevicePolicy, EntryBundlePlan,
+    EnvPolicy, HostExec, LandlockPolicy, MergeConflict, Origin, ProcessPolicy, RunMode, StateLayer,
+    TracedFile,
+};
 use thiserror::Error;
 
+mod accounts;
 mod launcher;
+mod ldconfig;
 mod shim;
-use launcher::write_launchers;
+use launcher::{write_launchers, LauncherOptions};
 use shim::write_shims;
 
 /// Writes the dependency closure to disk and generates launchers.
@@ -30,7 +36,10 @@ use shim::write_shims;
 pub struct Packager {
     output_root: PathBuf,
     copy_system_assets: bool,
+    copy_host_accounts: bool,
     emit_shim: bool,
+    merge_conflicts: Vec<MergeConflict>,
+    launcher: LauncherOptions,
 }
 
 impl Default for Packager {
@@ -38,7 +47,10 @@ impl Default for Packager {
         Self {
             output_root: PathBuf::from("target/bundles"),
             copy_system_assets: true,
+            copy_host_accounts: false,
             emit_shim: false,
+            merge_conflicts: Vec::new(),
+            launcher: LauncherOptions::default(),
         }
     }
 }
@@ -59,12 +71,127 @@ impl Packager {
         self
     }
 
+    /// Copy the build host's passwd, group, nsswitch.conf and hosts into bundles instead of
+    /// writing minimal synthetic ones (default: off, as they expose the build machine's users).
+    pub fn with_host_accounts(mut self, enabled: bool) -> Self {
+        self.copy_host_accounts = enabled;
+        self
+    }
+
+    /// Record how merge conflicts between the closure's inputs were decided in the manifest.
+    pub fn with_merge_conflicts(
+        mut self,
+        conflicts: impl IntoIterator<Item = MergeConflict>,
+    ) -> Self {
+        self.merge_conflicts.extend(conflicts);
+        self
+    }
+
     /// Emit self-extracting shim executables alongside the bundle.
     pub fn with_shim_output(mut self, enabled: bool) -> Self {
         self.emit_shim = enabled;
         self
     }
 
+    /// Select how Host-mode launchers start dynamic entries.
+    pub fn with_host_exec(mut self, host_exec: HostExec) -> Self {
+        self.launcher.host_exec = host_exec;
+        self
+    }
+
+    /// Replay `profile` as the auxv of every entry from `origin`. Such entries are started through
+    /// the userland loader in Host mode regardless of [`Packager::with_host_exec`].
+    pub fn with_auxv_profile(mut self, origin: Origin, profile: AuxvProfile) -> Self {
+        self.launcher.auxv.insert(origin, profile);
+        self
+    }
+
+    /// Drop env vars matching `patterns` (in addition to the built-in secret patterns) from
+    /// build-time env snapshots.
+    pub fn with_secret_patterns(mut self, patterns: impl IntoIterator<Item = String>) -> Self {
+        self.launcher.secrets = self.launcher.secrets.with_patterns(patterns);
+        self
+    }
+
+    /// Fail packaging when a build-time env snapshot contains secrets instead of dropping them.
+    pub fn with_strict_secrets(mut self, strict: bool) -> Self {
+        self.launcher.strict_secrets = strict;
+        self
+    }
+
+    /// Replace the host paths overlay-mode launchers pass through (default:
+    /// [`sidebundle_core::DEFAULT_PASSTHROUGH`]).
+    pub fn with_passthrough(mut self, paths: impl IntoIterator<Item = PathBuf>) -> Self {
+        self.launcher.passthrough = Some(paths.into_iter().collect());
+        self
+    }
+
+    /// Also pass `paths` through for the entry named `entry` in overlay mode.
+    pub fn with_entry_passthrough(
+        mut self,
+        entry: impl Into<String>,
+        paths: impl IntoIterator<Item = PathBuf>,
+    ) -> Self {
+        self.launcher
+            .entry_passthrough
+            .entry(entry.into())
+            .or_default()
+            .extend(paths);
+        self
+    }
+
+    /// Sandbox policy written into bwrap-mode launchers (default: the strict preset).
+    pub fn with_bwrap_policy(mut self, policy: BwrapPolicy) -> Self {
+        self.launcher.bwrap = policy;
+        self
+    }
+
+    /// Run modes launchers fall back to, in order, when an entry's run mode is unavailable on the
+    /// target host.
+    pub fn with_run_mode_fallback(mut self, modes: impl IntoIterator<Item = RunMode>) -> Self {
+        self.launcher.run_mode_fallback = modes.into_iter().collect();
+        self
+    }
+
+    /// Keep HOME and the XDG base directories in `dir` (relative to the bundle root, e.g.
+    /// `../tool.home` to sit beside it), created by the launcher on first run.
+    pub fn with_portable_home(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.launcher.portable = Some(dir.into());
+        self
+    }
+
+    /// Mount a persistent writable layer over the payload in isolated run modes, so entries can
+    /// write into their own tree without modifying the bundle.
+    pub fn with_state_layer(mut self, layer: StateLayer) -> Self {
+        self.launcher.state = Some(layer);
+        self
+    }
+
+    /// Host devices (profiles such as `dri` or `kvm`, paths and globs) isolated-mode launchers
+    /// bind into the new root when the host has them.
+    pub fn with_devices(mut self, devices: DevicePolicy) -> Self {
+        self.launcher.devices = devices;
+        self
+    }
+
+    /// Landlock ruleset applied by host-mode launchers before they exec the entry.
+    pub fn with_landlock_policy(mut self, policy: LandlockPolicy) -> Self {
+        self.launcher.landlock = Some(policy);
+        self
+    }
+
+    /// rlimits, umask, nice/ionice, no_new_privs and seccomp denylist applied by every launcher.
+    pub fn with_process_policy(mut self, policy: ProcessPolicy) -> Self {
+        self.launcher.process = policy;
+        self
+    }
+
+    /// Set the env policy for entries from `origin`, replacing the origin's default.
+    pub fn with_env_policy(mut self, origin: Origin, policy: EnvPolicy) -> Self {
+        self.launcher.env_policy.insert(origin, policy);
+        self
+    }
+
     pub fn emit(
         &self,
         spec: &BundleSpec,
@@ -95,13 +222,20 @@ impl Packager {
             path: data_dir.clone(),
             source,
         })?;
-        // ensure payload has /data so runtime bind of bundle_root/data -> /data succeeds
-        fs::create_dir_all(bundle_root.join("payload/data")).map_err(|source| {
-            PackagerError::Io {
-                path: bundle_root.join("payload/data"),
-                source,
-            }
-        })?;
+        // Entries of different origins may run in their own payload roots.
+        let mut payload_roots: BTreeSet<PathBuf> = closure
+            .entry_plans
+            .iter()
+            .map(EntryBundlePlan::payload_root)
+            .collect();
+        if payload_roots.is_empty() {
+            payload_roots.insert(PathBuf::from("payload"));
+        }
+        // ensure each payload root has /data so runtime bind of bundle_root/data -> /data succeeds
+        for root in &payload_roots {
+            let dir = bundle_root.join(root).join("data");
+            fs::create_dir_all(&dir).map_err(|source| PackagerError::Io { path: dir, source })?;
+        }
 
         let mut manifest_files = Vec::new();
         let mut alias_map: HashMap<PathBuf, Vec<PathBuf>> = closure.runtime_aliases.clone();
@@ -117,7 +251,11 @@ impl Packager {
                 .push(traced.original.clone());
         }
         let host_assets = if self.copy_system_assets {
-            collect_host_system_assets()
+            let mut assets = collect_host_system_assets();
+            if !self.copy_host_accounts {
+                assets.retain(|path, _| !accounts::is_account_path(path));
+            }
+            assets
         } else {
             HashMap::new()
         };
@@ -138,8 +276,27 @@ impl Packager {
         let mut alias_copy_count: u64 = 0;
         let mut alias_logical_bytes: u64 = 0;
         let mut alias_allocated_bytes: u64 = 0;
+        let cache_roots = ldconfig::cache_roots(&closure.entry_plans);
 
         for file in &closure.files {
+            if ldconfig::is_generated(&cache_roots, &file.destination) {
+                debug!(
+                    "packager: not bundling {}; writing a bundle-local one",
+                    file.source.display()
+                );
+                alias_map.remove(&file.source);
+                continue;
+            }
+            if !self.copy_host_accounts
+                && accounts::is_host_copy(&payload_roots, &file.source, &file.destination)
+            {
+                debug!(
+                    "packager: not bundling host {}; writing a synthetic one",
+                    file.source.display()
+                );
+                alias_map.remove(&file.source);
+                continue;
+            }
             let mut source_path = file.source.clone();
             let mut digest = file.digest.clone();
 
@@ -220,6 +377,7 @@ impl Packager {
             });
             if let Some(runtime_aliases) = alias_map.remove(&file.source) {
                 let canonical_abs = bundle_root.join(&normalized_destination);
+                let root = payload_root_of(&payload_roots, &normalized_destination);
                 for runtime in runtime_aliases {
                     if symlink_destinations.contains(&runtime) {
                         debug!(
@@ -228,7 +386,13 @@ impl Packager {
                         );
                         continue;
                     }
-                    let alias_rel = payload_alias_destination(&runtime);
+                    let mut alias_rel = payload_alias_destination(&runtime);
+                    if runtime.is_absolute() {
+                        // Traced runtime paths belong to the root of the file they resolved to.
+                        if let Ok(inside) = alias_rel.strip_prefix("payload") {
+                            alias_rel = root.join(inside);
+                        }
+                    }
                     let canonical_rel = clean_relative(&canonical_abs, &bundle_root);
                     let alias_rel = normalize_payload_path(&alias_rel);
                     if alias_rel == canonical_rel {
@@ -236,6 +400,14 @@ impl Packager {
                     }
                     let alias_abs = bundle_root.join(&alias_rel);
                     if alias_abs.exists() {
+                        // Merging resolves colliding aliases; anything left here is replaced.
+                        if compute_digest(&alias_abs).is_ok_and(|existing| existing != digest) {
+                            warn!(
+                                "packager: alias {} replaced by {} with different content",
+                                alias_rel.display(),
+                                source_path.display()
+                            );
+                        }
                         fs::remove_file(&alias_abs).map_err(|source| PackagerError::Io {
                             path: alias_abs.clone(),
                             source,
@@ -364,10 +536,30 @@ impl Packager {
         if symlink_count > 0 {
             info!("packager: emitted {symlink_count} symlink(s)");
         }
+        if !self.copy_host_accounts {
+            for root in &payload_roots {
+                manifest_files.extend(accounts::write_synthetic(&bundle_root, &data_dir, root)?);
+            }
+        }
+        let ld_caches = ldconfig::write(&bundle_root, &data_dir, &closure.entry_plans)?;
+        manifest_files.extend(ld_caches.files);
 
-        write_launchers(&bundle_root, &closure.entry_plans, &closure.metadata)?;
+        let dropped_secrets = write_launchers(
+            &bundle_root,
+            &closure.entry_plans,
+            &closure.metadata,
+            &closure.host_drivers,
+            &ld_caches.conflicted,
+            &self.launcher,
+        )?;
         let mut traced_manifest = Vec::new();
         for traced in &traced_queue {
+            if !self.copy_host_accounts
+                && traced.resolved == traced.original
+                && accounts::is_account_path(&traced.original)
+            {
+                continue;
+            }
             let mut source_path = traced.resolved.clone();
             if !source_path.exists() {
                 if let Some(host_path) = host_assets.get(&traced.original) {
@@ -415,7 +607,9 @@ impl Packager {
             });
         }
 
-        ensure_runtime_shims(&bundle_root)?;
+        for root in &payload_roots {
+            ensure_runtime_shims(&bundle_root.join(root))?;
+        }
 
         write_manifest(
             &bundle_root,
@@ -424,6 +618,12 @@ impl Packager {
                 target: spec.target().as_str().to_string(),
                 files: manifest_files,
                 traced_files: traced_manifest,
+                merge_conflicts: self
+                    .merge_conflicts
+                    .iter()
+                    .map(ManifestConflict::from)
+                    .collect(),
+                dropped_secrets,
             },
         )?;
 
@@ -458,6 +658,8 @@ pub enum PackagerError {
     Manifest(serde_json::Error),
     #[error("shim generation failed: {0}")]
     Shim(String),
+    #[error("build-time env snapshot contains secrets: {0}")]
+    Secrets(String),
 }
 
 #[derive(Serialize)]
@@ -466,6 +668,11 @@ struct Manifest {
     target: String,
     files: Vec<ManifestFile>,
     traced_files: Vec<ManifestFile>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    merge_conflicts: Vec<ManifestConflict>,
+    /// Secret env vars dropped from build-time snapshots, as `entry:KEY`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    dropped_secrets: Vec<String>,
 }
 
 #[derive(Serialize)]
@@ -476,11 +683,38 @@ struct ManifestFile {
     digest: String,
 }
 
+/// A bundle path two inputs disagreed on, and which one the merge policy kept.
+#[derive(Serialize)]
+struct ManifestConflict {
+    destination: PathBuf,
+    policy: String,
+    resolution: String,
+    existing_source: PathBuf,
+    existing_digest: String,
+    incoming_source: PathBuf,
+    incoming_digest: String,
+}
+
+impl From<&MergeConflict> for ManifestConflict {
+    fn from(conflict: &MergeConflict) -> Self {
+        Self {
+            destination: conflict.destination.clone(),
+            policy: conflict.policy.to_string(),
+            resolution: conflict.resolution.to_string(),
+            existing_source: conflict.existing_source.clone(),
+            existing_digest: conflict.existing_digest.clone(),
+            incoming_source: conflict.incoming_source.clone(),
+            incoming_digest: conflict.incoming_digest.clone(),
+        }
+    }
+}
+
 #[derive(Serialize)]
 #[serde(rename_all = "snake_case")]
 enum FileOrigin {
     Dependency,
     Trace,
+    Generated,
 }
 
 fn stored_data_path(data_dir: &Path, digest: &str) -> PathBuf {
@@ -507,6 +741,33 @@ fn store_in_data(data_dir: &Path, source: &Path, digest: &str) -> Result<PathBuf
     Ok(stored)
 }
 
+/// Store generated `contents` in the data dir and place them at `rel` in the bundle; `source`
+/// names the generator in the manifest.
+fn write_generated(
+    bundle_root: &Path,
+    data_dir: &Path,
+    rel: &Path,
+    contents: &[u8],
+    source: &str,
+) -> Result<ManifestFile, PackagerError> {
+    let digest = format!("{:x}", Sha256::digest(contents));
+    let stored = stored_data_path(data_dir, &digest);
+    if !stored.exists() {
+        fs::write(&stored, contents).map_err(|source| PackagerError::Io {
+            path: stored.clone(),
+            source,
+        })?;
+    }
+    link_or_copy(&stored, &bundle_root.join(rel), false)?;
+    debug!("packager: wrote generated {}", rel.display());
+    Ok(ManifestFile {
+        origin: FileOrigin::Generated,
+        source: source.to_string(),
+        destination: rel.to_path_buf(),
+        digest,
+    })
+}
+
 fn link_or_copy(stored: &Path, dest: &Path, allow_symlink: bool) -> Result<(), PackagerError> {
     if let Some(parent) = dest.parent() {
         fs::create_dir_all(parent).map_err(|source| PackagerError::Io {
@@ -718,6 +979,15 @@ fn traced_destination(path: &Path) -> PathBuf {
     dest
 }
 
+/// The payload root in `roots` that holds bundle path `destination`.
+fn payload_root_of<'a>(roots: &'a BTreeSet<PathBuf>, destination: &Path) -> &'a Path {
+    roots
+        .iter()
+        .filter(|root| destination.starts_with(root))
+        .max_by_key(|root| root.components().count())
+        .map_or(Path::new("payload"), PathBuf::as_path)
+}
+
 fn payload_alias_destination(path: &Path) -> PathBuf {
     let mut dest = PathBuf::from("payload");
     let mut comps = path.components().peekable();
@@ -768,90 +1038,63 @@ fn collect_host_system_assets() -> HashMap<PathBuf, PathBuf> {
     map
 }
 
-struct DeviceNodeSpec {
-    rel_path: &'static str,
-    major: u64,
-    minor: u64,
-    mode: u32,
-}
-
-fn ensure_device_nodes(bundle_root: &Path) -> Result<(), PackagerError> {
-    const DEVICES: &[DeviceNodeSpec] = &[
-        DeviceNodeSpec {
-            rel_path: "payload/dev/null",
-            major: 1,
-            minor: 3,
-            mode: 0o666,
-        },
-        DeviceNodeSpec {
-            rel_path: "payload/dev/tty",
-            major: 5,
-            minor: 0,
-            mode: 0o666,
-        },
-        DeviceNodeSpec {
-            rel_path: "payload/dev/zero",
-            major: 1,
-            minor: 5,
-            mode: 0o666,
-        },
-        DeviceNodeSpec {
-            rel_path: "payload/dev/urandom",
-            major: 1,
-            minor: 9,
-            mode: 0o666,
-        },
-    ];
-
-    for spec in DEVICES {
-        let dest = bundle_root.join(spec.rel_path);
-        if dest.exists() {
+/// Mount points the isolated-mode launchers cover, created here so a launch never has to add them
+/// to a payload that may be read-only or shared. Files are empty placeholders: device nodes are
+/// bound from the host, so a bundle never carries devices of its own.
+const DIR_MOUNT_POINTS: &[&str] = &["data", "proc", "dev/shm", "tmp", "run"];
+const FILE_MOUNT_POINTS: &[&str] = &[
+    "dev/null",
+    "dev/tty",
+    "dev/zero",
+    "dev/urandom",
+    "etc/resolv.conf",
+    "etc/hosts",
+    "etc/passwd",
+    "etc/group",
+];
+
+fn ensure_mount_points(payload_root: &Path) -> Result<(), PackagerError> {
+    let io_err = |path: &Path| {
+        let path = path.to_path_buf();
+        move |source| PackagerError::Io { path, source }
+    };
+    for rel in DIR_MOUNT_POINTS {
+        let dest = payload_root.join(rel);
+        if fs::symlink_metadata(&dest).is_err() {
+            fs::create_dir_all(&dest).map_err(io_err(&dest))?;
+        }
+    }
+    for rel in FILE_MOUNT_POINTS {
+        let dest = payload_root.join(rel);
+        if fs::symlink_metadata(&dest).is_ok() {
             continue;
         }
         if let Some(parent) = dest.parent() {
-            fs::create_dir_all(parent).map_err(|source| PackagerError::Io {
-                path: parent.to_path_buf(),
-                source,
-            })?;
-        }
-        match create_device_node(&dest, spec.major, spec.minor, spec.mode) {
-            Ok(()) => {
-                debug!("created device node {}", dest.display());
-            }
-            Err(err) => {
-                warn!(
-                    "failed to create device {} via mknod ({}); writing stub file",
-                    dest.display(),
-                    err
-                );
-                File::create(&dest).map_err(|source| PackagerError::Io {
-                    path: dest.clone(),
-                    source,
-                })?;
-            }
+            fs::create_dir_all(parent).map_err(io_err(parent))?;
         }
+        File::create(&dest).map_err(io_err(&dest))?;
     }
     Ok(())
 }
 
-/// Bundle-time shims for runtime expectations (device nodes, interpreter aliases, etc.).
-fn ensure_runtime_shims(bundle_root: &Path) -> Result<(), PackagerError> {
-    ensure_device_nodes(bundle_root)?;
+/// Bundle-time shims for runtime expectations (mount points, interpreter aliases, etc.).
+fn ensure_runtime_shims(payload_root: &Path) -> Result<(), PackagerError> {
+    ensure_mount_points(payload_root)?;
     // Data-driven alias list for common interpreter names.
     const ALIASES: &[(&str, &str)] = &[
         // pip shebang commonly points at /usr/bin/python3; ensure it exists if python3.10 is present.
-        ("payload/usr/bin/python3", "payload/usr/bin/python3.10"),
+        ("usr/bin/python3", "usr/bin/python3.10"),
     ];
-    ensure_aliases(bundle_root, ALIASES)
+    ensure_aliases(payload_root, ALIASES)
 }
 
-fn ensure_aliases(bundle_root: &Path, aliases: &[(&str, &str)]) -> Result<(), PackagerError> {
+fn ensure_aliases(payload_root: &Path, aliases: &[(&str, &str)]) -> Result<(), PackagerError> {
     for (dst_rel, target_rel) in aliases {
-        let dst = bundle_root.join(dst_rel);
+        let dst = payload_root.join(dst_rel);
         if dst.exists() {
             continue;
         }
-        let target = bundle_root.join(target_rel);
+        let target = payload_root.join(target_rel);
         if !target.exists() {
             continue;
         }
@@ -877,23 +1120,6 @@ fn ensure_aliases(bundle_root: &Path, aliases: &[(&str, &str)]) -> Result<(), Pa
     Ok(())
 }
 
-#[cfg(unix)]
-fn create_device_node(path: &Path, major: u64, minor: u64, mode: u32) -> io::Result<()> {
-    use nix::sys::stat::{makedev, mknod, Mode, SFlag};
-
-    let dev = makedev(major, minor);
-    let mode = Mode::from_bits_truncate(mode);
-    mknod(path, SFlag::S_IFCHR, mode, dev).map_err(|err| io::Error::other(err.to_string()))
-}
-
-#[cfg(not(unix))]
-fn create_device_node(_path: &Path, _major: u64, _minor: u64, _mode: u32) -> io::Result<()> {
-    Err(io::Error::new(
-        io::ErrorKind::Other,
-        "device nodes are unsupported on this platform",
-    ))
-}
-
 fn write_manifest(bundle_root: &Path, manifest: Manifest) -> Result<(), PackagerError> {
     let manifest_path = bundle_root.join("manifest.lock");
     let mut file = File::create(&manifest_path).map_err(|source| PackagerError::Io {
@@ -992,6 +1218,7 @@ mod tests {
                 linker_source: source.clone(),
                 linker_destination: PathBuf::from("payload/usr/bin/java"),
                 library_dirs: Vec::new(),
+                library_search_path: Vec::new(),
                 requires_linker: false,
                 origin: Origin::Host,
                 run_mode: None,
@@ -1010,4 +1237,89 @@ mod tests {
         let target = fs::read_link(&link_path).unwrap();
         assert_eq!(target, PathBuf::from("../lib/jvm/java/bin/java"));
     }
+
+    #[test]
+    fn mount_points_are_created_without_replacing_payload_files() {
+        let temp = tempdir().unwrap();
+        let payload = temp.path();
+        fs::create_dir_all(payload.join("etc")).unwrap();
+        fs::write(payload.join("etc/hosts"), b"127.0.0.1 localhost\n").unwrap();
+
+        ensure_mount_points(payload).unwrap();
+        for dir in DIR_MOUNT_POINTS {
+            assert!(payload.join(dir).is_dir(), "{dir}");
+        }
+        for file in FILE_MOUNT_POINTS {
+            assert!(payload.join(file).is_file(), "{file}");
+        }
+        assert_eq!(
+            fs::read(payload.join("etc/hosts")).unwrap(),
+            b"127.0.0.1 localhost\n"
+        );
+        assert_eq!(fs::read(payload.join("etc/resolv.conf")).unwrap(), b"");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn origin_roots_are_packaged_side_by_side() {
+        use std::os::unix::fs::MetadataExt;
+
+        let temp = tempdir().unwrap();
+        let root = temp.path();
+        let origin_closure = |origin: Origin, name: &str, tool: &[u8]| {
+            let source = root.join(name);
+            fs::write(&source, tool).unwrap();
+            let shared = root.join("libshared.so");
+            fs::write(&shared, b"shared").unwrap();
+            let mut closure = DependencyClosure::default();
+            closure.files.push(ResolvedFile::new(
+                &source,
+                "payload/usr/bin/tool",
+                compute_digest(&source).unwrap(),
+            ));
+            closure.files.push(ResolvedFile::new(
+                &shared,
+                "payload/usr/lib/libshared.so",
+                compute_digest(&shared).unwrap(),
+            ));
+            closure
+                .entry_plans
+                .push(EntryBundlePlan::Binary(BinaryEntryPlan {
+                    display_name: name.to_string(),
+                    binary_source: source.clone(),
+                    binary_destination: PathBuf::from("payload/usr/bin/tool"),
+                    linker_source: PathBuf::new(),
+                    linker_destination: PathBuf::new(),
+                    library_dirs: vec![PathBuf::from("payload/usr/lib")],
+                    library_search_path: Vec::new(),
+                    requires_linker: false,
+                    origin: origin.clone(),
+                    run_mode: None,
+                }));
+            closure.rebase_payload(&origin.payload_root());
+            closure
+        };
+        let mut closure = origin_closure(Origin::Host, "old", b"old tool");
+        let report = closure.merge(origin_closure(
+            Origin::Image("app:1".into()),
+            "new",
+            b"new tool",
+        ));
+        assert!(report.conflicts.is_empty());
+
+        let packager = Packager::new().with_output_root(root.join("out"));
+        let spec = BundleSpec::new("tools", TargetTriple::linux_x86_64());
+        let bundle_root = packager.emit(&spec, &closure).unwrap();
+
+        let host = bundle_root.join("payload/.origins/host");
+        let image_root = Origin::Image("app:1".into()).payload_root();
+        let image = bundle_root.join(&image_root);
+        assert_eq!(fs::read(host.join("usr/bin/tool")).unwrap(), b"old tool");
+        assert_eq!(fs::read(image.join("usr/bin/tool")).unwrap(), b"new tool");
+        let shared = |root: &Path| fs::metadata(root.join("usr/lib/libshared.so")).unwrap();
+        assert_eq!(shared(&host).ino(), shared(&image).ino());
+        assert!(image.join("data").is_dir());
+        let config = fs::read_to_string(bundle_root.join("launchers/new.json")).unwrap();
+        assert!(config.contains(&format!("\"root\": \"{}\"", image_root.display())));
+    }
 }